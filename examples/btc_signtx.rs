@@ -49,6 +49,7 @@ async fn signtx<R: bitbox_api::runtime::Runtime>() {
                     .to_vec(),
             },
         ],
+        output_script_configs: vec![],
         version: 1,
         inputs: vec![
             bitbox_api::btc::TxInput {
@@ -62,6 +63,7 @@ async fn signtx<R: bitbox_api::runtime::Runtime>() {
                 keypath: "m/84'/0'/0'/0/0".try_into().unwrap(),
                 script_config_index: 0,
                 prev_tx: Some(prevtx.clone()),
+                silent_payment_pubkey: None,
             },
             bitbox_api::btc::TxInput {
                 prev_out_hash: hex::decode(
@@ -74,6 +76,7 @@ async fn signtx<R: bitbox_api::runtime::Runtime>() {
                 keypath: "m/49'/0'/0'/0/1".try_into().unwrap(),
                 script_config_index: 1,
                 prev_tx: Some(prevtx.clone()),
+                silent_payment_pubkey: None,
             },
         ],
         outputs: vec![
@@ -81,6 +84,7 @@ async fn signtx<R: bitbox_api::runtime::Runtime>() {
                 keypath: "m/84'/0'/0'/1/0".try_into().unwrap(),
                 value: 100000000,
                 script_config_index: 0,
+                output_script_config_index: None,
             }),
             bitbox_api::btc::TxOutput::External(bitbox_api::btc::TxExternalOutput {
                 payload: bitbox_api::btc::Payload {
@@ -88,11 +92,13 @@ async fn signtx<R: bitbox_api::runtime::Runtime>() {
                     output_type: pb::BtcOutputType::P2wsh,
                 },
                 value: 20000000,
+                payment_request_index: None,
             }),
         ],
         locktime: 0,
+        payment_requests: vec![],
     };
-    let sigs = paired_bitbox
+    let result = paired_bitbox
         .btc_sign(
             pb::BtcCoin::Btc,
             &transaction,
@@ -101,7 +107,7 @@ async fn signtx<R: bitbox_api::runtime::Runtime>() {
         .await
         .unwrap();
     println!("Sigs:");
-    for (i, sig) in sigs.iter().enumerate() {
+    for (i, sig) in result.signatures.iter().enumerate() {
         println!("Input {}: {}", i, hex::encode(sig));
     }
 }
@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Device attestation: verifies that the device is a genuine BitBox whose attestation key was
+//! certified by BitBox during production.
+
+use bitcoin::hashes::{sha256, Hash};
+use p256::ecdsa::signature::Verifier;
+
+/// Attestation root pubkey used by BitBox to certify device attestation keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RootPubkey {
+    /// Hex-encoded uncompressed secp256k1 pubkey. The root pubkey identifier reported by the
+    /// device is the SHA256 hash of the pubkey.
+    pub pubkey: &'static str,
+    /// Hex-encoded bootloader hashes accepted for devices certified by this root pubkey. If empty,
+    /// any bootloader hash is accepted.
+    pub accepted_bootloader_hashes: &'static [&'static str],
+}
+
+/// Attestation root pubkeys known to this library.
+///
+/// Devices certified by a root pubkey missing from this list are reported as
+/// `Verification::UnknownRootPubkey`. Use `BitBox::perform_attestation_with_root_pubkeys()`
+/// to verify against a different list.
+pub const ROOT_PUBKEYS: &[RootPubkey] = &[RootPubkey {
+    pubkey: "04074ff1273b36c24e80fe3d59e0e897a81732d3f8e9cd07e17e9fc06319cd16b25cf74255674477b3ac9cbac2d12f0dc27a662681fcbc12955b0bccdcbbdcfd01",
+    accepted_bootloader_hashes: &[],
+}];
+
+/// Outcome of the attestation verification.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verification {
+    /// The device attestation key was certified by a known root key, and the device signed the
+    /// challenge with it.
+    Verified,
+    /// The root pubkey identifier reported by the device does not match any known root pubkey.
+    UnknownRootPubkey,
+    /// The certificate over the bootloader hash and device pubkey is invalid.
+    InvalidCertificate,
+    /// The root pubkey only certifies devices with specific bootloaders, and the bootloader hash
+    /// reported by the device is not one of them.
+    UnacceptedBootloaderHash,
+    /// The challenge signature does not verify against the device attestation pubkey.
+    InvalidChallengeSignature,
+}
+
+/// Attestation data returned by the device, together with the verification outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestationResult {
+    /// Hash of the bootloader installed on the device.
+    pub bootloader_hash: [u8; 32],
+    /// Uncompressed NIST P-256 device attestation pubkey, without the 0x04 prefix.
+    pub device_pubkey: [u8; 64],
+    /// Signature by the root key over the bootloader hash and device pubkey.
+    pub certificate: [u8; 64],
+    /// Identifies the root pubkey which signed the certificate.
+    pub root_pubkey_identifier: [u8; 32],
+    /// Signature by the device attestation key over the challenge.
+    pub challenge_signature: [u8; 64],
+    pub verification: Verification,
+}
+
+impl AttestationResult {
+    /// Returns true if the attestation was verified successfully. If this is false, the device
+    /// might not be a genuine BitBox.
+    pub fn is_verified(&self) -> bool {
+        self.verification == Verification::Verified
+    }
+}
+
+fn root_pubkey_identifier(pubkey: &[u8]) -> [u8; 32] {
+    sha256::Hash::hash(pubkey).to_byte_array()
+}
+
+fn verify_certificate(
+    root_pubkey: &bitcoin::secp256k1::PublicKey,
+    bootloader_hash: &[u8],
+    device_pubkey: &[u8],
+    certificate: &[u8],
+) -> bool {
+    let mut msg = bootloader_hash.to_vec();
+    msg.extend_from_slice(device_pubkey);
+    let mut sig = match bitcoin::secp256k1::ecdsa::Signature::from_compact(certificate) {
+        Ok(sig) => sig,
+        Err(_) => return false,
+    };
+    sig.normalize_s();
+    let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+    secp.verify_ecdsa(
+        &bitcoin::secp256k1::Message::from_digest(sha256::Hash::hash(&msg).to_byte_array()),
+        &sig,
+        root_pubkey,
+    )
+    .is_ok()
+}
+
+fn verify_challenge_signature(device_pubkey: &[u8], challenge: &[u8], signature: &[u8]) -> bool {
+    let mut sec1_pubkey = vec![0x04];
+    sec1_pubkey.extend_from_slice(device_pubkey);
+    let verifying_key = match p256::ecdsa::VerifyingKey::from_sec1_bytes(&sec1_pubkey) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let signature = match p256::ecdsa::Signature::from_slice(signature) {
+        Ok(sig) => sig,
+        Err(_) => return false,
+    };
+    verifying_key.verify(challenge, &signature).is_ok()
+}
+
+/// Parses the attestation response and verifies it against the challenge and the given root
+/// pubkeys. Use `ROOT_PUBKEYS` for the root pubkeys known to this library.
+///
+/// Returns None if the response is malformed.
+pub fn verify(
+    challenge: &[u8],
+    response: &[u8],
+    root_pubkeys: &[RootPubkey],
+) -> Option<AttestationResult> {
+    if response.len() != 32 + 64 + 64 + 32 + 64 {
+        return None;
+    }
+    let (bootloader_hash, rest) = response.split_at(32);
+    let (device_pubkey, rest) = rest.split_at(64);
+    let (certificate, rest) = rest.split_at(64);
+    let (root_pubkey_identifier, challenge_signature) = rest.split_at(32);
+
+    let root_pubkey = root_pubkeys.iter().find_map(|root_pubkey| {
+        let pubkey = hex::decode(root_pubkey.pubkey).ok()?;
+        if root_pubkey_identifier != self::root_pubkey_identifier(&pubkey) {
+            return None;
+        }
+        Some((
+            bitcoin::secp256k1::PublicKey::from_slice(&pubkey).ok()?,
+            root_pubkey.accepted_bootloader_hashes,
+        ))
+    });
+
+    let verification = match root_pubkey {
+        None => Verification::UnknownRootPubkey,
+        Some((root_pubkey, accepted_bootloader_hashes)) => {
+            if !verify_certificate(&root_pubkey, bootloader_hash, device_pubkey, certificate) {
+                Verification::InvalidCertificate
+            } else if !accepted_bootloader_hashes.is_empty()
+                && !accepted_bootloader_hashes
+                    .iter()
+                    .any(|hash| hex::decode(hash).ok().as_deref() == Some(bootloader_hash))
+            {
+                Verification::UnacceptedBootloaderHash
+            } else if !verify_challenge_signature(device_pubkey, challenge, challenge_signature) {
+                Verification::InvalidChallengeSignature
+            } else {
+                Verification::Verified
+            }
+        }
+    };
+
+    Some(AttestationResult {
+        bootloader_hash: bootloader_hash.try_into().unwrap(),
+        device_pubkey: device_pubkey.try_into().unwrap(),
+        certificate: certificate.try_into().unwrap(),
+        root_pubkey_identifier: root_pubkey_identifier.try_into().unwrap(),
+        challenge_signature: challenge_signature.try_into().unwrap(),
+        verification,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::Signer;
+
+    /// Pubkey of the test root privkey `[1u8; 32]`.
+    const TEST_ROOT_PUBKEY: &str = "041b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f70beaf8f588b541507fed6a642c5ab42dfdf8120a7f639de5122d47a69a8e8d1";
+
+    /// Returns the bootloader hash and the attestation response of a device certified by the root
+    /// privkey, signing `challenge`.
+    fn make_response(
+        root_privkey: &bitcoin::secp256k1::SecretKey,
+        root_pubkey_identifier: &[u8],
+        challenge: &[u8],
+    ) -> ([u8; 32], Vec<u8>) {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let device_privkey = p256::ecdsa::SigningKey::from_slice(&[2u8; 32]).unwrap();
+        let device_pubkey = device_privkey
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()[1..]
+            .to_vec();
+        let bootloader_hash = [3u8; 32];
+
+        let mut cert_msg = bootloader_hash.to_vec();
+        cert_msg.extend_from_slice(&device_pubkey);
+        let certificate = secp
+            .sign_ecdsa(
+                &bitcoin::secp256k1::Message::from_digest(
+                    sha256::Hash::hash(&cert_msg).to_byte_array(),
+                ),
+                root_privkey,
+            )
+            .serialize_compact();
+        let challenge_signature: p256::ecdsa::Signature = device_privkey.sign(challenge);
+
+        let mut response = bootloader_hash.to_vec();
+        response.extend_from_slice(&device_pubkey);
+        response.extend_from_slice(&certificate);
+        response.extend_from_slice(root_pubkey_identifier);
+        response.extend_from_slice(&challenge_signature.to_bytes());
+        (bootloader_hash, response)
+    }
+
+    #[test]
+    fn test_verify() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let root_privkey = bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let root_pubkey = root_privkey.public_key(&secp).serialize_uncompressed();
+        assert_eq!(hex::encode(root_pubkey), TEST_ROOT_PUBKEY);
+        let test_root = RootPubkey {
+            pubkey: TEST_ROOT_PUBKEY,
+            accepted_bootloader_hashes: &[],
+        };
+        let challenge = [4u8; 32];
+        let (bootloader_hash, response) = make_response(
+            &root_privkey,
+            &root_pubkey_identifier(&root_pubkey),
+            &challenge,
+        );
+
+        let result = verify(&challenge, &response, &[test_root]).unwrap();
+        assert!(result.is_verified());
+        assert_eq!(result.bootloader_hash, bootloader_hash);
+        assert_eq!(&result.device_pubkey[..], &response[32..96]);
+
+        // Root pubkey not known.
+        assert_eq!(
+            verify(&challenge, &response, ROOT_PUBKEYS)
+                .unwrap()
+                .verification,
+            Verification::UnknownRootPubkey
+        );
+
+        // Invalid certificate.
+        let mut invalid_certificate = response.clone();
+        invalid_certificate[96 + 10] ^= 1;
+        assert_eq!(
+            verify(&challenge, &invalid_certificate, &[test_root])
+                .unwrap()
+                .verification,
+            Verification::InvalidCertificate
+        );
+
+        // Signature over a different challenge.
+        assert_eq!(
+            verify(&[5u8; 32], &response, &[test_root])
+                .unwrap()
+                .verification,
+            Verification::InvalidChallengeSignature
+        );
+
+        // Malformed response.
+        assert!(verify(&challenge, &response[1..], &[test_root]).is_none());
+    }
+
+    #[test]
+    fn test_verify_accepted_bootloader_hashes() {
+        let root_privkey = bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let challenge = [4u8; 32];
+        let (_, response) = make_response(
+            &root_privkey,
+            &root_pubkey_identifier(&hex::decode(TEST_ROOT_PUBKEY).unwrap()),
+            &challenge,
+        );
+
+        let pinned = RootPubkey {
+            pubkey: TEST_ROOT_PUBKEY,
+            accepted_bootloader_hashes: &[
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "0303030303030303030303030303030303030303030303030303030303030303",
+            ],
+        };
+        assert!(verify(&challenge, &response, &[pinned])
+            .unwrap()
+            .is_verified());
+
+        let pinned_other = RootPubkey {
+            pubkey: TEST_ROOT_PUBKEY,
+            accepted_bootloader_hashes: &[
+                "0000000000000000000000000000000000000000000000000000000000000000",
+            ],
+        };
+        assert_eq!(
+            verify(&challenge, &response, &[pinned_other])
+                .unwrap()
+                .verification,
+            Verification::UnacceptedBootloaderHash
+        );
+    }
+
+    #[test]
+    fn test_root_pubkeys() {
+        let root_privkey = bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let challenge = [4u8; 32];
+        for root in ROOT_PUBKEYS {
+            let pubkey = hex::decode(root.pubkey).unwrap();
+            assert_eq!(pubkey.len(), 65);
+            assert!(bitcoin::secp256k1::PublicKey::from_slice(&pubkey).is_ok());
+            for hash in root.accepted_bootloader_hashes {
+                assert_eq!(hex::decode(hash).unwrap().len(), 32);
+            }
+
+            // A response with the identifier of this root is checked against it: the certificate
+            // by a different key is rejected.
+            let (_, response) =
+                make_response(&root_privkey, &root_pubkey_identifier(&pubkey), &challenge);
+            assert_eq!(
+                verify(&challenge, &response, ROOT_PUBKEYS)
+                    .unwrap()
+                    .verification,
+                Verification::InvalidCertificate
+            );
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Functions and methods related to microSD card backups.
+
+use crate::runtime::Runtime;
+
+use crate::error::Error;
+use crate::pb::{self, request::Request, response::Response};
+use crate::util;
+use crate::PairedBitBox;
+
+/// The backup endpoints are only used with firmware >=9.0.0 in this library.
+const MIN_VERSION: &str = ">=9.0.0";
+
+/// A backup stored on the microSD card.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    /// Backup ID, used to restore or identify the backup.
+    pub id: String,
+    /// Time at which the backup was created.
+    pub time: chrono::DateTime<chrono::Utc>,
+    /// Device name at the time the backup was created.
+    pub name: String,
+}
+
+impl TryFrom<pb::BackupInfo> for Backup {
+    type Error = Error;
+    fn try_from(value: pb::BackupInfo) -> Result<Self, Self::Error> {
+        Ok(Backup {
+            time: chrono::DateTime::from_timestamp(value.timestamp as _, 0)
+                .ok_or(Error::UnexpectedResponse)?,
+            id: value.id,
+            name: value.name,
+        })
+    }
+}
+
+impl<R: Runtime> PairedBitBox<R> {
+    async fn query_proto_backup(&self, request: Request) -> Result<Response, Error> {
+        self.validate_version(MIN_VERSION)?;
+        self.query_proto(request).await
+    }
+
+    /// Returns true if a microSD card is inserted.
+    pub async fn check_sdcard(&self) -> Result<bool, Error> {
+        match self
+            .query_proto_backup(Request::CheckSdcard(pb::CheckSdCardRequest {}))
+            .await?
+        {
+            Response::CheckSdcard(pb::CheckSdCardResponse { inserted }) => Ok(inserted),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Prompts the user to insert a microSD card. Returns once the card is inserted or immediately
+    /// if it already was.
+    pub async fn insert_sdcard(&self) -> Result<(), Error> {
+        self.insert_remove_sdcard(pb::insert_remove_sd_card_request::SdCardAction::InsertCard)
+            .await
+    }
+
+    /// Prompts the user to remove the microSD card.
+    pub async fn remove_sdcard(&self) -> Result<(), Error> {
+        self.insert_remove_sdcard(pb::insert_remove_sd_card_request::SdCardAction::RemoveCard)
+            .await
+    }
+
+    async fn insert_remove_sdcard(
+        &self,
+        action: pb::insert_remove_sd_card_request::SdCardAction,
+    ) -> Result<(), Error> {
+        match self
+            .query_proto_backup(Request::InsertRemoveSdcard(pb::InsertRemoveSdCardRequest {
+                action: action as _,
+            }))
+            .await?
+        {
+            Response::Success(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Creates a backup of the seed on the microSD card. The user is asked to confirm on the
+    /// device. The current time and timezone are stored with the backup.
+    pub async fn create_backup(&self) -> Result<(), Error> {
+        let (timestamp, timezone_offset) = util::timestamp_and_timezone_offset();
+        match self
+            .query_proto_backup(Request::CreateBackup(pb::CreateBackupRequest {
+                timestamp,
+                timezone_offset,
+            }))
+            .await?
+        {
+            Response::Success(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Lists all backups found on the microSD card.
+    pub async fn list_backups(&self) -> Result<Vec<Backup>, Error> {
+        match self
+            .query_proto_backup(Request::ListBackups(pb::ListBackupsRequest {}))
+            .await?
+        {
+            Response::ListBackups(pb::ListBackupsResponse { info }) => {
+                info.into_iter().map(Backup::try_from).collect()
+            }
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Restores the backup with the given ID from the microSD card. Use `list_backups()` to get
+    /// the available IDs. The device must be uninitialized.
+    pub async fn restore_backup(&self, id: &str) -> Result<(), Error> {
+        let (timestamp, timezone_offset) = util::timestamp_and_timezone_offset();
+        match self
+            .query_proto_backup(Request::RestoreBackup(pb::RestoreBackupRequest {
+                id: id.into(),
+                timestamp,
+                timezone_offset,
+            }))
+            .await?
+        {
+            Response::Success(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Checks that the backup on the microSD card matches the seed of the device. Returns the ID
+    /// of the matching backup.
+    ///
+    /// If `silent` is true, the result is not shown on the device and no user interaction is
+    /// needed. This can be used to check if a backup exists.
+    pub async fn check_backup(&self, silent: bool) -> Result<String, Error> {
+        match self
+            .query_proto_backup(Request::CheckBackup(pb::CheckBackupRequest { silent }))
+            .await?
+        {
+            Response::CheckBackup(pb::CheckBackupResponse { id }) => Ok(id),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_from_backup_info() {
+        let backup = Backup::try_from(pb::BackupInfo {
+            id: "41233dfbad010723dbbb93514b7b81016b73f8aa35c5148e1b478f60d5750dce".into(),
+            timestamp: 1601281809,
+            name: "My BitBox".into(),
+        })
+        .unwrap();
+        assert_eq!(
+            backup,
+            Backup {
+                id: "41233dfbad010723dbbb93514b7b81016b73f8aa35c5148e1b478f60d5750dce".into(),
+                time: "2020-09-28T08:30:09Z".parse().unwrap(),
+                name: "My BitBox".into(),
+            }
+        );
+    }
+}
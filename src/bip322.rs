@@ -0,0 +1,316 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generic signed message format, see <https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki>.
+//!
+//! A BIP-322 signature is a signature of the virtual `to_sign` transaction, which spends the single
+//! output of the virtual `to_spend` transaction committing to the message. The "simple" signature
+//! encodes only the witness of the `to_sign` input, while the "full" signature encodes the whole
+//! `to_sign` transaction. Only p2wpkh and p2tr (key path) addresses are supported.
+
+use thiserror::Error;
+
+use bitcoin::base64::{prelude::BASE64_STANDARD, Engine};
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::{
+    absolute, opcodes, script, transaction, Amount, OutPoint, Script, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Witness,
+};
+
+const TAG: &[u8] = b"BIP0322-signed-message";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("only p2wpkh and p2tr addresses are supported")]
+    UnsupportedScript,
+    #[error("invalid signature encoding: {0}")]
+    Decode(&'static str),
+    #[error("invalid signature: {0}")]
+    Invalid(&'static str),
+}
+
+/// Encoding of a BIP-322 signature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignatureFormat {
+    /// Only the witness of the `to_sign` transaction.
+    Simple,
+    /// The whole `to_sign` transaction.
+    Full,
+}
+
+/// Returns the tagged hash of the message, which is committed to in the `to_spend` transaction.
+pub fn message_hash(msg: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(msg);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Returns the virtual `to_spend` transaction of a message signed by the owner of
+/// `script_pubkey`.
+pub fn to_spend(script_pubkey: &Script, msg: &[u8]) -> Transaction {
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: script::Builder::new()
+                .push_opcode(opcodes::OP_0)
+                .push_slice(message_hash(msg))
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.into(),
+        }],
+    }
+}
+
+/// Returns the virtual `to_sign` transaction spending `to_spend`, without the witness.
+pub fn to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: to_spend.compute_txid(),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script::Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    }
+}
+
+/// Encodes the signed `to_sign` transaction as a base64 BIP-322 signature.
+pub fn encode_signature(to_sign: &Transaction, format: SignatureFormat) -> String {
+    match format {
+        SignatureFormat::Simple => BASE64_STANDARD.encode(serialize(&to_sign.input[0].witness)),
+        SignatureFormat::Full => BASE64_STANDARD.encode(serialize(to_sign)),
+    }
+}
+
+/// Decodes a base64 BIP-322 signature of a message signed by the owner of `script_pubkey` into the
+/// signed `to_sign` transaction. Both the simple and the full format are accepted.
+pub fn decode_signature(
+    script_pubkey: &Script,
+    msg: &[u8],
+    signature: &str,
+) -> Result<Transaction, Error> {
+    let bytes = BASE64_STANDARD
+        .decode(signature)
+        .map_err(|_| Error::Decode("invalid base64"))?;
+    let expected = to_sign(&to_spend(script_pubkey, msg));
+    if let Ok(witness) = deserialize::<Witness>(&bytes) {
+        let mut to_sign = expected;
+        to_sign.input[0].witness = witness;
+        return Ok(to_sign);
+    }
+    let to_sign: Transaction =
+        deserialize(&bytes).map_err(|_| Error::Decode("neither a witness nor a transaction"))?;
+    if to_sign.input.len() != 1
+        || to_sign.input[0].previous_output != expected.input[0].previous_output
+    {
+        return Err(Error::Invalid("the transaction does not spend to_spend"));
+    }
+    if to_sign.output != expected.output {
+        return Err(Error::Invalid(
+            "the transaction must have a single OP_RETURN output",
+        ));
+    }
+    Ok(to_sign)
+}
+
+/// Verifies a base64 BIP-322 signature (simple or full) of `msg` by the owner of `script_pubkey`.
+pub fn verify(script_pubkey: &Script, msg: &[u8], signature: &str) -> Result<(), Error> {
+    let to_spend = to_spend(script_pubkey, msg);
+    let to_sign = decode_signature(script_pubkey, msg, signature)?;
+    verify_to_sign(&to_spend, &to_sign)
+}
+
+/// Verifies the witness of the signed `to_sign` transaction.
+pub(crate) fn verify_to_sign(to_spend: &Transaction, to_sign: &Transaction) -> Result<(), Error> {
+    let secp = Secp256k1::verification_only();
+    let script_pubkey = &to_spend.output[0].script_pubkey;
+    let witness = &to_sign.input[0].witness;
+    let mut sighash_cache = SighashCache::new(to_sign);
+    if script_pubkey.is_p2wpkh() {
+        let (Some(sig), Some(pubkey), 2) = (witness.nth(0), witness.nth(1), witness.len()) else {
+            return Err(Error::Invalid("p2wpkh witness must have two elements"));
+        };
+        let sig = bitcoin::ecdsa::Signature::from_slice(sig)
+            .map_err(|_| Error::Invalid("invalid ECDSA signature"))?;
+        let pubkey = bitcoin::CompressedPublicKey::from_slice(pubkey)
+            .map_err(|_| Error::Invalid("invalid pubkey"))?;
+        if ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash()) != *script_pubkey {
+            return Err(Error::Invalid("the pubkey does not match the address"));
+        }
+        let sighash = sighash_cache
+            .p2wpkh_signature_hash(0, script_pubkey, Amount::ZERO, sig.sighash_type)
+            .map_err(|_| Error::Invalid("could not compute the sighash"))?;
+        secp.verify_ecdsa(
+            &Message::from_digest(sighash.to_byte_array()),
+            &sig.signature,
+            &pubkey.0,
+        )
+        .map_err(|_| Error::Invalid("signature verification failed"))
+    } else if script_pubkey.is_p2tr() {
+        let (Some(sig), 1) = (witness.nth(0), witness.len()) else {
+            return Err(Error::Invalid(
+                "p2tr key path witness must have one element",
+            ));
+        };
+        let sig = bitcoin::taproot::Signature::from_slice(sig)
+            .map_err(|_| Error::Invalid("invalid Schnorr signature"))?;
+        let output_key =
+            bitcoin::secp256k1::XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])
+                .map_err(|_| Error::Invalid("invalid taproot output key"))?;
+        let sighash = sighash_cache
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&to_spend.output), sig.sighash_type)
+            .map_err(|_| Error::Invalid("could not compute the sighash"))?;
+        secp.verify_schnorr(
+            &sig.signature,
+            &Message::from_digest(sighash.to_byte_array()),
+            &output_key,
+        )
+        .map_err(|_| Error::Invalid("signature verification failed"))
+    } else {
+        Err(Error::UnsupportedScript)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn script_pubkey(address: &str) -> ScriptBuf {
+        bitcoin::Address::from_str(address)
+            .unwrap()
+            .assume_checked()
+            .script_pubkey()
+    }
+
+    // Test vectors from BIP-322.
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(
+            hex::encode(message_hash(b"")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            hex::encode(message_hash(b"Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_transactions() {
+        let script_pubkey = script_pubkey("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l");
+        for (msg, to_spend_txid, to_sign_txid) in [
+            (
+                &b""[..],
+                "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7",
+                "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6",
+            ),
+            (
+                &b"Hello World"[..],
+                "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b",
+                "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf",
+            ),
+        ] {
+            let to_spend = to_spend(&script_pubkey, msg);
+            assert_eq!(to_spend.compute_txid().to_string(), to_spend_txid);
+            assert_eq!(to_sign(&to_spend).compute_txid().to_string(), to_sign_txid);
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let script_pubkey = script_pubkey("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l");
+        let sig_empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let sig_hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert_eq!(verify(&script_pubkey, b"", sig_empty), Ok(()));
+        assert_eq!(verify(&script_pubkey, b"Hello World", sig_hello), Ok(()));
+        assert_eq!(
+            verify(&script_pubkey, b"Hello World", sig_empty),
+            Err(Error::Invalid("signature verification failed"))
+        );
+        assert_eq!(
+            verify(
+                &super::tests::script_pubkey("bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"),
+                b"",
+                sig_empty
+            ),
+            Err(Error::Invalid("the pubkey does not match the address"))
+        );
+
+        // The same signature in the full format.
+        let to_sign = decode_signature(&script_pubkey, b"Hello World", sig_hello).unwrap();
+        let sig_full = encode_signature(&to_sign, SignatureFormat::Full);
+        assert_eq!(verify(&script_pubkey, b"Hello World", &sig_full), Ok(()));
+        assert_eq!(
+            encode_signature(&to_sign, SignatureFormat::Simple),
+            sig_hello
+        );
+
+        assert_eq!(
+            verify(&script_pubkey, b"", "not base64!"),
+            Err(Error::Decode("invalid base64"))
+        );
+        assert_eq!(
+            verify(
+                &super::tests::script_pubkey("3HSVzEhCFuH9Z3wvoWTexy7BMVVp3PjS6f"),
+                b"",
+                sig_empty
+            ),
+            Err(Error::UnsupportedScript)
+        );
+    }
+
+    #[test]
+    fn test_verify_taproot() {
+        // Sign with a key path spend and check that the signature verifies.
+        let secp = Secp256k1::new();
+        let keypair = bitcoin::secp256k1::Keypair::from_seckey_slice(&secp, &[1u8; 32]).unwrap();
+        let (internal_key, _) = keypair.x_only_public_key();
+        let script_pubkey = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let tweaked = bitcoin::key::TapTweak::tap_tweak(keypair, &secp, None);
+
+        let to_spend = to_spend(&script_pubkey, b"Hello World");
+        let mut to_sign = to_sign(&to_spend);
+        let sighash = SighashCache::new(&to_sign)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&to_spend.output),
+                bitcoin::TapSighashType::Default,
+            )
+            .unwrap();
+        let signature = secp.sign_schnorr_no_aux_rand(
+            &Message::from_digest(sighash.to_byte_array()),
+            &tweaked.to_inner(),
+        );
+        to_sign.input[0].witness = Witness::p2tr_key_spend(&bitcoin::taproot::Signature {
+            signature,
+            sighash_type: bitcoin::TapSighashType::Default,
+        });
+        for format in [SignatureFormat::Simple, SignatureFormat::Full] {
+            let encoded = encode_signature(&to_sign, format);
+            assert_eq!(verify(&script_pubkey, b"Hello World", &encoded), Ok(()));
+            assert!(verify(&script_pubkey, b"Hello", &encoded).is_err());
+        }
+    }
+}
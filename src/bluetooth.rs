@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Functions and methods related to Bluetooth, available on BitBox02 Nova devices.
+
+use crate::runtime::Runtime;
+
+use crate::error::Error;
+use crate::pb::{self, request::Request, response::Response};
+use crate::PairedBitBox;
+
+/// Status of the Bluetooth chip, as reported by `PairedBitBox::bluetooth_status()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BluetoothStatus {
+    /// Hash of the currently active Bluetooth firmware.
+    pub firmware_hash: [u8; 32],
+    /// Version of the currently active Bluetooth firmware.
+    pub firmware_version: u32,
+    /// True if Bluetooth is enabled.
+    pub enabled: bool,
+}
+
+impl TryFrom<pb::device_info_response::Bluetooth> for BluetoothStatus {
+    type Error = Error;
+    fn try_from(value: pb::device_info_response::Bluetooth) -> Result<Self, Self::Error> {
+        Ok(BluetoothStatus {
+            firmware_hash: value
+                .firmware_hash
+                .as_slice()
+                .try_into()
+                .map_err(|_| Error::UnexpectedResponse)?,
+            firmware_version: value
+                .firmware_version
+                .parse()
+                .map_err(|_| Error::UnexpectedResponse)?,
+            enabled: value.enabled,
+        })
+    }
+}
+
+impl<R: Runtime> PairedBitBox<R> {
+    async fn query_proto_bluetooth(
+        &self,
+        request: pb::bluetooth_request::Request,
+    ) -> Result<pb::bluetooth_response::Response, Error> {
+        match self
+            .query_proto(Request::Bluetooth(pb::BluetoothRequest {
+                request: Some(request),
+            }))
+            .await?
+        {
+            Response::Bluetooth(pb::BluetoothResponse {
+                response: Some(response),
+            }) => Ok(response),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Returns the status of the Bluetooth chip, or None if the device does not support
+    /// Bluetooth.
+    pub async fn bluetooth_status(&self) -> Result<Option<BluetoothStatus>, Error> {
+        self.device_info()
+            .await?
+            .bluetooth
+            .map(BluetoothStatus::try_from)
+            .transpose()
+    }
+
+    /// Toggles Bluetooth on or off. The user is asked to confirm on the device.
+    pub async fn bluetooth_toggle_enabled(&self) -> Result<(), Error> {
+        match self
+            .query_proto_bluetooth(pb::bluetooth_request::Request::ToggleEnabled(
+                pb::BluetoothToggleEnabledRequest {},
+            ))
+            .await?
+        {
+            pb::bluetooth_response::Response::Success(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Installs a Bluetooth firmware upgrade. The user is asked to confirm on the device. The
+    /// firmware must not be empty.
+    ///
+    /// The device requests the firmware in chunks. `progress` is called with the fraction of the
+    /// firmware transferred so far, from 0.0 to 1.0.
+    pub async fn bluetooth_upgrade(
+        &self,
+        firmware: &[u8],
+        progress: impl FnMut(f64),
+    ) -> Result<(), Error> {
+        upgrade(firmware, progress, async |request| {
+            self.query_proto_bluetooth(request).await
+        })
+        .await
+    }
+}
+
+/// Performs the upgrade protocol of `bluetooth_upgrade()`, sending the requests using `query`:
+/// the firmware is served in the chunks requested by the device.
+async fn upgrade(
+    firmware: &[u8],
+    mut progress: impl FnMut(f64),
+    query: impl AsyncFn(
+        pb::bluetooth_request::Request,
+    ) -> Result<pb::bluetooth_response::Response, Error>,
+) -> Result<(), Error> {
+    if firmware.is_empty() {
+        return Err(Error::BluetoothFirmware("empty"));
+    }
+    let mut response = query(pb::bluetooth_request::Request::UpgradeInit(
+        pb::BluetoothUpgradeInitRequest {
+            firmware_length: firmware.len() as _,
+        },
+    ))
+    .await?;
+    while let pb::bluetooth_response::Response::RequestChunk(chunk_req) = &response {
+        let offset = chunk_req.offset as usize;
+        let end = offset
+            .checked_add(chunk_req.length as usize)
+            .ok_or(Error::UnexpectedResponse)?;
+
+        if end > firmware.len() {
+            return Err(Error::UnexpectedResponse);
+        }
+
+        let data = firmware[offset..end].to_vec();
+        progress(end as f64 / firmware.len() as f64);
+        response = query(pb::bluetooth_request::Request::Chunk(
+            pb::BluetoothChunkRequest { data },
+        ))
+        .await?;
+    }
+    match response {
+        pb::bluetooth_response::Response::Success(_) => Ok(()),
+        _ => Err(Error::UnexpectedResponse),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Mock Bluetooth chip which requests the firmware in chunks of `chunk_size` bytes and stores
+    /// the received firmware.
+    struct MockBluetooth {
+        chunk_size: u32,
+        firmware_length: Mutex<u32>,
+        received: Mutex<Vec<u8>>,
+    }
+
+    impl MockBluetooth {
+        fn new(chunk_size: u32) -> Self {
+            MockBluetooth {
+                chunk_size,
+                firmware_length: Mutex::new(0),
+                received: Mutex::new(vec![]),
+            }
+        }
+
+        fn next_chunk(&self) -> pb::bluetooth_response::Response {
+            let offset = self.received.lock().unwrap().len() as u32;
+            let firmware_length = *self.firmware_length.lock().unwrap();
+            if offset == firmware_length {
+                return pb::bluetooth_response::Response::Success(pb::BluetoothSuccess {});
+            }
+            pb::bluetooth_response::Response::RequestChunk(pb::BluetoothRequestChunkResponse {
+                offset,
+                length: self.chunk_size.min(firmware_length - offset),
+            })
+        }
+
+        fn handle(
+            &self,
+            request: pb::bluetooth_request::Request,
+        ) -> Result<pb::bluetooth_response::Response, Error> {
+            match request {
+                pb::bluetooth_request::Request::UpgradeInit(init) => {
+                    *self.firmware_length.lock().unwrap() = init.firmware_length;
+                    self.received.lock().unwrap().clear();
+                }
+                pb::bluetooth_request::Request::Chunk(chunk) => {
+                    self.received.lock().unwrap().extend_from_slice(&chunk.data);
+                }
+                _ => return Err(Error::UnexpectedResponse),
+            }
+            Ok(self.next_chunk())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upgrade() {
+        let firmware: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let mock = MockBluetooth::new(256);
+        let mut progress_updates = Vec::new();
+        upgrade(
+            &firmware,
+            |progress| progress_updates.push(progress),
+            async |request| mock.handle(request),
+        )
+        .await
+        .unwrap();
+        assert_eq!(*mock.received.lock().unwrap(), firmware);
+        assert_eq!(progress_updates, vec![0.256, 0.512, 0.768, 1.0]);
+
+        // Empty firmware is rejected before communicating with the device.
+        assert!(matches!(
+            upgrade(&[], |_| {}, async |_| panic!("unexpected request")).await,
+            Err(Error::BluetoothFirmware(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_invalid_chunk_request() {
+        let firmware = vec![0u8; 100];
+        for (offset, length) in [(90, 11), (101, 0), (u32::MAX, u32::MAX)] {
+            let result = upgrade(
+                &firmware,
+                |_| {},
+                async |request| match request {
+                    pb::bluetooth_request::Request::UpgradeInit(_) => {
+                        Ok(pb::bluetooth_response::Response::RequestChunk(
+                            pb::BluetoothRequestChunkResponse { offset, length },
+                        ))
+                    }
+                    _ => panic!("unexpected request"),
+                },
+            )
+            .await;
+            assert!(matches!(result, Err(Error::UnexpectedResponse)));
+        }
+    }
+
+    #[test]
+    fn test_bluetooth_status_from_pb() {
+        let status = BluetoothStatus::try_from(pb::device_info_response::Bluetooth {
+            firmware_hash: vec![0x11; 32],
+            firmware_version: "2".into(),
+            enabled: true,
+        })
+        .unwrap();
+        assert_eq!(
+            status,
+            BluetoothStatus {
+                firmware_hash: [0x11; 32],
+                firmware_version: 2,
+                enabled: true,
+            }
+        );
+
+        assert!(
+            BluetoothStatus::try_from(pb::device_info_response::Bluetooth {
+                firmware_hash: vec![0x11; 31],
+                firmware_version: "2".into(),
+                enabled: true,
+            })
+            .is_err()
+        );
+        assert!(
+            BluetoothStatus::try_from(pb::device_info_response::Bluetooth {
+                firmware_hash: vec![0x11; 32],
+                firmware_version: "".into(),
+                enabled: true,
+            })
+            .is_err()
+        );
+    }
+}
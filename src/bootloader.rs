@@ -0,0 +1,484 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Communication with the BitBox02 bootloader, used to install firmware upgrades.
+//!
+//! Use `PairedBitBox::reboot()` with `Purpose::Upgrade` to reboot a device into the bootloader.
+
+use crate::communication::{self, ReadWrite};
+use crate::error::Error as BitBoxError;
+use crate::Product;
+
+use bitcoin::hashes::{sha256, sha256d, Hash};
+use thiserror::Error;
+
+pub const BOOTLOADER_CMD: u8 = 0x80 + 0x40 + 0x03;
+
+/// Size of the chunks in which the firmware is written.
+const CHUNK_SIZE: usize = 4096;
+/// Maximum size of the firmware.
+pub const MAX_FIRMWARE_SIZE: usize = 884736;
+
+const NUM_ROOT_KEYS: usize = 3;
+const NUM_SIGNING_KEYS: usize = 3;
+/// signing keys version, signing pubkeys, signatures of the signing pubkeys by the root keys.
+const SIGNING_PUBKEYS_DATA_LEN: usize = 4 + NUM_SIGNING_KEYS * 64 + NUM_ROOT_KEYS * 64;
+/// firmware version, signatures of the firmware by the signing keys.
+const FIRMWARE_DATA_LEN: usize = 4 + NUM_SIGNING_KEYS * 64;
+const SIGDATA_LEN: usize = SIGNING_PUBKEYS_DATA_LEN + FIRMWARE_DATA_LEN;
+const MAGIC_LEN: usize = 4;
+
+const MAGIC_BITBOX02_MULTI: u32 = 0x653f362b;
+const MAGIC_BITBOX02_BTCONLY: u32 = 0x11233b0b;
+const MAGIC_BITBOX02_NOVA_MULTI: u32 = 0x5b648ceb;
+const MAGIC_BITBOX02_NOVA_BTCONLY: u32 = 0x48714774;
+
+const OP_VERSIONS: u8 = b'v';
+const OP_HASHES: u8 = b'h';
+const OP_SHOW_FIRMWARE_HASH: u8 = b'H';
+const OP_ERASE: u8 = b'e';
+const OP_WRITE_CHUNK: u8 = b'w';
+const OP_SIGNATURE: u8 = b's';
+const OP_REBOOT: u8 = b'r';
+
+const RESPONSE_SUCCESS: u8 = 0x00;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("bootloader returned error status {0}")]
+    Status(u8),
+    #[error("unsupported product")]
+    UnsupportedProduct,
+    #[error("invalid signed firmware: {0}")]
+    InvalidFirmware(&'static str),
+    #[error("the firmware hash reported by the bootloader does not match the firmware")]
+    HashMismatch,
+}
+
+/// Returns the firmware file magic expected by the bootloader of the given product.
+fn magic(product: Product) -> Option<u32> {
+    match product {
+        Product::BitBox02Multi => Some(MAGIC_BITBOX02_MULTI),
+        Product::BitBox02BtcOnly => Some(MAGIC_BITBOX02_BTCONLY),
+        Product::BitBox02NovaMulti => Some(MAGIC_BITBOX02_NOVA_MULTI),
+        Product::BitBox02NovaBtcOnly => Some(MAGIC_BITBOX02_NOVA_BTCONLY),
+        Product::Unknown => None,
+    }
+}
+
+/// A signed firmware binary, as published in the firmware releases: a 4 byte magic identifying
+/// the product, followed by the signature data and the firmware.
+#[derive(Debug, PartialEq)]
+pub struct SignedFirmware<'a> {
+    magic: u32,
+    sigdata: &'a [u8],
+    firmware: &'a [u8],
+}
+
+impl<'a> SignedFirmware<'a> {
+    /// Parses and checks the header of a signed firmware binary. The signatures are verified by
+    /// the bootloader.
+    pub fn parse(signed_firmware: &'a [u8]) -> Result<Self, Error> {
+        if signed_firmware.len() <= MAGIC_LEN + SIGDATA_LEN {
+            return Err(Error::InvalidFirmware("too small"));
+        }
+        let (magic, rest) = signed_firmware.split_at(MAGIC_LEN);
+        let (sigdata, firmware) = rest.split_at(SIGDATA_LEN);
+        if firmware.len() > MAX_FIRMWARE_SIZE {
+            return Err(Error::InvalidFirmware("too large"));
+        }
+        Ok(SignedFirmware {
+            magic: u32::from_be_bytes(magic.try_into().unwrap()),
+            sigdata,
+            firmware,
+        })
+    }
+
+    /// Returns the product this firmware is for, or None if the magic is not recognized.
+    pub fn product(&self) -> Option<Product> {
+        [
+            Product::BitBox02Multi,
+            Product::BitBox02BtcOnly,
+            Product::BitBox02NovaMulti,
+            Product::BitBox02NovaBtcOnly,
+        ]
+        .into_iter()
+        .find(|&product| magic(product) == Some(self.magic))
+    }
+
+    /// Returns the monotonic firmware version contained in the signature data. The bootloader
+    /// refuses to install a firmware with a lower version than the installed one.
+    pub fn firmware_version(&self) -> u32 {
+        u32::from_le_bytes(
+            self.sigdata[SIGNING_PUBKEYS_DATA_LEN..SIGNING_PUBKEYS_DATA_LEN + 4]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Computes the firmware hash the same way the bootloader does:
+    /// `sha256d(<firmware version><firmware padded with 0xFF to the max firmware size>)`.
+    ///
+    /// After flashing, this is the hash the bootloader reports and can display.
+    pub fn firmware_hash(&self) -> [u8; 32] {
+        let mut engine = sha256d::Hash::engine();
+        bitcoin::hashes::HashEngine::input(&mut engine, &self.firmware_version().to_le_bytes());
+        bitcoin::hashes::HashEngine::input(&mut engine, self.firmware);
+        bitcoin::hashes::HashEngine::input(
+            &mut engine,
+            &vec![0xFF; MAX_FIRMWARE_SIZE - self.firmware.len()],
+        );
+        sha256d::Hash::from_engine(engine).to_byte_array()
+    }
+
+    /// Returns the hash of the signing keydata contained in the signature data, as reported by
+    /// `Bootloader::hashes()`.
+    pub fn signing_keydata_hash(&self) -> [u8; 32] {
+        sha256::Hash::hash(&self.sigdata[..SIGNING_PUBKEYS_DATA_LEN]).to_byte_array()
+    }
+}
+
+/// Versions reported by the bootloader.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Versions {
+    /// Monotonic version of the installed firmware.
+    pub firmware_version: u32,
+    /// Version of the firmware signing pubkeys.
+    pub signing_pubkeys_version: u32,
+}
+
+/// Hashes reported by the bootloader.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hashes {
+    /// Hash of the installed firmware, see `SignedFirmware::firmware_hash()`.
+    pub firmware_hash: [u8; 32],
+    /// Hash of the firmware signing keydata.
+    pub signing_keydata_hash: [u8; 32],
+}
+
+/// BitBox02 in bootloader mode. See `usb::get_any_bitbox02_bootloader()`.
+pub struct Bootloader {
+    communication: Box<dyn ReadWrite>,
+    product: Product,
+}
+
+impl Bootloader {
+    /// Creates a bootloader client for the given product. `transport` is the raw connection to
+    /// the device exchanging 64 byte U2F HID packets.
+    pub fn from_transport(transport: Box<dyn ReadWrite>, product: Product) -> Self {
+        Bootloader {
+            communication: Box::new(communication::U2fHidCommunication::from(
+                transport,
+                BOOTLOADER_CMD,
+            )),
+            product,
+        }
+    }
+
+    /// Creates a bootloader client from a BitBox02 bootloader HID device. Use
+    /// `usb::get_any_bitbox02_bootloader()` to find one.
+    #[cfg(feature = "usb")]
+    pub fn from_hid_device(device: hidapi::HidDevice) -> Result<Self, BitBoxError> {
+        let product = device
+            .get_product_string()?
+            .as_deref()
+            .and_then(crate::usb::bootloader_product)
+            .ok_or(Error::UnsupportedProduct)?;
+        Ok(Self::from_transport(
+            Box::new(crate::usb::HidDevice::new(device)),
+            product,
+        ))
+    }
+
+    /// Returns which product's bootloader we are connected to.
+    pub fn product(&self) -> Product {
+        self.product
+    }
+
+    async fn query(&self, op: u8, data: &[u8]) -> Result<Vec<u8>, BitBoxError> {
+        let mut request = vec![op];
+        request.extend_from_slice(data);
+        let mut response = self.communication.query(&request).await?;
+        match response.as_slice() {
+            [response_op, RESPONSE_SUCCESS, ..] if *response_op == op => Ok(response.split_off(2)),
+            [response_op, status, ..] if *response_op == op => Err(Error::Status(*status).into()),
+            _ => Err(BitBoxError::UnexpectedResponse),
+        }
+    }
+
+    /// Queries the installed firmware version and the signing pubkeys version.
+    pub async fn versions(&self) -> Result<Versions, BitBoxError> {
+        let response = self.query(OP_VERSIONS, &[]).await?;
+        match response.get(..8) {
+            Some(response) => Ok(Versions {
+                firmware_version: u32::from_le_bytes(response[..4].try_into().unwrap()),
+                signing_pubkeys_version: u32::from_le_bytes(response[4..].try_into().unwrap()),
+            }),
+            None => Err(BitBoxError::UnexpectedResponse),
+        }
+    }
+
+    /// Queries the firmware hash and the signing keydata hash. The hashes are also shown on the
+    /// device if requested.
+    pub async fn hashes(
+        &self,
+        display_firmware_hash: bool,
+        display_signing_keydata_hash: bool,
+    ) -> Result<Hashes, BitBoxError> {
+        let response = self
+            .query(
+                OP_HASHES,
+                &[
+                    display_firmware_hash as u8,
+                    display_signing_keydata_hash as u8,
+                ],
+            )
+            .await?;
+        match response.get(..64) {
+            Some(response) => Ok(Hashes {
+                firmware_hash: response[..32].try_into().unwrap(),
+                signing_keydata_hash: response[32..].try_into().unwrap(),
+            }),
+            None => Err(BitBoxError::UnexpectedResponse),
+        }
+    }
+
+    /// Returns whether the bootloader shows the firmware hash on every boot.
+    pub async fn show_firmware_hash_enabled(&self) -> Result<bool, BitBoxError> {
+        let response = self.query(OP_SHOW_FIRMWARE_HASH, &[0xFF]).await?;
+        match response.first() {
+            Some(enabled) => Ok(*enabled != 0x00),
+            None => Err(BitBoxError::UnexpectedResponse),
+        }
+    }
+
+    /// Enables or disables showing the firmware hash on every boot.
+    pub async fn set_show_firmware_hash_enabled(&self, enabled: bool) -> Result<(), BitBoxError> {
+        self.query(OP_SHOW_FIRMWARE_HASH, &[enabled as u8]).await?;
+        Ok(())
+    }
+
+    /// Installs a signed firmware binary. The magic in the firmware header must match the product
+    /// of the bootloader.
+    ///
+    /// `progress` is called with the fraction of the firmware written so far, from 0.0 to 1.0.
+    ///
+    /// After writing the firmware, the firmware hash computed by the bootloader is compared to the
+    /// expected hash. The bootloader verifies the signatures and only boots the firmware if they
+    /// are valid. Call `reboot()` afterwards to boot into the new firmware.
+    pub async fn flash_signed_firmware(
+        &self,
+        signed_firmware: &[u8],
+        mut progress: impl FnMut(f64),
+    ) -> Result<(), BitBoxError> {
+        let signed_firmware = SignedFirmware::parse(signed_firmware)?;
+        if magic(self.product) != Some(signed_firmware.magic) {
+            return Err(Error::InvalidFirmware("the firmware is for a different product").into());
+        }
+
+        let chunks: Vec<&[u8]> = signed_firmware.firmware.chunks(CHUNK_SIZE).collect();
+        self.query(OP_ERASE, &[chunks.len() as u8]).await?;
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            progress(chunk_index as f64 / chunks.len() as f64);
+            let mut request = vec![chunk_index as u8];
+            request.extend_from_slice(chunk);
+            // The last chunk is padded.
+            request.resize(1 + CHUNK_SIZE, 0xFF);
+            self.query(OP_WRITE_CHUNK, &request).await?;
+        }
+        progress(1.0);
+        self.query(OP_SIGNATURE, signed_firmware.sigdata).await?;
+
+        let hashes = self.hashes(false, false).await?;
+        if hashes.firmware_hash != signed_firmware.firmware_hash() {
+            return Err(Error::HashMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// Reboots the device. If a valid firmware is installed, the device boots into it. The device
+    /// disconnects, closing the connection.
+    pub async fn reboot(self) -> Result<(), BitBoxError> {
+        match self.query(OP_REBOOT, &[]).await {
+            Ok(_) => Ok(()),
+            Err(BitBoxError::Communication(
+                communication::Error::Read | communication::Error::Write,
+            )) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::u2fframing::{self, U2FFraming};
+    use crate::util::Threading;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Mock bootloader which stores the written firmware and computes the firmware hash like the
+    /// real bootloader does.
+    struct MockBootloader {
+        codec: u2fframing::U2fHid,
+        written: Mutex<Vec<u8>>,
+        responses: Mutex<Vec<Vec<u8>>>,
+        firmware: Mutex<Vec<u8>>,
+        firmware_version: Mutex<u32>,
+    }
+
+    impl MockBootloader {
+        fn new() -> Self {
+            MockBootloader {
+                codec: u2fframing::U2fHid::new(BOOTLOADER_CMD),
+                written: Mutex::new(vec![]),
+                responses: Mutex::new(vec![]),
+                firmware: Mutex::new(vec![]),
+                firmware_version: Mutex::new(0),
+            }
+        }
+
+        fn handle(&self, request: &[u8]) -> Vec<u8> {
+            let (op, data) = (request[0], &request[1..]);
+            let mut response = vec![op, RESPONSE_SUCCESS];
+            match op {
+                OP_VERSIONS => {
+                    response
+                        .extend_from_slice(&self.firmware_version.lock().unwrap().to_le_bytes());
+                    response.extend_from_slice(&7u32.to_le_bytes());
+                }
+                OP_ERASE => {
+                    *self.firmware.lock().unwrap() = vec![0xFF; data[0] as usize * CHUNK_SIZE];
+                }
+                OP_WRITE_CHUNK => {
+                    let offset = data[0] as usize * CHUNK_SIZE;
+                    self.firmware.lock().unwrap()[offset..offset + CHUNK_SIZE]
+                        .copy_from_slice(&data[1..]);
+                }
+                OP_SIGNATURE => {
+                    *self.firmware_version.lock().unwrap() = u32::from_le_bytes(
+                        data[SIGNING_PUBKEYS_DATA_LEN..SIGNING_PUBKEYS_DATA_LEN + 4]
+                            .try_into()
+                            .unwrap(),
+                    );
+                }
+                OP_HASHES => {
+                    let mut firmware = self.firmware.lock().unwrap().clone();
+                    firmware.resize(MAX_FIRMWARE_SIZE, 0xFF);
+                    let mut msg = self.firmware_version.lock().unwrap().to_le_bytes().to_vec();
+                    msg.extend_from_slice(&firmware);
+                    response.extend_from_slice(sha256d::Hash::hash(&msg).as_ref());
+                    response.extend_from_slice(&[0u8; 32]);
+                }
+                OP_SHOW_FIRMWARE_HASH => response.push(0x01),
+                _ => response[1] = 0x01,
+            }
+            response
+        }
+    }
+
+    impl Threading for MockBootloader {}
+
+    #[cfg_attr(feature = "multithreaded", async_trait)]
+    #[cfg_attr(not(feature="multithreaded"), async_trait(?Send))]
+    impl ReadWrite for MockBootloader {
+        fn write(&self, msg: &[u8]) -> Result<usize, communication::Error> {
+            let mut written = self.written.lock().unwrap();
+            written.extend_from_slice(msg);
+            if let Some(request) = self.codec.decode(&written).unwrap() {
+                written.clear();
+                let mut buf = [0u8; u2fframing::MAX_LEN];
+                let size = self.codec.encode(&self.handle(&request), &mut buf).unwrap();
+                *self.responses.lock().unwrap() =
+                    buf[..size].chunks(64).rev().map(|c| c.to_vec()).collect();
+            }
+            Ok(msg.len())
+        }
+
+        async fn read(&self) -> Result<Vec<u8>, communication::Error> {
+            self.responses
+                .lock()
+                .unwrap()
+                .pop()
+                .ok_or(communication::Error::Read)
+        }
+    }
+
+    fn make_signed_firmware(magic: u32, firmware_version: u32, firmware: &[u8]) -> Vec<u8> {
+        let mut signed_firmware = magic.to_be_bytes().to_vec();
+        let mut sigdata = vec![0u8; SIGDATA_LEN];
+        sigdata[SIGNING_PUBKEYS_DATA_LEN..SIGNING_PUBKEYS_DATA_LEN + 4]
+            .copy_from_slice(&firmware_version.to_le_bytes());
+        signed_firmware.extend_from_slice(&sigdata);
+        signed_firmware.extend_from_slice(firmware);
+        signed_firmware
+    }
+
+    #[test]
+    fn test_signed_firmware_parse() {
+        let signed_firmware = make_signed_firmware(MAGIC_BITBOX02_BTCONLY, 42, &[1, 2, 3]);
+        let parsed = SignedFirmware::parse(&signed_firmware).unwrap();
+        assert_eq!(parsed.product(), Some(Product::BitBox02BtcOnly));
+        assert_eq!(parsed.firmware_version(), 42);
+        assert_eq!(parsed.firmware, &[1, 2, 3]);
+
+        let signed_firmware_unknown = make_signed_firmware(0x12345678, 42, &[1]);
+        let parsed_unknown = SignedFirmware::parse(&signed_firmware_unknown).unwrap();
+        assert_eq!(parsed_unknown.product(), None);
+
+        assert!(matches!(
+            SignedFirmware::parse(&signed_firmware[..MAGIC_LEN + SIGDATA_LEN]),
+            Err(Error::InvalidFirmware("too small"))
+        ));
+        assert!(matches!(
+            SignedFirmware::parse(&make_signed_firmware(
+                MAGIC_BITBOX02_MULTI,
+                1,
+                &vec![0; MAX_FIRMWARE_SIZE + 1]
+            )),
+            Err(Error::InvalidFirmware("too large"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_flash_signed_firmware() {
+        let bootloader =
+            Bootloader::from_transport(Box::new(MockBootloader::new()), Product::BitBox02Multi);
+        assert_eq!(
+            bootloader.versions().await.unwrap(),
+            Versions {
+                firmware_version: 0,
+                signing_pubkeys_version: 7,
+            }
+        );
+        assert!(bootloader.show_firmware_hash_enabled().await.unwrap());
+
+        let firmware: Vec<u8> = (0..CHUNK_SIZE * 2 + 100).map(|i| i as u8).collect();
+        let signed_firmware = make_signed_firmware(MAGIC_BITBOX02_MULTI, 36, &firmware);
+
+        let mut progress_updates = Vec::new();
+        bootloader
+            .flash_signed_firmware(&signed_firmware, |progress| progress_updates.push(progress))
+            .await
+            .unwrap();
+        assert_eq!(progress_updates, vec![0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]);
+        assert_eq!(bootloader.versions().await.unwrap().firmware_version, 36);
+        assert_eq!(
+            bootloader.hashes(false, false).await.unwrap().firmware_hash,
+            SignedFirmware::parse(&signed_firmware)
+                .unwrap()
+                .firmware_hash()
+        );
+
+        // Firmware for a different product.
+        let result = bootloader
+            .flash_signed_firmware(
+                &make_signed_firmware(MAGIC_BITBOX02_BTCONLY, 36, &firmware),
+                |_| {},
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(BitBoxError::Bootloader(Error::InvalidFirmware(_)))
+        ));
+    }
+}
@@ -137,6 +137,9 @@ pub struct TxInput {
     pub script_config_index: u32,
     // Can be None if all transaction inputs are Taproot.
     pub prev_tx: Option<PrevTx>,
+    // Required if the transaction contains silent payment outputs: the input's pubkey as defined
+    // in BIP-352. For Taproot inputs, this is the output key with even Y coordinate.
+    pub silent_payment_pubkey: Option<bitcoin::secp256k1::PublicKey>,
 }
 
 impl TxInput {
@@ -152,6 +155,9 @@ pub struct TxInternalOutput {
     pub keypath: Keypath,
     pub value: u64,
     pub script_config_index: u32,
+    /// If set, the output belongs to a different account of the same keystore, described by
+    /// `Transaction::output_script_configs[index]`, and `script_config_index` is ignored.
+    pub output_script_config_index: Option<u32>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -246,6 +252,8 @@ impl Payload {
 pub struct TxExternalOutput {
     pub payload: Payload,
     pub value: u64,
+    /// Index into `Transaction::payment_requests` if this output is paid to a payment request.
+    pub payment_request_index: Option<u32>,
 }
 
 impl TryFrom<&bitcoin::TxOut> for TxExternalOutput {
@@ -254,26 +262,145 @@ impl TryFrom<&bitcoin::TxOut> for TxExternalOutput {
         Ok(TxExternalOutput {
             payload: Payload::from_pkscript(value.script_pubkey.as_bytes())?,
             value: value.value.to_sat(),
+            payment_request_index: None,
         })
     }
 }
 
+/// Output sending to a silent payment address (BIP-352). The output script is generated by the
+/// BitBox and returned in `SignResult::generated_outputs`.
+#[derive(Debug, PartialEq)]
+pub struct TxSilentPaymentOutput {
+    pub address: String,
+    pub value: u64,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum TxOutput {
     Internal(TxInternalOutput),
     External(TxExternalOutput),
+    SilentPayment(TxSilentPaymentOutput),
+}
+
+/// Memo of a payment request, shown to the user on the device.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentRequestMemo {
+    Text(String),
+}
+
+/// A payment request according to SLIP-24, signed by a trusted payment request provider (e.g. an
+/// exchange). The recipient name and memos are shown on the device instead of the output address
+/// once the signature has been verified by the BitBox.
+///
+/// See <https://github.com/satoshilabs/slips/blob/master/slip-0024.md>.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentRequest {
+    pub recipient_name: String,
+    pub memos: Vec<PaymentRequestMemo>,
+    pub nonce: Vec<u8>,
+    /// Sum of the values of all outputs belonging to this payment request.
+    pub total_amount: u64,
+    pub signature: Vec<u8>,
+}
+
+impl From<&PaymentRequest> for pb::BtcPaymentRequestRequest {
+    fn from(value: &PaymentRequest) -> Self {
+        use pb::btc_payment_request_request::{memo, Memo};
+        pb::BtcPaymentRequestRequest {
+            recipient_name: value.recipient_name.clone(),
+            memos: value
+                .memos
+                .iter()
+                .map(|m| match m {
+                    PaymentRequestMemo::Text(note) => Memo {
+                        memo: Some(memo::Memo::TextMemo(memo::TextMemo { note: note.clone() })),
+                    },
+                })
+                .collect(),
+            nonce: value.nonce.clone(),
+            total_amount: value.total_amount,
+            signature: value.signature.clone(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Transaction {
     pub script_configs: Vec<pb::BtcScriptConfigWithKeypath>,
+    /// Script configs of internal outputs which send to a different account than the one being
+    /// spent from, referenced by `TxInternalOutput::output_script_config_index`.
+    pub output_script_configs: Vec<pb::BtcScriptConfigWithKeypath>,
     pub version: u32,
     pub inputs: Vec<TxInput>,
     pub outputs: Vec<TxOutput>,
     pub locktime: u32,
+    /// Payment requests referenced by outputs using `TxExternalOutput::payment_request_index`.
+    pub payment_requests: Vec<PaymentRequest>,
+}
+
+/// How an output is shown to the user, see `Transaction::summary()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputKind {
+    /// Output to an external address.
+    External { address: String },
+    /// OP_RETURN output with its data.
+    OpReturn { data: Vec<u8> },
+    /// Output to a silent payment address (BIP-352).
+    SilentPayment { address: String },
+    /// Change output of the account being spent from. Not shown on the BitBox.
+    Change,
+    /// Receive address of the account being spent from.
+    SameAccount,
+    /// Address of a different account of the same keystore.
+    OtherAccount { account_keypath: Keypath },
 }
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSummary {
+    pub kind: OutputKind,
+    pub value: u64,
+}
+
+/// Anomalies of a transaction which deserve the user's attention.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxWarning {
+    /// The fee is at least 10% of the amount sent to other wallets, in which case the BitBox also
+    /// shows a warning.
+    HighFee { percentage: f64 },
+    /// The inputs are spent with more than one script type, e.g. p2wpkh and p2tr.
+    MixedScriptTypes,
+}
+
+/// Summary of a transaction as it is shown on the BitBox, see `Transaction::summary()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxSummary {
+    pub outputs: Vec<OutputSummary>,
+    pub total_in: u64,
+    pub total_out: u64,
+    /// Sum of the external (including silent payment) outputs, i.e. the amount leaving the
+    /// wallet.
+    pub amount_sent: u64,
+    pub fee: u64,
+    /// Estimated virtual size of the signed transaction. `None` if it can't be estimated, e.g.
+    /// for multisig and policy inputs.
+    pub vsize: Option<u64>,
+    /// Fee rate in sat/vB, if the virtual size could be estimated.
+    pub fee_rate: Option<f64>,
+    pub warnings: Vec<TxWarning>,
+}
+
+/// Result of `PairedBitBox::btc_sign()`.
+#[derive(Debug, PartialEq)]
+pub struct SignResult {
+    /// One 64 byte signature (compact serialization of the R and S values) per input.
+    pub signatures: Vec<Vec<u8>>,
+    /// Output scripts generated by the BitBox for silent payment outputs, keyed by output index.
+    /// They have been verified and must be used in the final transaction.
+    pub generated_outputs: std::collections::BTreeMap<usize, Vec<u8>>,
+}
+
 // See https://github.com/spesmilo/electrum/blob/84dc181b6e7bb20e88ef6b98fb8925c5f645a765/electrum/ecc.py#L521-L523
-#[derive(Debug, PartialEq, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SignMessageSignature {
     pub sig: Vec<u8>,
@@ -299,6 +426,144 @@ pub enum PsbtError {
     #[error("Invalid OP_RETURN script: {0}")]
     #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-op-return"))]
     InvalidOpReturn(&'static str),
+    #[error("Unsupported or invalid multisig witness script.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-multisig-script"))]
+    InvalidMultisigScript,
+    #[error("Could not find the xpubs of all multisig cosigners in the PSBT global xpubs.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "multisig-xpub-not-found"))]
+    MultisigXpubNotFound,
+    #[error("The script config of the PSBT is not registered.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "script-config-not-registered"))]
+    ScriptConfigNotRegistered,
+    #[error("Invalid silent payment output info.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-silent-payment-info"))]
+    InvalidSilentPaymentInfo,
+    #[error("Could not finalize input {0}: {1}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "finalize"))]
+    Finalize(usize, String),
+    #[error("Could not extract the transaction: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "extract-tx"))]
+    ExtractTx(String),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Assoc), func(pub const fn js_code(&self) -> &'static str))]
+pub enum TxValidationError {
+    #[error("firmware version {0} required")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "version"))]
+    Version(&'static str),
+    #[error("the coin is not supported by this BitBox edition")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "unsupported-coin"))]
+    UnsupportedCoin,
+    #[error("transaction version {0} is not supported, only versions 1 and 2 are")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-version"))]
+    InvalidVersion(u32),
+    #[error("the transaction must have at least one input")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "no-inputs"))]
+    NoInputs,
+    #[error("the transaction must have at least one output")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "no-outputs"))]
+    NoOutputs,
+    #[error("the transaction must have at least one script config")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "no-script-configs"))]
+    NoScriptConfigs,
+    #[error("multisig and policy script configs can't be mixed with other script configs")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "mixed-script-configs"))]
+    MixedScriptConfigs,
+    #[error("script config {0} is invalid or has an invalid keypath")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-script-config"))]
+    InvalidScriptConfig(usize),
+    #[error("output script config {0} is not a simple type or has an invalid keypath")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-output-script-config"))]
+    InvalidOutputScriptConfig(usize),
+    #[error("input {0}: script config index out of range")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "input-script-config-index"))]
+    InputScriptConfigIndex(usize),
+    #[error("input {0}: the sequence number must be at least 0xfffffffd")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "input-sequence"))]
+    InputSequence(usize),
+    #[error("input {0}: the keypath does not match the script config")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "input-keypath"))]
+    InputKeypath(usize),
+    #[error("input {0}: the previous transaction is required but missing")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "input-missing-prev-tx"))]
+    InputMissingPrevTx(usize),
+    #[error("input {0}: the previous transaction does not match the previous output")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "input-invalid-prev-tx"))]
+    InputInvalidPrevTx(usize),
+    #[error("output {0}: script config index out of range")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-script-config-index"))]
+    OutputScriptConfigIndex(usize),
+    #[error("output {0}: the keypath does not match the script config")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-keypath"))]
+    OutputKeypath(usize),
+    #[error("output {0}: the payload length does not match the output type")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-payload"))]
+    OutputPayload(usize),
+    #[error("output {0}: the value must not be zero")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-zero-value"))]
+    OutputZeroValue(usize),
+    #[error("output {0}: OP_RETURN outputs must have a zero value and at most 80 bytes of data")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-op-return"))]
+    OutputOpReturn(usize),
+    #[error("output {0}: payment request index out of range")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-payment-request-index"))]
+    OutputPaymentRequestIndex(usize),
+    #[error("the outputs exceed the inputs")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "insufficient-inputs"))]
+    InsufficientInputs,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Assoc), func(pub const fn js_code(&self) -> &'static str))]
+pub enum AssembleTxError {
+    #[error("one account xpub per script config is required")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "xpub-count"))]
+    XpubCount,
+    #[error("one signature per input is required")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "signature-count"))]
+    SignatureCount,
+    #[error("input {0}: script config index out of range")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "input-script-config-index"))]
+    InputScriptConfigIndex(usize),
+    #[error("input {0}: only single-sig inputs can be assembled")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "unsupported-input"))]
+    UnsupportedInput(usize),
+    #[error("input {0}: invalid previous output hash")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "input-prev-out-hash"))]
+    InputPrevOutHash(usize),
+    #[error("output {0}: script config index out of range")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-script-config-index"))]
+    OutputScriptConfigIndex(usize),
+    #[error("output {0}: invalid payload")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "output-payload"))]
+    OutputPayload(usize),
+    #[error("output {0}: missing generated silent payment output")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "missing-silent-payment-output"))]
+    MissingSilentPaymentOutput(usize),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Assoc), func(pub const fn js_code(&self) -> &'static str))]
+pub enum AddressError {
+    #[error("The account xpub is required to derive the address of a single-sig script config.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "missing-xpub"))]
+    MissingXpub,
+    #[error("Invalid xpub.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-xpub"))]
+    InvalidXpub,
+    #[error("The keypath does not match the script config.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "invalid-keypath"))]
+    InvalidKeypath,
+    #[error("Unsupported script config.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "unsupported-script-config"))]
+    UnsupportedScriptConfig,
+    #[error("Could not derive the policy address: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "policy"))]
+    Policy(String),
+    #[error("The BitBox returned the address {device}, but the host derived {host}.")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "mismatch"))]
+    Mismatch { device: String, host: String },
 }
 
 impl From<PayloadError> for PsbtError {
@@ -437,13 +702,136 @@ fn find_our_key<T: PsbtOutputInfo>(
     Err(PsbtError::KeyNotFound)
 }
 
-fn script_config_from_utxo(
+/// Parses a `OP_m <pubkey1> ... <pubkeyN> OP_n OP_CHECKMULTISIG` script, returning the threshold
+/// and the pubkeys.
+fn parse_multisig_script(
+    script: &bitcoin::Script,
+) -> Option<(u32, Vec<bitcoin::secp256k1::PublicKey>)> {
+    let small_int = |instruction: &Instruction| match instruction {
+        Instruction::Op(op)
+            if (opcodes::all::OP_PUSHNUM_1.to_u8()..=opcodes::all::OP_PUSHNUM_16.to_u8())
+                .contains(&op.to_u8()) =>
+        {
+            Some((op.to_u8() - opcodes::all::OP_PUSHNUM_1.to_u8() + 1) as u32)
+        }
+        _ => None,
+    };
+    let instructions: Vec<Instruction> = script.instructions().collect::<Result<_, _>>().ok()?;
+    let (threshold, rest) = instructions.split_first()?;
+    let (checkmultisig, rest) = rest.split_last()?;
+    let (num_pubkeys, pubkeys) = rest.split_last()?;
+    if *checkmultisig != Instruction::Op(opcodes::all::OP_CHECKMULTISIG) {
+        return None;
+    }
+    let threshold = small_int(threshold)?;
+    let pubkeys = pubkeys
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::PushBytes(push) if push.len() == 33 => {
+                bitcoin::secp256k1::PublicKey::from_slice(push.as_bytes()).ok()
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if small_int(num_pubkeys)? as usize != pubkeys.len()
+        || threshold == 0
+        || threshold as usize > pubkeys.len()
+    {
+        return None;
+    }
+    Some((threshold, pubkeys))
+}
+
+/// Finds the xpub in the PSBT global xpubs from which the pubkey was derived.
+fn find_xpub(
+    xpubs: &std::collections::BTreeMap<Xpub, bitcoin::bip32::KeySource>,
+    pubkey: &bitcoin::secp256k1::PublicKey,
+    (fingerprint, derivation_path): &bitcoin::bip32::KeySource,
+) -> Option<(Xpub, bitcoin::bip32::DerivationPath)> {
+    let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+    xpubs
+        .iter()
+        .find(|(xpub, (xpub_fingerprint, xpub_path))| {
+            if xpub_fingerprint != fingerprint {
+                return false;
+            }
+            match derivation_path.as_ref().strip_prefix(xpub_path.as_ref()) {
+                Some(suffix) => xpub
+                    .derive_pub(&secp, &suffix)
+                    .is_ok_and(|derived| &derived.public_key == pubkey),
+                None => false,
+            }
+        })
+        .map(|(xpub, (_, xpub_path))| (*xpub, xpub_path.clone()))
+}
+
+/// Reconstructs the multisig script config from a p2wsh witness script. The xpubs of all cosigners
+/// must be present in the PSBT global xpubs, and the key origin infos of all pubkeys in the
+/// `bip32_derivation` field of the PSBT input/output.
+fn multisig_script_config(
+    witness_script: &bitcoin::Script,
+    our_pubkey: &bitcoin::secp256k1::PublicKey,
+    bip32_derivation: &std::collections::BTreeMap<
+        bitcoin::secp256k1::PublicKey,
+        bitcoin::bip32::KeySource,
+    >,
+    xpubs: &std::collections::BTreeMap<Xpub, bitcoin::bip32::KeySource>,
+    script_type: pb::btc_script_config::multisig::ScriptType,
+) -> Result<pb::BtcScriptConfigWithKeypath, PsbtError> {
+    let (threshold, pubkeys) =
+        parse_multisig_script(witness_script).ok_or(PsbtError::InvalidMultisigScript)?;
+    // The BitBox only supports sorted multisig scripts (BIP-67).
+    if !pubkeys
+        .windows(2)
+        .all(|w| w[0].serialize() < w[1].serialize())
+    {
+        return Err(PsbtError::InvalidMultisigScript);
+    }
+
+    let mut multisig_xpubs: Vec<Xpub> = Vec::new();
+    let mut our_xpub: Option<(Xpub, bitcoin::bip32::DerivationPath)> = None;
+    for pubkey in pubkeys.iter() {
+        let key_source = bip32_derivation
+            .get(pubkey)
+            .ok_or(PsbtError::MultisigXpubNotFound)?;
+        let (xpub, xpub_path) =
+            find_xpub(xpubs, pubkey, key_source).ok_or(PsbtError::MultisigXpubNotFound)?;
+        if pubkey == our_pubkey {
+            our_xpub = Some((xpub, xpub_path));
+        }
+        multisig_xpubs.push(xpub);
+    }
+    let (our_xpub, our_xpub_path) = our_xpub.ok_or(PsbtError::KeyNotFound)?;
+
+    // The order of the xpubs does not depend on the order of the pubkeys in the script, which
+    // changes from address to address, so all inputs and change outputs of the same multisig
+    // account result in the same script config.
+    multisig_xpubs.sort_by_key(|xpub| xpub.encode());
+    let our_xpub_index = multisig_xpubs
+        .iter()
+        .position(|xpub| xpub == &our_xpub)
+        .unwrap();
+
+    Ok(pb::BtcScriptConfigWithKeypath {
+        script_config: Some(make_script_config_multisig(
+            threshold,
+            &multisig_xpubs,
+            our_xpub_index as _,
+            script_type,
+        )),
+        keypath: Keypath::from(&our_xpub_path).to_vec(),
+    })
+}
+
+fn script_config_from_utxo<T: PsbtOutputInfo>(
     output: &bitcoin::TxOut,
-    keypath: Keypath,
+    our_key: &OurKey,
     redeem_script: Option<&bitcoin::ScriptBuf>,
-    _witness_script: Option<&bitcoin::ScriptBuf>,
+    witness_script: Option<&bitcoin::ScriptBuf>,
+    output_info: T,
+    xpubs: &std::collections::BTreeMap<Xpub, bitcoin::bip32::KeySource>,
 ) -> Result<pb::BtcScriptConfigWithKeypath, PsbtError> {
-    let keypath = keypath.hardened_prefix();
+    let keypath = our_key.keypath().hardened_prefix();
     if output.script_pubkey.is_p2wpkh() {
         return Ok(pb::BtcScriptConfigWithKeypath {
             script_config: Some(make_script_config_simple(
@@ -473,922 +861,4186 @@ fn script_config_from_utxo(
     let redeem_script_is_p2wsh = redeem_script.map(|s| s.is_p2wsh()).unwrap_or(false);
     let is_p2wsh_p2sh = output.script_pubkey.is_p2sh() && redeem_script_is_p2wsh;
     if output.script_pubkey.is_p2wsh() || is_p2wsh_p2sh {
-        todo!();
+        let witness_script = witness_script.ok_or(PsbtError::InvalidMultisigScript)?;
+        let p2wsh = bitcoin::ScriptBuf::new_p2wsh(&witness_script.wscript_hash());
+        let (expected_script, script_type) = if is_p2wsh_p2sh {
+            (
+                redeem_script.unwrap(),
+                pb::btc_script_config::multisig::ScriptType::P2wshP2sh,
+            )
+        } else {
+            (
+                &output.script_pubkey,
+                pb::btc_script_config::multisig::ScriptType::P2wsh,
+            )
+        };
+        if &p2wsh != expected_script {
+            return Err(PsbtError::InvalidMultisigScript);
+        }
+        let our_pubkey = match our_key {
+            OurKey::Segwit(pubkey, _) => pubkey,
+            _ => return Err(PsbtError::KeyNotFound),
+        };
+        return multisig_script_config(
+            witness_script,
+            our_pubkey,
+            output_info.get_bip32_derivation(),
+            xpubs,
+            script_type,
+        );
     }
     Err(PsbtError::UnknownOutputType)
 }
 
-impl Transaction {
-    fn from_psbt(
-        our_root_fingerprint: &[u8],
-        psbt: &bitcoin::psbt::Psbt,
-        force_script_config: Option<pb::BtcScriptConfigWithKeypath>,
-    ) -> Result<(Self, Vec<OurKey>), PsbtError> {
-        let mut script_configs: Vec<pb::BtcScriptConfigWithKeypath> = Vec::new();
-        let mut is_script_config_forced = false;
-        if let Some(cfg) = force_script_config {
-            script_configs.push(cfg);
-            is_script_config_forced = true;
+/// Returns the input's pubkey as used for silent payments (BIP-352), if the input type is eligible.
+fn silent_payment_pubkey(
+    utxo: &bitcoin::TxOut,
+    redeem_script: Option<&bitcoin::ScriptBuf>,
+    our_key: &OurKey,
+) -> Option<bitcoin::secp256k1::PublicKey> {
+    let script = &utxo.script_pubkey;
+    if script.is_p2tr() {
+        let output_key =
+            bitcoin::secp256k1::XOnlyPublicKey::from_slice(&script.as_bytes()[2..]).ok()?;
+        return Some(bitcoin::secp256k1::PublicKey::from_x_only_public_key(
+            output_key,
+            bitcoin::secp256k1::Parity::Even,
+        ));
+    }
+    match our_key {
+        OurKey::Segwit(pubkey, _) if script.is_p2wpkh() => Some(*pubkey),
+        // Only p2sh-p2wpkh is eligible among the p2sh inputs, not e.g. p2wsh-p2sh multisig.
+        OurKey::Segwit(pubkey, _) if script.is_p2sh() => {
+            let redeem_script = redeem_script?;
+            (redeem_script.is_p2wpkh()
+                && bitcoin::ScriptBuf::new_p2sh(&redeem_script.script_hash()) == *script)
+                .then_some(*pubkey)
         }
+        _ => None,
+    }
+}
 
-        let mut our_keys: Vec<OurKey> = Vec::new();
-        let mut inputs: Vec<TxInput> = Vec::new();
-
-        let mut add_script_config = |script_config: pb::BtcScriptConfigWithKeypath| -> usize {
-            match script_configs.iter().position(|el| el == &script_config) {
-                Some(pos) => pos,
-                None => {
-                    script_configs.push(script_config);
-                    script_configs.len() - 1
-                }
-            }
-        };
-
-        for (input_index, (tx_input, psbt_input)) in
-            psbt.unsigned_tx.input.iter().zip(&psbt.inputs).enumerate()
-        {
-            let utxo = psbt.spend_utxo(input_index)?;
-            let our_key = find_our_key(our_root_fingerprint, psbt_input)?;
-            let script_config_index = if is_script_config_forced {
-                0
-            } else {
-                add_script_config(script_config_from_utxo(
-                    utxo,
-                    our_key.keypath(),
-                    psbt_input.redeem_script.as_ref(),
-                    psbt_input.witness_script.as_ref(),
-                )?)
-            };
+/// PSBT_OUT_SP_V0_INFO, see BIP-375.
+const PSBT_OUT_SP_V0_INFO: u8 = 0x09;
 
-            inputs.push(TxInput {
-                prev_out_hash: (tx_input.previous_output.txid.as_ref() as &[u8]).to_vec(),
-                prev_out_index: tx_input.previous_output.vout,
-                prev_out_value: utxo.value.to_sat(),
-                sequence: tx_input.sequence.to_consensus_u32(),
-                keypath: our_key.keypath(),
-                script_config_index: script_config_index as _,
-                prev_tx: psbt_input.non_witness_utxo.as_ref().map(PrevTx::from),
-            });
-            our_keys.push(our_key);
-        }
+/// Returns the silent payment address of the output if it has the silent payment info
+/// (PSBT_OUT_SP_V0_INFO, containing the 33 byte scan and spend pubkeys).
+fn silent_payment_address(
+    coin: pb::BtcCoin,
+    psbt_output: &bitcoin::psbt::Output,
+) -> Result<Option<String>, PsbtError> {
+    let info = match psbt_output.unknown.get(&bitcoin::psbt::raw::Key {
+        type_value: PSBT_OUT_SP_V0_INFO,
+        key: vec![],
+    }) {
+        Some(info) => info,
+        None => return Ok(None),
+    };
+    if info.len() != 66 {
+        return Err(PsbtError::InvalidSilentPaymentInfo);
+    }
+    let hrp = match coin {
+        pb::BtcCoin::Btc => "sp",
+        pb::BtcCoin::Tbtc | pb::BtcCoin::Rbtc => "tsp",
+        _ => return Err(PsbtError::InvalidSilentPaymentInfo),
+    };
+    let address = crate::silentpayments::Address {
+        scan_pubkey: bitcoin::secp256k1::PublicKey::from_slice(&info[..33])
+            .map_err(|_| PsbtError::InvalidSilentPaymentInfo)?,
+        spend_pubkey: bitcoin::secp256k1::PublicKey::from_slice(&info[33..])
+            .map_err(|_| PsbtError::InvalidSilentPaymentInfo)?,
+    };
+    Ok(Some(address.encode(hrp)))
+}
 
-        let mut outputs: Vec<TxOutput> = Vec::new();
-        for (tx_output, psbt_output) in psbt.unsigned_tx.output.iter().zip(&psbt.outputs) {
-            let our_key = find_our_key(our_root_fingerprint, psbt_output);
-            // Either change output or a non-change output owned by the BitBox.
-            match our_key {
-                Ok(our_key) => {
-                    let script_config_index = if is_script_config_forced {
-                        0
-                    } else {
-                        add_script_config(script_config_from_utxo(
-                            tx_output,
-                            our_key.keypath(),
-                            psbt_output.redeem_script.as_ref(),
-                            psbt_output.witness_script.as_ref(),
-                        )?)
-                    };
-                    outputs.push(TxOutput::Internal(TxInternalOutput {
-                        keypath: our_key.keypath(),
-                        value: tx_output.value.to_sat(),
-                        script_config_index: script_config_index as _,
-                    }));
-                }
-                Err(_) => {
-                    outputs.push(TxOutput::External(tx_output.try_into()?));
-                }
-            }
+fn add_script_config(
+    script_configs: &mut Vec<pb::BtcScriptConfigWithKeypath>,
+    script_config: pb::BtcScriptConfigWithKeypath,
+) -> usize {
+    match script_configs.iter().position(|el| el == &script_config) {
+        Some(pos) => pos,
+        None => {
+            script_configs.push(script_config);
+            script_configs.len() - 1
         }
-
-        Ok((
-            Transaction {
-                script_configs,
-                version: psbt.unsigned_tx.version.0 as _,
-                inputs,
-                outputs,
-                locktime: psbt.unsigned_tx.lock_time.to_consensus_u32(),
-            },
-            our_keys,
-        ))
     }
 }
 
-/// Create a single-sig script config.
-pub fn make_script_config_simple(
-    simple_type: pb::btc_script_config::SimpleType,
-) -> pb::BtcScriptConfig {
-    pb::BtcScriptConfig {
-        config: Some(pb::btc_script_config::Config::SimpleType(
-            simple_type.into(),
-        )),
-    }
+fn is_simple_type(script_config: &pb::BtcScriptConfigWithKeypath) -> bool {
+    matches!(
+        script_config.script_config.as_ref(),
+        Some(pb::BtcScriptConfig {
+            config: Some(pb::btc_script_config::Config::SimpleType(_)),
+        })
+    )
 }
 
-#[derive(Clone)]
-#[cfg_attr(
-    feature = "wasm",
-    derive(serde::Deserialize),
-    serde(rename_all = "camelCase")
-)]
-#[derive(PartialEq)]
-pub struct KeyOriginInfo {
-    pub root_fingerprint: Option<bitcoin::bip32::Fingerprint>,
-    pub keypath: Option<Keypath>,
-    pub xpub: bitcoin::bip32::Xpub,
+/// Returns true if an internal output with this script config can reference the script configs of
+/// the inputs. The BitBox accepts multiple input script configs only if they are all single-sig
+/// configs of the same coin and account (e.g. p2wpkh and p2tr of account 0). Other outputs to our
+/// keystore are described using `Transaction::output_script_configs`.
+fn is_input_account(
+    script_configs: &[pb::BtcScriptConfigWithKeypath],
+    script_config: &pb::BtcScriptConfigWithKeypath,
+) -> bool {
+    if script_configs.contains(script_config) {
+        return true;
+    }
+    is_simple_type(script_config)
+        && script_configs.iter().all(|el| {
+            is_simple_type(el)
+                && el.keypath.len() == 3
+                && script_config.keypath.len() == 3
+                && el.keypath[1..] == script_config.keypath[1..]
+        })
 }
 
-fn convert_xpub(xpub: &bitcoin::bip32::Xpub) -> pb::XPub {
-    pb::XPub {
-        depth: vec![xpub.depth],
-        parent_fingerprint: xpub.parent_fingerprint[..].to_vec(),
-        child_num: xpub.child_number.into(),
-        chain_code: xpub.chain_code[..].to_vec(),
-        public_key: xpub.public_key.serialize().to_vec(),
+// Keypath limits enforced by the firmware for single-sig and multisig accounts.
+const MAX_ACCOUNT: u32 = 99;
+const MAX_ADDRESS_INDEX: u32 = 9999;
+// Maximum size of the data pushed in an OP_RETURN output.
+const MAX_OP_RETURN_DATA: usize = 80;
+// Whether a firmware release signs the BIP-322 `to_sign` transaction, see
+// `btc_sign_message_bip322()`. Replace with a version check once one does.
+const FIRMWARE_SUPPORTS_BIP322: bool = false;
+
+/// BIP-44 coin type used in keypaths of the given coin.
+fn bip44_coin_type(coin: pb::BtcCoin) -> u32 {
+    match coin {
+        pb::BtcCoin::Btc => 0,
+        pb::BtcCoin::Ltc => 2,
+        pb::BtcCoin::Tbtc | pb::BtcCoin::Rbtc | pb::BtcCoin::Tltc => 1,
     }
 }
 
-impl From<KeyOriginInfo> for pb::KeyOriginInfo {
-    fn from(value: KeyOriginInfo) -> Self {
-        pb::KeyOriginInfo {
-            root_fingerprint: value
-                .root_fingerprint
-                .map_or(vec![], |fp| fp.as_bytes().to_vec()),
-            keypath: value.keypath.map_or(vec![], |kp| kp.to_vec()),
-            xpub: Some(convert_xpub(&value.xpub)),
+/// Checks the account keypath of a script config, returning false if the firmware would reject it.
+fn is_valid_account_keypath(
+    coin: pb::BtcCoin,
+    script_config: &pb::BtcScriptConfigWithKeypath,
+) -> bool {
+    use crate::keypath::HARDENED;
+    let keypath = &script_config.keypath;
+    let coin_type = bip44_coin_type(coin) + HARDENED;
+    let valid_account = |account: u32| (HARDENED..=HARDENED + MAX_ACCOUNT).contains(&account);
+    match script_config
+        .script_config
+        .as_ref()
+        .and_then(|c| c.config.as_ref())
+    {
+        Some(pb::btc_script_config::Config::SimpleType(simple_type)) => {
+            let purpose = match pb::btc_script_config::SimpleType::try_from(*simple_type) {
+                Ok(pb::btc_script_config::SimpleType::P2wpkhP2sh) => 49,
+                Ok(pb::btc_script_config::SimpleType::P2wpkh) => 84,
+                Ok(pb::btc_script_config::SimpleType::P2tr) => 86,
+                Err(_) => return false,
+            };
+            matches!(keypath.as_slice(), &[p, c, account]
+                if p == purpose + HARDENED && c == coin_type && valid_account(account))
         }
+        Some(pb::btc_script_config::Config::Multisig(multisig)) => {
+            let script_type =
+                match pb::btc_script_config::multisig::ScriptType::try_from(multisig.script_type) {
+                    Ok(pb::btc_script_config::multisig::ScriptType::P2wsh) => 2,
+                    Ok(pb::btc_script_config::multisig::ScriptType::P2wshP2sh) => 1,
+                    Err(_) => return false,
+                };
+            matches!(keypath.as_slice(), &[p, c, account, t]
+                if p == 48 + HARDENED && c == coin_type && valid_account(account)
+                    && t == script_type + HARDENED)
+        }
+        Some(pb::btc_script_config::Config::Policy(_)) => true,
+        None => false,
     }
 }
 
-/// Create a multi-sig script config.
-pub fn make_script_config_multisig(
-    threshold: u32,
-    xpubs: &[bitcoin::bip32::Xpub],
-    our_xpub_index: u32,
-    script_type: pb::btc_script_config::multisig::ScriptType,
-) -> pb::BtcScriptConfig {
-    pb::BtcScriptConfig {
-        config: Some(pb::btc_script_config::Config::Multisig(
-            pb::btc_script_config::Multisig {
-                threshold,
-                xpubs: xpubs.iter().map(convert_xpub).collect(),
-                our_xpub_index,
-                script_type: script_type as _,
-            },
-        )),
+/// Checks that `keypath` is a receive or change address keypath of the script config's account.
+fn is_valid_address_keypath(
+    script_config: &pb::BtcScriptConfigWithKeypath,
+    keypath: &Keypath,
+) -> bool {
+    let keypath = keypath.to_vec();
+    let account = &script_config.keypath;
+    if keypath.len() != account.len() + 2 || !keypath.starts_with(account) {
+        return false;
+    }
+    let (change, address) = (keypath[account.len()], keypath[account.len() + 1]);
+    match script_config
+        .script_config
+        .as_ref()
+        .and_then(|c| c.config.as_ref())
+    {
+        Some(pb::btc_script_config::Config::Policy(_)) => {
+            change < crate::keypath::HARDENED && address < crate::keypath::HARDENED
+        }
+        _ => change <= 1 && address <= MAX_ADDRESS_INDEX,
     }
 }
 
-/// Create a wallet policy script config according to the wallet policies BIP:
-/// <https://github.com/bitcoin/bips/pull/1389>
-///
-/// At least one of the keys must be ours, i.e. contain our root fingerprint and a keypath to one of
-/// our xpubs.
-pub fn make_script_config_policy(policy: &str, keys: &[KeyOriginInfo]) -> pb::BtcScriptConfig {
-    pb::BtcScriptConfig {
-        config: Some(pb::btc_script_config::Config::Policy(
-            pb::btc_script_config::Policy {
-                policy: policy.into(),
-                keys: keys.iter().cloned().map(pb::KeyOriginInfo::from).collect(),
-            },
-        )),
+impl PrevTx {
+    fn txid(&self) -> Option<bitcoin::Txid> {
+        use bitcoin::hashes::Hash;
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version(self.version as i32),
+            lock_time: bitcoin::absolute::LockTime::from_consensus(self.locktime),
+            input: self
+                .inputs
+                .iter()
+                .map(|input| {
+                    Some(bitcoin::TxIn {
+                        previous_output: bitcoin::OutPoint {
+                            txid: bitcoin::Txid::from_slice(&input.prev_out_hash).ok()?,
+                            vout: input.prev_out_index,
+                        },
+                        script_sig: input.signature_script.clone().into(),
+                        sequence: bitcoin::Sequence(input.sequence),
+                        witness: bitcoin::Witness::new(),
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?,
+            output: self
+                .outputs
+                .iter()
+                .map(|output| bitcoin::TxOut {
+                    value: bitcoin::Amount::from_sat(output.value),
+                    script_pubkey: output.pubkey_script.clone().into(),
+                })
+                .collect(),
+        };
+        Some(tx.compute_txid())
     }
 }
 
-fn is_taproot_simple(script_config: &pb::BtcScriptConfigWithKeypath) -> bool {
-    matches!(
-        script_config.script_config.as_ref(),
-        Some(pb::BtcScriptConfig {
-            config: Some(pb::btc_script_config::Config::SimpleType(simple_type)),
-        }) if *simple_type == pb::btc_script_config::SimpleType::P2tr as i32
-    )
-}
+impl Transaction {
+    /// Checks the transaction against the rules enforced by the firmware of the given product and
+    /// version, so that invalid transactions are rejected before anything is sent to the BitBox.
+    /// This is done automatically by `PairedBitBox::btc_sign()`.
+    pub fn validate(
+        &self,
+        coin: pb::BtcCoin,
+        product: crate::Product,
+        version: &semver::Version,
+    ) -> Result<(), TxValidationError> {
+        let require_version = |req: &'static str| {
+            if semver::VersionReq::parse(req).unwrap().matches(version) {
+                Ok(())
+            } else {
+                Err(TxValidationError::Version(req))
+            }
+        };
+        require_version(">=9.4.0")?;
+        if matches!(
+            product,
+            crate::Product::BitBox02BtcOnly | crate::Product::BitBox02NovaBtcOnly
+        ) && matches!(coin, pb::BtcCoin::Ltc | pb::BtcCoin::Tltc)
+        {
+            return Err(TxValidationError::UnsupportedCoin);
+        }
+        if self.version != 1 && self.version != 2 {
+            return Err(TxValidationError::InvalidVersion(self.version));
+        }
+        if self.inputs.is_empty() {
+            return Err(TxValidationError::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            return Err(TxValidationError::NoOutputs);
+        }
 
-fn is_taproot_policy(script_config: &pb::BtcScriptConfigWithKeypath) -> bool {
-    matches!(
-        script_config.script_config.as_ref(),
-        Some(pb::BtcScriptConfig {
-            config: Some(pb::btc_script_config::Config::Policy(policy)),
-        })  if policy.policy.as_str().starts_with("tr("),
-    )
-}
+        if self.script_configs.is_empty() {
+            return Err(TxValidationError::NoScriptConfigs);
+        }
+        if self.script_configs.len() > 1 && !self.script_configs.iter().all(is_simple_type) {
+            return Err(TxValidationError::MixedScriptConfigs);
+        }
+        for (index, script_config) in self.script_configs.iter().enumerate() {
+            if !is_valid_account_keypath(coin, script_config) {
+                return Err(TxValidationError::InvalidScriptConfig(index));
+            }
+        }
+        if self.script_configs.iter().any(is_taproot_simple) {
+            require_version(">=9.10.0")?;
+        }
+        if !self.output_script_configs.is_empty() {
+            require_version(">=9.22.0")?;
+        }
+        for (index, script_config) in self.output_script_configs.iter().enumerate() {
+            if !is_simple_type(script_config) || !is_valid_account_keypath(coin, script_config) {
+                return Err(TxValidationError::InvalidOutputScriptConfig(index));
+            }
+        }
+        if !self.payment_requests.is_empty() {
+            require_version(">=9.19.0")?;
+        }
 
-fn is_schnorr(script_config: &pb::BtcScriptConfigWithKeypath) -> bool {
-    is_taproot_simple(script_config) | is_taproot_policy(script_config)
+        // The previous transactions are needed unless all inputs are Taproot.
+        let mut prev_tx_required = false;
+        for (index, input) in self.inputs.iter().enumerate() {
+            let script_config = self
+                .script_configs
+                .get(input.script_config_index as usize)
+                .ok_or(TxValidationError::InputScriptConfigIndex(index))?;
+            // The firmware has no rule on the locktime or on RBF signalling: both are only shown
+            // to the user for confirmation. It only rejects sequence numbers below 0xfffffffd.
+            if input.sequence < 0xffffffff - 2 {
+                return Err(TxValidationError::InputSequence(index));
+            }
+            if !is_valid_address_keypath(script_config, &input.keypath) {
+                return Err(TxValidationError::InputKeypath(index));
+            }
+            prev_tx_required |= !is_schnorr(script_config);
+        }
+        if prev_tx_required {
+            for (index, input) in self.inputs.iter().enumerate() {
+                let prev_tx = input
+                    .prev_tx
+                    .as_ref()
+                    .ok_or(TxValidationError::InputMissingPrevTx(index))?;
+                let matches_prev_out = prev_tx
+                    .outputs
+                    .get(input.prev_out_index as usize)
+                    .is_some_and(|output| output.value == input.prev_out_value)
+                    && prev_tx.txid().is_some_and(|txid| {
+                        (txid.as_ref() as &[u8]) == input.prev_out_hash.as_slice()
+                    });
+                if !matches_prev_out {
+                    return Err(TxValidationError::InputInvalidPrevTx(index));
+                }
+            }
+        }
+
+        for (index, output) in self.outputs.iter().enumerate() {
+            match output {
+                TxOutput::Internal(output) => {
+                    let script_config = match output.output_script_config_index {
+                        Some(i) => self.output_script_configs.get(i as usize),
+                        None => self.script_configs.get(output.script_config_index as usize),
+                    }
+                    .ok_or(TxValidationError::OutputScriptConfigIndex(index))?;
+                    if !is_valid_address_keypath(script_config, &output.keypath) {
+                        return Err(TxValidationError::OutputKeypath(index));
+                    }
+                    if output.value == 0 {
+                        return Err(TxValidationError::OutputZeroValue(index));
+                    }
+                }
+                TxOutput::External(output) => {
+                    let expected_len = match output.payload.output_type {
+                        pb::BtcOutputType::P2pkh
+                        | pb::BtcOutputType::P2sh
+                        | pb::BtcOutputType::P2wpkh => Some(20),
+                        pb::BtcOutputType::P2wsh | pb::BtcOutputType::P2tr => Some(32),
+                        pb::BtcOutputType::OpReturn => None,
+                        pb::BtcOutputType::Unknown => Some(0),
+                    };
+                    match expected_len {
+                        Some(len) if output.payload.data.len() != len || len == 0 => {
+                            return Err(TxValidationError::OutputPayload(index));
+                        }
+                        Some(_) if output.value == 0 => {
+                            return Err(TxValidationError::OutputZeroValue(index));
+                        }
+                        Some(_) => {}
+                        None => {
+                            require_version(">=9.24.0")?;
+                            if output.value != 0 || output.payload.data.len() > MAX_OP_RETURN_DATA {
+                                return Err(TxValidationError::OutputOpReturn(index));
+                            }
+                        }
+                    }
+                    if let Some(i) = output.payment_request_index {
+                        if i as usize >= self.payment_requests.len() {
+                            return Err(TxValidationError::OutputPaymentRequestIndex(index));
+                        }
+                    }
+                }
+                TxOutput::SilentPayment(output) => {
+                    require_version(">=9.21.0")?;
+                    if output.value == 0 {
+                        return Err(TxValidationError::OutputZeroValue(index));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
-impl<R: Runtime> PairedBitBox<R> {
-    /// Retrieves an xpub. For non-standard keypaths, a warning is displayed on the BitBox even if
-    /// `display` is false.
-    pub async fn btc_xpub(
-        &self,
-        coin: pb::BtcCoin,
-        keypath: &Keypath,
-        xpub_type: pb::btc_pub_request::XPubType,
-        display: bool,
-    ) -> Result<String, Error> {
-        match self
-            .query_proto(Request::BtcPub(pb::BtcPubRequest {
-                coin: coin as _,
-                keypath: keypath.to_vec(),
-                display,
-                output: Some(pb::btc_pub_request::Output::XpubType(xpub_type as _)),
-            }))
-            .await?
-        {
-            Response::Pub(pb::PubResponse { r#pub }) => Ok(r#pub),
-            _ => Err(Error::UnexpectedResponse),
+// Fee percentage from which the BitBox shows a high fee warning.
+const HIGH_FEE_PERCENTAGE: f64 = 10.0;
+
+/// Estimated weight of an input spent with the given script config, if it is a simple type.
+fn input_weight(script_config: &pb::BtcScriptConfigWithKeypath) -> Option<u64> {
+    // Outpoint, script_sig length and sequence.
+    const BASE: u64 = 36 + 1 + 4;
+    // Witness item count, 72 byte DER signature and 33 byte pubkey.
+    const P2WPKH_WITNESS: u64 = 1 + 1 + 72 + 1 + 33;
+    match script_config.script_config.as_ref()?.config.as_ref()? {
+        pb::btc_script_config::Config::SimpleType(simple_type) => {
+            match pb::btc_script_config::SimpleType::try_from(*simple_type).ok()? {
+                pb::btc_script_config::SimpleType::P2wpkh => Some(4 * BASE + P2WPKH_WITNESS),
+                // script_sig pushing the 22 byte p2wpkh redeem script.
+                pb::btc_script_config::SimpleType::P2wpkhP2sh => {
+                    Some(4 * (BASE + 23) + P2WPKH_WITNESS)
+                }
+                // Witness item count and 64 byte Schnorr signature.
+                pb::btc_script_config::SimpleType::P2tr => Some(4 * BASE + 1 + 1 + 64),
+            }
         }
+        _ => None,
     }
+}
 
-    /// Retrieves multiple xpubs at once. Only standard keypaths are allowed.
-    /// On firmware <v9.24.0, this falls back to calling `btc_xpub()` for each keypath.
-    pub async fn btc_xpubs(
-        &self,
-        coin: pb::BtcCoin,
-        keypaths: &[Keypath],
-        xpub_type: pb::btc_xpubs_request::XPubType,
-    ) -> Result<Vec<String>, Error> {
-        if self.validate_version(">=9.24.0").is_err() {
-            // Fallback to fetching them one-by-one on older firmware.
-            let mut xpubs = Vec::<String>::with_capacity(keypaths.len());
-            for keypath in keypaths {
-                let converted_xpub_type = match xpub_type {
-                    pb::btc_xpubs_request::XPubType::Unknown => return Err(Error::Unknown),
-                    pb::btc_xpubs_request::XPubType::Tpub => pb::btc_pub_request::XPubType::Tpub,
-                    pb::btc_xpubs_request::XPubType::Xpub => pb::btc_pub_request::XPubType::Xpub,
-                };
-                let xpub = self
-                    .btc_xpub(coin, keypath, converted_xpub_type, false)
-                    .await?;
-                xpubs.push(xpub);
+/// Length of the output script of an address of the given script config.
+fn script_config_output_script_len(script_config: &pb::BtcScriptConfigWithKeypath) -> Option<u64> {
+    match script_config.script_config.as_ref()?.config.as_ref()? {
+        pb::btc_script_config::Config::SimpleType(simple_type) => {
+            match pb::btc_script_config::SimpleType::try_from(*simple_type).ok()? {
+                pb::btc_script_config::SimpleType::P2wpkh => Some(22),
+                pb::btc_script_config::SimpleType::P2wpkhP2sh => Some(23),
+                pb::btc_script_config::SimpleType::P2tr => Some(34),
             }
-            return Ok(xpubs);
         }
-        match self
-            .query_proto_btc(pb::btc_request::Request::Xpubs(pb::BtcXpubsRequest {
-                coin: coin as _,
-                xpub_type: xpub_type as _,
-                keypaths: keypaths.iter().map(|kp| kp.into()).collect(),
-            }))
-            .await?
-        {
-            pb::btc_response::Response::Pubs(pb::PubsResponse { pubs }) => Ok(pubs),
-            _ => Err(Error::UnexpectedResponse),
+        pb::btc_script_config::Config::Multisig(multisig) => {
+            match pb::btc_script_config::multisig::ScriptType::try_from(multisig.script_type)
+                .ok()?
+            {
+                pb::btc_script_config::multisig::ScriptType::P2wsh => Some(34),
+                pb::btc_script_config::multisig::ScriptType::P2wshP2sh => Some(23),
+            }
         }
+        // wsh(...) and tr(...) policies.
+        pb::btc_script_config::Config::Policy(_) => Some(34),
     }
+}
 
-    /// Retrieves a Bitcoin address at the provided keypath.
-    ///
-    /// For the simple script configs (single-sig), the keypath must follow the
-    /// BIP44/BIP49/BIP84/BIP86 conventions.
-    pub async fn btc_address(
-        &self,
-        coin: pb::BtcCoin,
-        keypath: &Keypath,
-        script_config: &pb::BtcScriptConfig,
-        display: bool,
-    ) -> Result<String, Error> {
-        match self
-            .query_proto(Request::BtcPub(pb::BtcPubRequest {
-                coin: coin as _,
-                keypath: keypath.to_vec(),
-                display,
-                output: Some(pb::btc_pub_request::Output::ScriptConfig(
-                    script_config.clone(),
-                )),
-            }))
-            .await?
-        {
-            Response::Pub(pb::PubResponse { r#pub }) => Ok(r#pub),
-            _ => Err(Error::UnexpectedResponse),
+fn compact_size_len(n: usize) -> u64 {
+    bitcoin::VarInt::from(n).size() as u64
+}
+
+/// Output script of an external output. Returns None if the payload does not match the type.
+fn payload_script_pubkey(payload: &Payload) -> Option<bitcoin::ScriptBuf> {
+    use bitcoin::hashes::Hash;
+    let data = payload.data.as_slice();
+    match payload.output_type {
+        pb::BtcOutputType::P2pkh => Some(bitcoin::ScriptBuf::new_p2pkh(
+            &bitcoin::PubkeyHash::from_slice(data).ok()?,
+        )),
+        pb::BtcOutputType::P2sh => Some(bitcoin::ScriptBuf::new_p2sh(
+            &bitcoin::ScriptHash::from_slice(data).ok()?,
+        )),
+        pb::BtcOutputType::P2wpkh => Some(bitcoin::ScriptBuf::new_p2wpkh(
+            &bitcoin::WPubkeyHash::from_slice(data).ok()?,
+        )),
+        pb::BtcOutputType::P2wsh => Some(bitcoin::ScriptBuf::new_p2wsh(
+            &bitcoin::WScriptHash::from_slice(data).ok()?,
+        )),
+        pb::BtcOutputType::P2tr => Some(bitcoin::ScriptBuf::new_p2tr_tweaked(
+            bitcoin::key::TweakedPublicKey::dangerous_assume_tweaked(
+                bitcoin::XOnlyPublicKey::from_slice(data).ok()?,
+            ),
+        )),
+        pb::BtcOutputType::OpReturn => {
+            let push = <&bitcoin::script::PushBytes>::try_from(data).ok()?;
+            Some(bitcoin::ScriptBuf::new_op_return(push))
         }
+        pb::BtcOutputType::Unknown => None,
     }
+}
 
-    async fn query_proto_btc(
-        &self,
-        request: pb::btc_request::Request,
-    ) -> Result<pb::btc_response::Response, Error> {
-        match self
-            .query_proto(Request::Btc(pb::BtcRequest {
-                request: Some(request),
-            }))
-            .await?
+impl Transaction {
+    /// Summarizes the transaction the way the BitBox shows it when signing: the outputs are
+    /// classified and external addresses are rendered for the given coin, the fee and the
+    /// estimated fee rate are computed and anomalies are flagged.
+    ///
+    /// If an input's previous transaction is present, the input value is taken from it.
+    pub fn summary(&self, coin: pb::BtcCoin) -> Result<TxSummary, TxValidationError> {
+        use crate::keypath::HARDENED;
+
+        let mut total_in: u64 = 0;
+        let mut vsize_known = true;
+        let mut weight: u64 = 4 * (4 + 4
+            + compact_size_len(self.inputs.len())
+            + compact_size_len(self.outputs.len()))
+            // Segwit marker and flag.
+            + 2;
+        let mut input_script_types = Vec::with_capacity(self.inputs.len());
+        for (index, input) in self.inputs.iter().enumerate() {
+            let script_config = self
+                .script_configs
+                .get(input.script_config_index as usize)
+                .ok_or(TxValidationError::InputScriptConfigIndex(index))?;
+            input_script_types.push(script_config.script_config.as_ref());
+            let value = match &input.prev_tx {
+                Some(prev_tx) => {
+                    prev_tx
+                        .outputs
+                        .get(input.prev_out_index as usize)
+                        .ok_or(TxValidationError::InputInvalidPrevTx(index))?
+                        .value
+                }
+                None => input.prev_out_value,
+            };
+            total_in = total_in
+                .checked_add(value)
+                .ok_or(TxValidationError::InsufficientInputs)?;
+            match input_weight(script_config) {
+                Some(input_weight) => weight += input_weight,
+                None => vsize_known = false,
+            }
+        }
+
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        let mut total_out: u64 = 0;
+        let mut amount_sent: u64 = 0;
+        for (index, output) in self.outputs.iter().enumerate() {
+            let (kind, value, script_len) = match output {
+                TxOutput::Internal(output) => {
+                    let (kind, script_config) = match output.output_script_config_index {
+                        Some(i) => {
+                            let script_config = self
+                                .output_script_configs
+                                .get(i as usize)
+                                .ok_or(TxValidationError::OutputScriptConfigIndex(index))?;
+                            (
+                                OutputKind::OtherAccount {
+                                    account_keypath: script_config.keypath.as_slice().into(),
+                                },
+                                script_config,
+                            )
+                        }
+                        None => {
+                            let script_config = self
+                                .script_configs
+                                .get(output.script_config_index as usize)
+                                .ok_or(TxValidationError::OutputScriptConfigIndex(index))?;
+                            let keypath = output.keypath.to_vec();
+                            let is_change =
+                                keypath.len() >= 2 && keypath[keypath.len() - 2] % HARDENED == 1;
+                            let kind = if is_change {
+                                OutputKind::Change
+                            } else {
+                                OutputKind::SameAccount
+                            };
+                            (kind, script_config)
+                        }
+                    };
+                    (
+                        kind,
+                        output.value,
+                        script_config_output_script_len(script_config),
+                    )
+                }
+                TxOutput::External(output) => {
+                    let script_pubkey = payload_script_pubkey(&output.payload)
+                        .ok_or(TxValidationError::OutputPayload(index))?;
+                    let kind = if output.payload.output_type == pb::BtcOutputType::OpReturn {
+                        OutputKind::OpReturn {
+                            data: output.payload.data.clone(),
+                        }
+                    } else {
+                        OutputKind::External {
+                            address: address_from_script_pubkey(coin, &script_pubkey)
+                                .map_err(|_| TxValidationError::OutputPayload(index))?,
+                        }
+                    };
+                    amount_sent = amount_sent
+                        .checked_add(output.value)
+                        .ok_or(TxValidationError::InsufficientInputs)?;
+                    (kind, output.value, Some(script_pubkey.len() as u64))
+                }
+                TxOutput::SilentPayment(output) => {
+                    amount_sent = amount_sent
+                        .checked_add(output.value)
+                        .ok_or(TxValidationError::InsufficientInputs)?;
+                    (
+                        OutputKind::SilentPayment {
+                            address: output.address.clone(),
+                        },
+                        output.value,
+                        // Silent payment outputs are Taproot outputs.
+                        Some(34),
+                    )
+                }
+            };
+            total_out = total_out
+                .checked_add(value)
+                .ok_or(TxValidationError::InsufficientInputs)?;
+            match script_len {
+                Some(len) => weight += 4 * (8 + compact_size_len(len as usize) + len),
+                None => vsize_known = false,
+            }
+            outputs.push(OutputSummary { kind, value });
+        }
+
+        let fee = total_in
+            .checked_sub(total_out)
+            .ok_or(TxValidationError::InsufficientInputs)?;
+        let vsize = vsize_known.then(|| weight.div_ceil(4));
+        let fee_rate = vsize.map(|vsize| fee as f64 / vsize as f64);
+
+        let mut warnings = Vec::new();
+        if amount_sent > 0 {
+            let percentage = 100.0 * fee as f64 / amount_sent as f64;
+            if percentage >= HIGH_FEE_PERCENTAGE {
+                warnings.push(TxWarning::HighFee { percentage });
+            }
+        }
+        if input_script_types
+            .iter()
+            .any(|script_type| Some(script_type) != input_script_types.first())
         {
-            Response::Btc(pb::BtcResponse {
-                response: Some(response),
-            }) => Ok(response),
-            _ => Err(Error::UnexpectedResponse),
+            warnings.push(TxWarning::MixedScriptTypes);
         }
+
+        Ok(TxSummary {
+            outputs,
+            total_in,
+            total_out,
+            amount_sent,
+            fee,
+            vsize,
+            fee_rate,
+            warnings,
+        })
     }
+}
 
-    async fn get_next_response(&self, request: Request) -> Result<pb::BtcSignNextResponse, Error> {
-        match self.query_proto(request).await? {
-            Response::BtcSignNext(next_response) => Ok(next_response),
-            _ => Err(Error::UnexpectedResponse),
+/// Summarizes a PSBT the way `PairedBitBox::btc_sign_psbt()` would present it to the BitBox, see
+/// `Transaction::summary()`. `our_root_fingerprint` is the root fingerprint of the BitBox, see
+/// `PairedBitBox::root_fingerprint()`.
+pub fn psbt_summary(
+    coin: pb::BtcCoin,
+    our_root_fingerprint: &Fingerprint,
+    psbt: &bitcoin::psbt::Psbt,
+    force_script_config: Option<pb::BtcScriptConfigWithKeypath>,
+) -> Result<TxSummary, Error> {
+    let (transaction, _) = Transaction::from_psbt(
+        coin,
+        our_root_fingerprint.as_bytes(),
+        psbt,
+        force_script_config,
+    )?;
+    Ok(transaction.summary(coin)?)
+}
+
+/// Returns the first of the `registered` script configs which matches all inputs of the PSBT, i.e.
+/// the output script derived at our keypath of each input is the script of the spent output.
+fn registered_script_config_for_psbt(
+    coin: pb::BtcCoin,
+    our_root_fingerprint: &[u8],
+    psbt: &bitcoin::psbt::Psbt,
+    registered: &[crate::registry::RegisteredScriptConfig],
+) -> Option<pb::BtcScriptConfigWithKeypath> {
+    let inputs = (0..psbt.inputs.len())
+        .map(|index| {
+            Some((
+                find_our_key(our_root_fingerprint, &psbt.inputs[index]).ok()?,
+                psbt.spend_utxo(index).ok()?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    registered.iter().find_map(|registered| {
+        let registration = &registered.registration;
+        if registration.coin != coin as i32 {
+            return None;
         }
-    }
+        let script_config = registration.script_config.as_ref()?;
+        let keypath = match &script_config.config {
+            Some(pb::btc_script_config::Config::Multisig(_)) => registration.keypath.clone(),
+            // Policies are registered without keypath. Our key in the policy is the account.
+            Some(pb::btc_script_config::Config::Policy(policy)) => policy
+                .keys
+                .iter()
+                .find(|key| key.root_fingerprint == our_root_fingerprint)?
+                .keypath
+                .clone(),
+            _ => return None,
+        };
+        let matches = !inputs.is_empty()
+            && inputs.iter().all(|(our_key, utxo)| {
+                derive_script_pubkey(&our_key.keypath(), script_config, None).ok()
+                    == Some(utxo.script_pubkey.clone())
+            });
+        matches.then(|| pb::BtcScriptConfigWithKeypath {
+            script_config: Some(script_config.clone()),
+            keypath,
+        })
+    })
+}
 
-    async fn get_next_response_nested(
-        &self,
-        request: pb::btc_request::Request,
-    ) -> Result<pb::BtcSignNextResponse, Error> {
-        match self.query_proto_btc(request).await? {
-            pb::btc_response::Response::SignNext(next_response) => Ok(next_response),
-            _ => Err(Error::UnexpectedResponse),
+impl Transaction {
+    /// Request answering the BitBox's request for the output at `index` while signing.
+    fn sign_output_request(&self, index: usize) -> Request {
+        match &self.outputs[index] {
+            TxOutput::Internal(output) => Request::BtcSignOutput(pb::BtcSignOutputRequest {
+                ours: true,
+                value: output.value,
+                keypath: output.keypath.to_vec(),
+                script_config_index: output.script_config_index,
+                output_script_config_index: output.output_script_config_index,
+                ..Default::default()
+            }),
+            TxOutput::External(output) => Request::BtcSignOutput(pb::BtcSignOutputRequest {
+                ours: false,
+                value: output.value,
+                r#type: output.payload.output_type as _,
+                payload: output.payload.data.clone(),
+                payment_request_index: output.payment_request_index,
+                ..Default::default()
+            }),
+            TxOutput::SilentPayment(output) => Request::BtcSignOutput(pb::BtcSignOutputRequest {
+                ours: false,
+                value: output.value,
+                silent_payment: Some(pb::btc_sign_output_request::SilentPayment {
+                    address: output.address.clone(),
+                }),
+                ..Default::default()
+            }),
         }
     }
 
-    /// Sign a Bitcoin transaction. Returns one 64 byte signature (compact serlization of the R and
-    /// S values) per input.
-    pub async fn btc_sign(
-        &self,
+    /// Request answering the BitBox's request for the payment request at `index` while signing.
+    fn payment_request_request(&self, index: u32) -> Result<pb::btc_request::Request, Error> {
+        let payment_request = self
+            .payment_requests
+            .get(index as usize)
+            .ok_or(Error::BtcSign(
+                "payment request requested but missing".into(),
+            ))?;
+        Ok(pb::btc_request::Request::PaymentRequest(
+            payment_request.into(),
+        ))
+    }
+
+    fn from_psbt(
         coin: pb::BtcCoin,
-        transaction: &Transaction,
-        format_unit: pb::btc_sign_init_request::FormatUnit,
-    ) -> Result<Vec<Vec<u8>>, Error> {
-        self.validate_version(">=9.4.0")?; // anti-klepto since 9.4.0
-        if transaction.script_configs.iter().any(is_taproot_simple) {
-            self.validate_version(">=9.10.0")?; // taproot since 9.10.0
-        }
-        if transaction.outputs.iter().any(|output| {
-            matches!(
-                output,
-                TxOutput::External(tx_output)
-                    if tx_output.payload.output_type == pb::BtcOutputType::OpReturn
-            )
-        }) {
-            self.validate_version(">=9.24.0")?;
+        our_root_fingerprint: &[u8],
+        psbt: &bitcoin::psbt::Psbt,
+        force_script_config: Option<pb::BtcScriptConfigWithKeypath>,
+    ) -> Result<(Self, Vec<OurKey>), PsbtError> {
+        let mut script_configs: Vec<pb::BtcScriptConfigWithKeypath> = Vec::new();
+        let mut is_script_config_forced = false;
+        if let Some(cfg) = force_script_config {
+            script_configs.push(cfg);
+            is_script_config_forced = true;
         }
 
-        let mut sigs: Vec<Vec<u8>> = Vec::new();
-
-        let mut next_response = self
-            .get_next_response(Request::BtcSignInit(pb::BtcSignInitRequest {
-                coin: coin as _,
-                script_configs: transaction.script_configs.clone(),
-                output_script_configs: vec![],
-                version: transaction.version,
-                num_inputs: transaction.inputs.len() as _,
-                num_outputs: transaction.outputs.len() as _,
-                locktime: transaction.locktime,
-                format_unit: format_unit as _,
-                contains_silent_payment_outputs: false,
-            }))
-            .await?;
+        let mut our_keys: Vec<OurKey> = Vec::new();
+        let mut inputs: Vec<TxInput> = Vec::new();
 
-        let mut is_inputs_pass2 = false;
-        loop {
-            match pb::btc_sign_next_response::Type::try_from(next_response.r#type)
-                .map_err(|_| Error::UnexpectedResponse)?
-            {
-                pb::btc_sign_next_response::Type::Input => {
-                    let input_index: usize = next_response.index as _;
-                    let tx_input: &TxInput = &transaction.inputs[input_index];
+        let mut output_script_configs: Vec<pb::BtcScriptConfigWithKeypath> = Vec::new();
 
-                    let input_is_schnorr = is_schnorr(
-                        &transaction.script_configs[tx_input.script_config_index as usize],
-                    );
-                    let perform_antiklepto = is_inputs_pass2 && !input_is_schnorr;
-                    let host_nonce = if perform_antiklepto {
-                        Some(crate::antiklepto::gen_host_nonce()?)
-                    } else {
-                        None
-                    };
-                    next_response = self
-                        .get_next_response(Request::BtcSignInput(pb::BtcSignInputRequest {
-                            prev_out_hash: tx_input.prev_out_hash.clone(),
-                            prev_out_index: tx_input.prev_out_index,
-                            prev_out_value: tx_input.prev_out_value,
-                            sequence: tx_input.sequence,
-                            keypath: tx_input.keypath.to_vec(),
-                            script_config_index: tx_input.script_config_index,
-                            host_nonce_commitment: host_nonce.as_ref().map(|host_nonce| {
-                                pb::AntiKleptoHostNonceCommitment {
-                                    commitment: crate::antiklepto::host_commit(host_nonce).to_vec(),
-                                }
-                            }),
-                        }))
-                        .await?;
+        for (input_index, (tx_input, psbt_input)) in
+            psbt.unsigned_tx.input.iter().zip(&psbt.inputs).enumerate()
+        {
+            let utxo = psbt.spend_utxo(input_index)?;
+            let our_key = find_our_key(our_root_fingerprint, psbt_input)?;
+            let script_config_index = if is_script_config_forced {
+                0
+            } else {
+                add_script_config(
+                    &mut script_configs,
+                    script_config_from_utxo(
+                        utxo,
+                        &our_key,
+                        psbt_input.redeem_script.as_ref(),
+                        psbt_input.witness_script.as_ref(),
+                        psbt_input,
+                        &psbt.xpub,
+                    )?,
+                )
+            };
 
-                    if let Some(host_nonce) = host_nonce {
-                        if next_response.r#type
-                            != pb::btc_sign_next_response::Type::HostNonce as i32
-                        {
-                            return Err(Error::UnexpectedResponse);
-                        }
-                        if let Some(pb::AntiKleptoSignerCommitment { commitment }) =
-                            next_response.anti_klepto_signer_commitment
-                        {
-                            next_response = self
-                                .get_next_response_nested(
-                                    pb::btc_request::Request::AntikleptoSignature(
-                                        pb::AntiKleptoSignatureRequest {
-                                            host_nonce: host_nonce.to_vec(),
-                                        },
-                                    ),
+            inputs.push(TxInput {
+                prev_out_hash: (tx_input.previous_output.txid.as_ref() as &[u8]).to_vec(),
+                prev_out_index: tx_input.previous_output.vout,
+                prev_out_value: utxo.value.to_sat(),
+                sequence: tx_input.sequence.to_consensus_u32(),
+                keypath: our_key.keypath(),
+                script_config_index: script_config_index as _,
+                prev_tx: psbt_input.non_witness_utxo.as_ref().map(PrevTx::from),
+                silent_payment_pubkey: silent_payment_pubkey(
+                    utxo,
+                    psbt_input.redeem_script.as_ref(),
+                    &our_key,
+                ),
+            });
+            our_keys.push(our_key);
+        }
+
+        let mut outputs: Vec<TxOutput> = Vec::new();
+        for (tx_output, psbt_output) in psbt.unsigned_tx.output.iter().zip(&psbt.outputs) {
+            if let Some(address) = silent_payment_address(coin, psbt_output)? {
+                outputs.push(TxOutput::SilentPayment(TxSilentPaymentOutput {
+                    address,
+                    value: tx_output.value.to_sat(),
+                }));
+                continue;
+            }
+            let our_key = find_our_key(our_root_fingerprint, psbt_output);
+            // Either change output or a non-change output owned by the BitBox.
+            match our_key {
+                Ok(our_key) => {
+                    let (script_config_index, output_script_config_index) =
+                        if is_script_config_forced {
+                            (0, None)
+                        } else {
+                            let script_config = script_config_from_utxo(
+                                tx_output,
+                                &our_key,
+                                psbt_output.redeem_script.as_ref(),
+                                psbt_output.witness_script.as_ref(),
+                                psbt_output,
+                                &psbt.xpub,
+                            )?;
+                            if is_input_account(&script_configs, &script_config) {
+                                (add_script_config(&mut script_configs, script_config), None)
+                            } else {
+                                (
+                                    0,
+                                    Some(add_script_config(
+                                        &mut output_script_configs,
+                                        script_config,
+                                    ) as u32),
                                 )
-                                .await?;
-                            if !next_response.has_signature {
-                                return Err(Error::UnexpectedResponse);
                             }
-                            crate::antiklepto::verify_ecdsa(
-                                &host_nonce,
-                                &commitment,
-                                &next_response.signature,
-                            )?
-                        } else {
-                            return Err(Error::UnexpectedResponse);
-                        }
-                    }
+                        };
+                    outputs.push(TxOutput::Internal(TxInternalOutput {
+                        keypath: our_key.keypath(),
+                        value: tx_output.value.to_sat(),
+                        script_config_index: script_config_index as _,
+                        output_script_config_index,
+                    }));
+                }
+                Err(_) => {
+                    outputs.push(TxOutput::External(tx_output.try_into()?));
+                }
+            }
+        }
 
-                    if is_inputs_pass2 {
-                        if !next_response.has_signature {
-                            return Err(Error::UnexpectedResponse);
-                        }
-                        sigs.push(next_response.signature.clone());
-                    }
-                    if input_index == transaction.inputs.len() - 1 {
-                        is_inputs_pass2 = true
-                    }
+        Ok((
+            Transaction {
+                script_configs,
+                output_script_configs,
+                version: psbt.unsigned_tx.version.0 as _,
+                inputs,
+                outputs,
+                locktime: psbt.unsigned_tx.lock_time.to_consensus_u32(),
+                payment_requests: vec![],
+            },
+            our_keys,
+        ))
+    }
+}
+
+/// Create a single-sig script config.
+pub fn make_script_config_simple(
+    simple_type: pb::btc_script_config::SimpleType,
+) -> pb::BtcScriptConfig {
+    pb::BtcScriptConfig {
+        config: Some(pb::btc_script_config::Config::SimpleType(
+            simple_type.into(),
+        )),
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "wasm",
+    derive(serde::Deserialize),
+    serde(rename_all = "camelCase")
+)]
+#[derive(Debug, PartialEq)]
+pub struct KeyOriginInfo {
+    pub root_fingerprint: Option<bitcoin::bip32::Fingerprint>,
+    pub keypath: Option<Keypath>,
+    pub xpub: bitcoin::bip32::Xpub,
+}
+
+fn convert_xpub(xpub: &bitcoin::bip32::Xpub) -> pb::XPub {
+    pb::XPub {
+        depth: vec![xpub.depth],
+        parent_fingerprint: xpub.parent_fingerprint[..].to_vec(),
+        child_num: xpub.child_number.into(),
+        chain_code: xpub.chain_code[..].to_vec(),
+        public_key: xpub.public_key.serialize().to_vec(),
+    }
+}
+
+impl From<KeyOriginInfo> for pb::KeyOriginInfo {
+    fn from(value: KeyOriginInfo) -> Self {
+        pb::KeyOriginInfo {
+            root_fingerprint: value
+                .root_fingerprint
+                .map_or(vec![], |fp| fp.as_bytes().to_vec()),
+            keypath: value.keypath.map_or(vec![], |kp| kp.to_vec()),
+            xpub: Some(convert_xpub(&value.xpub)),
+        }
+    }
+}
+
+/// Create a multi-sig script config.
+pub fn make_script_config_multisig(
+    threshold: u32,
+    xpubs: &[bitcoin::bip32::Xpub],
+    our_xpub_index: u32,
+    script_type: pb::btc_script_config::multisig::ScriptType,
+) -> pb::BtcScriptConfig {
+    pb::BtcScriptConfig {
+        config: Some(pb::btc_script_config::Config::Multisig(
+            pb::btc_script_config::Multisig {
+                threshold,
+                xpubs: xpubs.iter().map(convert_xpub).collect(),
+                our_xpub_index,
+                script_type: script_type as _,
+            },
+        )),
+    }
+}
+
+/// Create a wallet policy script config according to the wallet policies BIP:
+/// <https://github.com/bitcoin/bips/pull/1389>
+///
+/// At least one of the keys must be ours, i.e. contain our root fingerprint and a keypath to one of
+/// our xpubs.
+pub fn make_script_config_policy(policy: &str, keys: &[KeyOriginInfo]) -> pb::BtcScriptConfig {
+    pb::BtcScriptConfig {
+        config: Some(pb::btc_script_config::Config::Policy(
+            pb::btc_script_config::Policy {
+                policy: policy.into(),
+                keys: keys.iter().cloned().map(pb::KeyOriginInfo::from).collect(),
+            },
+        )),
+    }
+}
+
+/// Finalizes all inputs of a PSBT signed by `PairedBitBox::btc_sign_psbt()`, filling in
+/// `final_script_sig` and `final_script_witness` and clearing the other input fields, as per
+/// BIP-174.
+///
+/// p2wpkh, p2wpkh-p2sh and p2tr key path spends are finalized directly. Other inputs (multisig and
+/// policies) are finalized using rust-miniscript if the `miniscript` feature is enabled, which
+/// requires all signatures needed to satisfy the script to be present. Inputs which are already
+/// finalized are left as is.
+pub fn finalize_psbt(psbt: &mut bitcoin::psbt::Psbt) -> Result<(), PsbtError> {
+    for index in 0..psbt.inputs.len() {
+        let input = &psbt.inputs[index];
+        if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+            continue;
+        }
+        let script_pubkey = psbt.spend_utxo(index)?.script_pubkey.clone();
+        let input = &mut psbt.inputs[index];
+        let p2wpkh_p2sh_redeem_script = input
+            .redeem_script
+            .clone()
+            .filter(|redeem_script| script_pubkey.is_p2sh() && redeem_script.is_p2wpkh());
+        let finalized = if script_pubkey.is_p2wpkh() {
+            input.final_script_witness = Some(p2wpkh_witness(input, &script_pubkey, index)?);
+            true
+        } else if let Some(redeem_script) = p2wpkh_p2sh_redeem_script {
+            let redeem_script_push: &bitcoin::script::PushBytes = redeem_script
+                .as_bytes()
+                .try_into()
+                .map_err(|_| PsbtError::Finalize(index, "redeem script too large".into()))?;
+            input.final_script_witness = Some(p2wpkh_witness(input, &redeem_script, index)?);
+            input.final_script_sig = Some(
+                bitcoin::script::Builder::new()
+                    .push_slice(redeem_script_push)
+                    .into_script(),
+            );
+            true
+        } else if let (true, Some(tap_key_sig)) = (script_pubkey.is_p2tr(), input.tap_key_sig) {
+            input.final_script_witness = Some(bitcoin::Witness::p2tr_key_spend(&tap_key_sig));
+            true
+        } else {
+            false
+        };
+        if finalized {
+            clear_finalized_input(input);
+            continue;
+        }
+        #[cfg(feature = "miniscript")]
+        {
+            use miniscript::psbt::PsbtExt;
+            let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+            psbt.finalize_inp_mut(&secp, index)
+                .map_err(|err| PsbtError::Finalize(index, err.to_string()))?;
+        }
+        #[cfg(not(feature = "miniscript"))]
+        return Err(PsbtError::Finalize(
+            index,
+            "unsupported input type, enable the `miniscript` feature".into(),
+        ));
+    }
+    Ok(())
+}
+
+fn p2wpkh_witness(
+    input: &bitcoin::psbt::Input,
+    script_pubkey: &bitcoin::Script,
+    index: usize,
+) -> Result<bitcoin::Witness, PsbtError> {
+    input
+        .partial_sigs
+        .iter()
+        .find(|(pubkey, _)| {
+            pubkey.wpubkey_hash().is_ok_and(|hash| {
+                bitcoin::ScriptBuf::new_p2wpkh(&hash).as_script() == script_pubkey
+            })
+        })
+        .map(|(pubkey, sig)| bitcoin::Witness::p2wpkh(sig, &pubkey.inner))
+        .ok_or_else(|| PsbtError::Finalize(index, "missing signature".into()))
+}
+
+fn clear_finalized_input(input: &mut bitcoin::psbt::Input) {
+    input.partial_sigs.clear();
+    input.sighash_type = None;
+    input.redeem_script = None;
+    input.witness_script = None;
+    input.bip32_derivation.clear();
+    input.tap_key_sig = None;
+    input.tap_script_sigs.clear();
+    input.tap_scripts.clear();
+    input.tap_key_origins.clear();
+    input.tap_internal_key = None;
+    input.tap_merkle_root = None;
+}
+
+/// Extracts the network serializable transaction from a PSBT finalized with `finalize_psbt()`.
+pub fn extract_tx(psbt: bitcoin::psbt::Psbt) -> Result<bitcoin::Transaction, PsbtError> {
+    if let Some(index) = psbt
+        .inputs
+        .iter()
+        .position(|input| input.final_script_sig.is_none() && input.final_script_witness.is_none())
+    {
+        return Err(PsbtError::Finalize(index, "input is not finalized".into()));
+    }
+    psbt.extract_tx()
+        .map_err(|err| PsbtError::ExtractTx(err.to_string()))
+}
+
+/// Builds the fully signed transaction from a transaction signed with `PairedBitBox::btc_sign()`.
+///
+/// `account_xpubs` and `output_account_xpubs` are the xpubs at the keypaths of
+/// `transaction.script_configs` and `transaction.output_script_configs` respectively, with the same
+/// indices. They are used to derive the input pubkeys and the scripts of internal outputs. Only
+/// p2wpkh, p2wpkh-p2sh and p2tr (key path) inputs are supported.
+pub fn assemble_signed_tx(
+    transaction: &Transaction,
+    sign_result: &SignResult,
+    account_xpubs: &[Xpub],
+    output_account_xpubs: &[Xpub],
+) -> Result<bitcoin::Transaction, Error> {
+    use bitcoin::hashes::Hash;
+
+    if account_xpubs.len() != transaction.script_configs.len()
+        || output_account_xpubs.len() != transaction.output_script_configs.len()
+    {
+        return Err(AssembleTxError::XpubCount.into());
+    }
+    if sign_result.signatures.len() != transaction.inputs.len() {
+        return Err(AssembleTxError::SignatureCount.into());
+    }
+    let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+
+    let mut inputs = Vec::with_capacity(transaction.inputs.len());
+    for (input_index, (input, signature)) in transaction
+        .inputs
+        .iter()
+        .zip(&sign_result.signatures)
+        .enumerate()
+    {
+        let index = input.script_config_index as usize;
+        let (script_config, account_xpub) = transaction
+            .script_configs
+            .get(index)
+            .zip(account_xpubs.get(index))
+            .ok_or(AssembleTxError::InputScriptConfigIndex(input_index))?;
+        let simple_type = match script_config
+            .script_config
+            .as_ref()
+            .and_then(|config| config.config.as_ref())
+        {
+            Some(pb::btc_script_config::Config::SimpleType(simple_type)) => {
+                pb::btc_script_config::SimpleType::try_from(*simple_type)
+                    .map_err(|_| AddressError::UnsupportedScriptConfig)?
+            }
+            _ => return Err(AssembleTxError::UnsupportedInput(input_index).into()),
+        };
+        let keypath = input.keypath.to_vec();
+        let relative: Vec<bitcoin::bip32::ChildNumber> = keypath
+            .get(script_config.keypath.len()..)
+            .filter(|_| keypath.starts_with(&script_config.keypath))
+            .ok_or(AddressError::InvalidKeypath)?
+            .iter()
+            .map(|&el| el.into())
+            .collect();
+        let pubkey = account_xpub
+            .derive_pub(&secp, &relative)
+            .map_err(|_| AddressError::InvalidXpub)?
+            .public_key;
+
+        let mut script_sig = bitcoin::ScriptBuf::new();
+        let witness = match simple_type {
+            pb::btc_script_config::SimpleType::P2tr => bitcoin::Witness::p2tr_key_spend(
+                &bitcoin::taproot::Signature::from_slice(signature)
+                    .map_err(|_| Error::InvalidSignature)?,
+            ),
+            pb::btc_script_config::SimpleType::P2wpkh
+            | pb::btc_script_config::SimpleType::P2wpkhP2sh => {
+                if simple_type == pb::btc_script_config::SimpleType::P2wpkhP2sh {
+                    let redeem_script = bitcoin::ScriptBuf::new_p2wpkh(
+                        &bitcoin::CompressedPublicKey(pubkey).wpubkey_hash(),
+                    );
+                    script_sig = bitcoin::script::Builder::new()
+                        .push_slice(
+                            <&bitcoin::script::PushBytes>::try_from(redeem_script.as_bytes())
+                                .unwrap(),
+                        )
+                        .into_script();
                 }
-                pb::btc_sign_next_response::Type::PrevtxInit => {
-                    let prevtx: &PrevTx =
-                        transaction.inputs[next_response.index as usize].get_prev_tx()?;
-                    next_response = self
-                        .get_next_response_nested(pb::btc_request::Request::PrevtxInit(
-                            pb::BtcPrevTxInitRequest {
-                                version: prevtx.version,
-                                num_inputs: prevtx.inputs.len() as _,
-                                num_outputs: prevtx.outputs.len() as _,
-                                locktime: prevtx.locktime,
-                            },
-                        ))
-                        .await?;
+                bitcoin::Witness::p2wpkh(
+                    &bitcoin::ecdsa::Signature {
+                        signature: bitcoin::secp256k1::ecdsa::Signature::from_compact(signature)
+                            .map_err(|_| Error::InvalidSignature)?,
+                        sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+                    },
+                    &pubkey,
+                )
+            }
+        };
+        inputs.push(bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint {
+                txid: bitcoin::Txid::from_slice(&input.prev_out_hash)
+                    .map_err(|_| AssembleTxError::InputPrevOutHash(input_index))?,
+                vout: input.prev_out_index,
+            },
+            script_sig,
+            sequence: bitcoin::Sequence(input.sequence),
+            witness,
+        });
+    }
+
+    let mut outputs = Vec::with_capacity(transaction.outputs.len());
+    for (index, output) in transaction.outputs.iter().enumerate() {
+        let (script_pubkey, value) = match output {
+            TxOutput::Internal(output) => {
+                let (script_config, account_xpub) = match output.output_script_config_index {
+                    Some(i) => transaction
+                        .output_script_configs
+                        .get(i as usize)
+                        .zip(output_account_xpubs.get(i as usize)),
+                    None => {
+                        let i = output.script_config_index as usize;
+                        transaction.script_configs.get(i).zip(account_xpubs.get(i))
+                    }
                 }
-                pb::btc_sign_next_response::Type::PrevtxInput => {
-                    let prevtx: &PrevTx =
-                        transaction.inputs[next_response.index as usize].get_prev_tx()?;
-                    let prevtx_input: &PrevTxInput =
-                        &prevtx.inputs[next_response.prev_index as usize];
-                    next_response = self
-                        .get_next_response_nested(pb::btc_request::Request::PrevtxInput(
-                            pb::BtcPrevTxInputRequest {
-                                prev_out_hash: prevtx_input.prev_out_hash.clone(),
-                                prev_out_index: prevtx_input.prev_out_index,
-                                signature_script: prevtx_input.signature_script.clone(),
-                                sequence: prevtx_input.sequence,
-                            },
-                        ))
-                        .await?;
+                .ok_or(AssembleTxError::OutputScriptConfigIndex(index))?;
+                let script_pubkey = derive_script_pubkey(
+                    &output.keypath,
+                    script_config
+                        .script_config
+                        .as_ref()
+                        .ok_or(AddressError::UnsupportedScriptConfig)?,
+                    Some(account_xpub),
+                )?;
+                (script_pubkey, output.value)
+            }
+            TxOutput::External(output) => (
+                payload_script_pubkey(&output.payload)
+                    .ok_or(AssembleTxError::OutputPayload(index))?,
+                output.value,
+            ),
+            TxOutput::SilentPayment(output) => (
+                sign_result
+                    .generated_outputs
+                    .get(&index)
+                    .ok_or(AssembleTxError::MissingSilentPaymentOutput(index))?
+                    .clone()
+                    .into(),
+                output.value,
+            ),
+        };
+        outputs.push(bitcoin::TxOut {
+            value: bitcoin::Amount::from_sat(value),
+            script_pubkey,
+        });
+    }
+
+    Ok(bitcoin::Transaction {
+        version: bitcoin::transaction::Version(transaction.version as i32),
+        lock_time: bitcoin::absolute::LockTime::from_consensus(transaction.locktime),
+        input: inputs,
+        output: outputs,
+    })
+}
+
+/// Returns the output script of a simple script config (single-sig) paying to `pubkey`.
+fn simple_type_script_pubkey(
+    simple_type: i32,
+    pubkey: bitcoin::secp256k1::PublicKey,
+) -> Result<bitcoin::ScriptBuf, AddressError> {
+    let p2wpkh =
+        bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::CompressedPublicKey(pubkey).wpubkey_hash());
+    match pb::btc_script_config::SimpleType::try_from(simple_type) {
+        Ok(pb::btc_script_config::SimpleType::P2wpkh) => Ok(p2wpkh),
+        Ok(pb::btc_script_config::SimpleType::P2wpkhP2sh) => {
+            Ok(bitcoin::ScriptBuf::new_p2sh(&p2wpkh.script_hash()))
+        }
+        Ok(pb::btc_script_config::SimpleType::P2tr) => Ok(bitcoin::ScriptBuf::new_p2tr(
+            &bitcoin::secp256k1::Secp256k1::verification_only(),
+            pubkey.x_only_public_key().0,
+            None,
+        )),
+        Err(_) => Err(AddressError::UnsupportedScriptConfig),
+    }
+}
+
+/// Derives the address at `keypath` for the given script config on the host, the same way the
+/// BitBox does in `PairedBitBox::btc_address()`, so that addresses returned by the BitBox can be
+/// cross-checked.
+///
+/// For simple script configs (single-sig), `account_xpub` must be the xpub at the account keypath
+/// (the hardened prefix of `keypath`, e.g. `m/84'/0'/0'`), as returned by
+/// `PairedBitBox::btc_xpub()`. For multisig and policy configs, the xpubs contained in the script
+/// config are used. Deriving policy addresses requires the `miniscript` feature.
+pub fn derive_address(
+    coin: pb::BtcCoin,
+    keypath: &Keypath,
+    script_config: &pb::BtcScriptConfig,
+    account_xpub: Option<&Xpub>,
+) -> Result<String, AddressError> {
+    address_from_script_pubkey(
+        coin,
+        &derive_script_pubkey(keypath, script_config, account_xpub)?,
+    )
+}
+
+/// Output script of the address derived by `derive_address()`.
+fn derive_script_pubkey(
+    keypath: &Keypath,
+    script_config: &pb::BtcScriptConfig,
+    account_xpub: Option<&Xpub>,
+) -> Result<bitcoin::ScriptBuf, AddressError> {
+    let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+    let keypath_vec = keypath.to_vec();
+    let account_len = keypath.hardened_prefix().to_vec().len();
+    let relative: Vec<bitcoin::bip32::ChildNumber> = keypath_vec[account_len..]
+        .iter()
+        .map(|&el| el.into())
+        .collect();
+    if relative.len() != 2 {
+        return Err(AddressError::InvalidKeypath);
+    }
+    let script_pubkey = match &script_config.config {
+        Some(pb::btc_script_config::Config::SimpleType(simple_type)) => {
+            let pubkey = account_xpub
+                .ok_or(AddressError::MissingXpub)?
+                .derive_pub(&secp, &relative)
+                .map_err(|_| AddressError::InvalidXpub)?
+                .public_key;
+            simple_type_script_pubkey(*simple_type, pubkey)?
+        }
+        Some(pb::btc_script_config::Config::Multisig(multisig)) => {
+            let mut pubkeys = multisig
+                .xpubs
+                .iter()
+                .map(|xpub| {
+                    Ok(xpub_from_pb(xpub)?
+                        .derive_pub(&secp, &relative)
+                        .map_err(|_| AddressError::InvalidXpub)?
+                        .public_key
+                        .serialize())
+                })
+                .collect::<Result<Vec<_>, AddressError>>()?;
+            // The BitBox sorts the pubkeys, see BIP-67.
+            pubkeys.sort();
+            let mut builder = bitcoin::script::Builder::new().push_int(multisig.threshold as i64);
+            for pubkey in pubkeys.iter() {
+                builder = builder.push_slice(pubkey);
+            }
+            let witness_script = builder
+                .push_int(pubkeys.len() as i64)
+                .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+                .into_script();
+            let p2wsh = bitcoin::ScriptBuf::new_p2wsh(&witness_script.wscript_hash());
+            match pb::btc_script_config::multisig::ScriptType::try_from(multisig.script_type) {
+                Ok(pb::btc_script_config::multisig::ScriptType::P2wsh) => p2wsh,
+                Ok(pb::btc_script_config::multisig::ScriptType::P2wshP2sh) => {
+                    bitcoin::ScriptBuf::new_p2sh(&p2wsh.script_hash())
                 }
-                pb::btc_sign_next_response::Type::PrevtxOutput => {
-                    let prevtx: &PrevTx =
-                        transaction.inputs[next_response.index as usize].get_prev_tx()?;
-                    let prevtx_output: &PrevTxOutput =
-                        &prevtx.outputs[next_response.prev_index as usize];
-                    next_response = self
-                        .get_next_response_nested(pb::btc_request::Request::PrevtxOutput(
-                            pb::BtcPrevTxOutputRequest {
-                                value: prevtx_output.value,
-                                pubkey_script: prevtx_output.pubkey_script.clone(),
-                            },
-                        ))
-                        .await?;
+                Err(_) => return Err(AddressError::UnsupportedScriptConfig),
+            }
+        }
+        Some(pb::btc_script_config::Config::Policy(policy)) => {
+            policy_script_pubkey(policy, keypath)?
+        }
+        None => return Err(AddressError::UnsupportedScriptConfig),
+    };
+    Ok(script_pubkey)
+}
+
+pub(crate) fn xpub_from_pb(xpub: &pb::XPub) -> Result<Xpub, AddressError> {
+    Ok(Xpub {
+        network: bitcoin::NetworkKind::Main,
+        depth: match xpub.depth.as_slice() {
+            &[depth] => depth,
+            _ => return Err(AddressError::InvalidXpub),
+        },
+        parent_fingerprint: <[u8; 4]>::try_from(xpub.parent_fingerprint.as_slice())
+            .map_err(|_| AddressError::InvalidXpub)?
+            .into(),
+        child_number: xpub.child_num.into(),
+        public_key: bitcoin::secp256k1::PublicKey::from_slice(&xpub.public_key)
+            .map_err(|_| AddressError::InvalidXpub)?,
+        chain_code: <[u8; 32]>::try_from(xpub.chain_code.as_slice())
+            .map_err(|_| AddressError::InvalidXpub)?
+            .into(),
+    })
+}
+
+/// Key placeholder of a BIP-388 policy template, e.g. `@0/<0;1>/*`.
+#[cfg(feature = "miniscript")]
+struct PolicyPlaceholder {
+    /// Position of the placeholder in the template.
+    range: std::ops::Range<usize>,
+    key_index: usize,
+    receive: u32,
+    change: u32,
+}
+
+#[cfg(feature = "miniscript")]
+fn policy_placeholders(template: &str) -> Result<Vec<PolicyPlaceholder>, AddressError> {
+    let invalid = || AddressError::Policy("invalid key placeholder".into());
+    let mut placeholders = Vec::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find('@') {
+        let start = template.len() - rest.len() + pos;
+        let after = &rest[pos + 1..];
+        let digits = after.chars().take_while(char::is_ascii_digit).count();
+        let index: usize = after[..digits].parse().map_err(|_| invalid())?;
+        let after = &after[digits..];
+        let (receive, change, len) = if after.starts_with("/**") {
+            (0, 1, 3)
+        } else {
+            let end = after.find(">/*").ok_or_else(invalid)?;
+            let (receive, change) = after[..end]
+                .strip_prefix("/<")
+                .and_then(|multipath| multipath.split_once(';'))
+                .ok_or_else(invalid)?;
+            (
+                receive.parse().map_err(|_| invalid())?,
+                change.parse().map_err(|_| invalid())?,
+                end + 3,
+            )
+        };
+        let end = start + 1 + digits + len;
+        placeholders.push(PolicyPlaceholder {
+            range: start..end,
+            key_index: index,
+            receive,
+            change,
+        });
+        rest = &template[end..];
+    }
+    Ok(placeholders)
+}
+
+#[cfg(feature = "miniscript")]
+fn policy_script_pubkey(
+    policy: &pb::btc_script_config::Policy,
+    keypath: &Keypath,
+) -> Result<bitcoin::ScriptBuf, AddressError> {
+    let keypath = keypath.to_vec();
+    let (account, [change, address_index]) = keypath.split_at(keypath.len() - 2) else {
+        return Err(AddressError::InvalidKeypath);
+    };
+    let placeholders = policy_placeholders(&policy.policy)?;
+    // Our key is the one at the account keypath. The change element of the keypath determines
+    // whether the receive or change derivation is used for all keys.
+    let is_change = placeholders
+        .iter()
+        .find_map(|placeholder| {
+            let key = policy.keys.get(placeholder.key_index)?;
+            if key.keypath != account {
+                return None;
+            }
+            match *change {
+                c if c == placeholder.receive => Some(false),
+                c if c == placeholder.change => Some(true),
+                _ => None,
+            }
+        })
+        .ok_or(AddressError::InvalidKeypath)?;
+
+    let mut descriptor = String::new();
+    let mut last_end = 0;
+    for placeholder in placeholders {
+        let xpub = policy
+            .keys
+            .get(placeholder.key_index)
+            .and_then(|key| key.xpub.as_ref())
+            .ok_or(AddressError::MissingXpub)?;
+        descriptor.push_str(&policy.policy[last_end..placeholder.range.start]);
+        descriptor.push_str(&format!(
+            "{}/{}/{}",
+            xpub_from_pb(xpub)?,
+            if is_change {
+                placeholder.change
+            } else {
+                placeholder.receive
+            },
+            address_index,
+        ));
+        last_end = placeholder.range.end;
+    }
+    descriptor.push_str(&policy.policy[last_end..]);
+
+    let descriptor: miniscript::Descriptor<miniscript::DescriptorPublicKey> = descriptor
+        .parse()
+        .map_err(|err: miniscript::Error| AddressError::Policy(err.to_string()))?;
+    Ok(descriptor
+        .at_derivation_index(0)
+        .map_err(|err| AddressError::Policy(err.to_string()))?
+        .script_pubkey())
+}
+
+#[cfg(not(feature = "miniscript"))]
+fn policy_script_pubkey(
+    _policy: &pb::btc_script_config::Policy,
+    _keypath: &Keypath,
+) -> Result<bitcoin::ScriptBuf, AddressError> {
+    Err(AddressError::Policy(
+        "enable the `miniscript` feature to derive policy addresses".into(),
+    ))
+}
+
+/// Verifies a signature returned by `PairedBitBox::btc_sign_message()` on the host: the pubkey
+/// is recovered from `signature.electrum_sig65` and the address it corresponds to under the simple
+/// script config `script_config` must be equal to `address`.
+pub fn verify_message_signature(
+    coin: pb::BtcCoin,
+    script_config: &pb::BtcScriptConfig,
+    msg: &[u8],
+    signature: &SignMessageSignature,
+    address: &str,
+) -> Result<(), Error> {
+    use bitcoin::consensus::Encodable;
+    use bitcoin::hashes::{Hash, HashEngine};
+
+    let simple_type = match script_config.config {
+        Some(pb::btc_script_config::Config::SimpleType(simple_type)) => simple_type,
+        _ => return Err(AddressError::UnsupportedScriptConfig.into()),
+    };
+    let signature = bitcoin::sign_message::MessageSignature::from_slice(&signature.electrum_sig65)
+        .map_err(|_| Error::InvalidSignature)?;
+    if !signature.compressed {
+        return Err(Error::SignatureVerification(
+            "segwit signatures must use a compressed pubkey".into(),
+        ));
+    }
+
+    let mut engine = bitcoin::hashes::sha256d::Hash::engine();
+    engine.input(bitcoin::sign_message::BITCOIN_SIGNED_MSG_PREFIX);
+    bitcoin::VarInt::from(msg.len())
+        .consensus_encode(&mut engine)
+        .map_err(|_| Error::Unknown)?;
+    engine.input(msg);
+    let msg_hash = bitcoin::hashes::sha256d::Hash::from_engine(engine);
+
+    let pubkey = signature
+        .recover_pubkey(
+            &bitcoin::secp256k1::Secp256k1::verification_only(),
+            msg_hash,
+        )
+        .map_err(|_| Error::InvalidSignature)?;
+    let recovered_address =
+        address_from_script_pubkey(coin, &simple_type_script_pubkey(simple_type, pubkey.inner)?)?;
+    if recovered_address != address {
+        return Err(Error::SignatureVerification(format!(
+            "the signature was made by the key of {}, expected {}",
+            recovered_address, address
+        )));
+    }
+    Ok(())
+}
+
+fn address_from_script_pubkey(
+    coin: pb::BtcCoin,
+    script_pubkey: &bitcoin::Script,
+) -> Result<String, AddressError> {
+    let (hrp, p2pkh_version, p2sh_version) = match coin {
+        pb::BtcCoin::Btc => ("bc", 0x00, 0x05),
+        pb::BtcCoin::Tbtc => ("tb", 0x6f, 0xc4),
+        pb::BtcCoin::Rbtc => ("bcrt", 0x6f, 0xc4),
+        pb::BtcCoin::Ltc => ("ltc", 0x30, 0x32),
+        pb::BtcCoin::Tltc => ("tltc", 0x6f, 0x3a),
+    };
+    if script_pubkey.is_p2pkh() {
+        let mut payload = vec![p2pkh_version];
+        payload.extend_from_slice(&script_pubkey.as_bytes()[3..23]);
+        return Ok(bitcoin::base58::encode_check(&payload));
+    }
+    if script_pubkey.is_p2sh() {
+        let mut payload = vec![p2sh_version];
+        payload.extend_from_slice(&script_pubkey.as_bytes()[2..22]);
+        return Ok(bitcoin::base58::encode_check(&payload));
+    }
+    match script_pubkey.witness_version() {
+        Some(version) => bech32::segwit::encode(
+            bech32::Hrp::parse_unchecked(hrp),
+            bech32::Fe32::try_from(version.to_num()).unwrap(),
+            &script_pubkey.as_bytes()[2..],
+        )
+        .map_err(|_| AddressError::UnsupportedScriptConfig),
+        None => Err(AddressError::UnsupportedScriptConfig),
+    }
+}
+
+fn is_taproot_simple(script_config: &pb::BtcScriptConfigWithKeypath) -> bool {
+    matches!(
+        script_config.script_config.as_ref(),
+        Some(pb::BtcScriptConfig {
+            config: Some(pb::btc_script_config::Config::SimpleType(simple_type)),
+        }) if *simple_type == pb::btc_script_config::SimpleType::P2tr as i32
+    )
+}
+
+fn is_taproot_policy(script_config: &pb::BtcScriptConfigWithKeypath) -> bool {
+    matches!(
+        script_config.script_config.as_ref(),
+        Some(pb::BtcScriptConfig {
+            config: Some(pb::btc_script_config::Config::Policy(policy)),
+        })  if policy.policy.as_str().starts_with("tr("),
+    )
+}
+
+fn is_schnorr(script_config: &pb::BtcScriptConfigWithKeypath) -> bool {
+    is_taproot_simple(script_config) | is_taproot_policy(script_config)
+}
+
+impl<R: Runtime> PairedBitBox<R> {
+    /// Retrieves an xpub. For non-standard keypaths, a warning is displayed on the BitBox even if
+    /// `display` is false.
+    pub async fn btc_xpub(
+        &self,
+        coin: pb::BtcCoin,
+        keypath: &Keypath,
+        xpub_type: pb::btc_pub_request::XPubType,
+        display: bool,
+    ) -> Result<String, Error> {
+        match self
+            .query_proto(Request::BtcPub(pb::BtcPubRequest {
+                coin: coin as _,
+                keypath: keypath.to_vec(),
+                display,
+                output: Some(pb::btc_pub_request::Output::XpubType(xpub_type as _)),
+            }))
+            .await?
+        {
+            Response::Pub(pb::PubResponse { r#pub }) => Ok(r#pub),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Retrieves multiple xpubs at once. Only standard keypaths are allowed.
+    /// On firmware <v9.24.0, this falls back to calling `btc_xpub()` for each keypath.
+    pub async fn btc_xpubs(
+        &self,
+        coin: pb::BtcCoin,
+        keypaths: &[Keypath],
+        xpub_type: pb::btc_xpubs_request::XPubType,
+    ) -> Result<Vec<String>, Error> {
+        if self.validate_version(">=9.24.0").is_err() {
+            // Fallback to fetching them one-by-one on older firmware.
+            let mut xpubs = Vec::<String>::with_capacity(keypaths.len());
+            for keypath in keypaths {
+                let converted_xpub_type = match xpub_type {
+                    pb::btc_xpubs_request::XPubType::Unknown => return Err(Error::Unknown),
+                    pb::btc_xpubs_request::XPubType::Tpub => pb::btc_pub_request::XPubType::Tpub,
+                    pb::btc_xpubs_request::XPubType::Xpub => pb::btc_pub_request::XPubType::Xpub,
+                };
+                let xpub = self
+                    .btc_xpub(coin, keypath, converted_xpub_type, false)
+                    .await?;
+                xpubs.push(xpub);
+            }
+            return Ok(xpubs);
+        }
+        match self
+            .query_proto_btc(pb::btc_request::Request::Xpubs(pb::BtcXpubsRequest {
+                coin: coin as _,
+                xpub_type: xpub_type as _,
+                keypaths: keypaths.iter().map(|kp| kp.into()).collect(),
+            }))
+            .await?
+        {
+            pb::btc_response::Response::Pubs(pb::PubsResponse { pubs }) => Ok(pubs),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Fetches the xpub of the account (the hardened prefix) of `keypath`.
+    pub(crate) async fn btc_account_xpub(
+        &self,
+        coin: pb::BtcCoin,
+        keypath: &Keypath,
+    ) -> Result<Xpub, Error> {
+        let xpub_type = match coin {
+            pb::BtcCoin::Btc | pb::BtcCoin::Ltc => pb::btc_pub_request::XPubType::Xpub,
+            _ => pb::btc_pub_request::XPubType::Tpub,
+        };
+        let xpub = self
+            .btc_xpub(coin, &keypath.hardened_prefix(), xpub_type, false)
+            .await?;
+        xpub.parse::<Xpub>().map_err(|_| Error::UnexpectedResponse)
+    }
+
+    /// Discovers the used single-sig accounts of the given script types, e.g. to restore a wallet.
+    ///
+    /// Following BIP-44 account discovery, the accounts `m/purpose'/coin'/account'` (purpose 49
+    /// for p2wpkh-p2sh, 84 for p2wpkh and 86 for p2tr) are scanned in increasing order until an
+    /// unused account is found for each script type. The account xpubs are fetched in one
+    /// `btc_xpubs()` call per account index, the addresses are derived on the host and looked up
+    /// in `source`, stopping after `gap_limit` consecutive unused addresses (see
+    /// `discovery::DEFAULT_GAP_LIMIT`). The gap limit may be at most `discovery::MAX_GAP_LIMIT`.
+    pub async fn btc_discover_accounts(
+        &self,
+        coin: pb::BtcCoin,
+        simple_types: &[pb::btc_script_config::SimpleType],
+        source: &dyn crate::discovery::UtxoSource,
+        gap_limit: u32,
+    ) -> Result<Vec<crate::discovery::DiscoveredAccount>, Error> {
+        use crate::keypath::HARDENED;
+        use pb::btc_script_config::SimpleType;
+        crate::discovery::check_gap_limit(gap_limit)?;
+        let (coin_type, xpub_type) = match coin {
+            pb::BtcCoin::Btc => (0, pb::btc_xpubs_request::XPubType::Xpub),
+            pb::BtcCoin::Ltc => (2, pb::btc_xpubs_request::XPubType::Xpub),
+            pb::BtcCoin::Tbtc | pb::BtcCoin::Rbtc | pb::BtcCoin::Tltc => {
+                (1, pb::btc_xpubs_request::XPubType::Tpub)
+            }
+        };
+        let mut accounts = Vec::new();
+        let mut pending: Vec<SimpleType> = simple_types.to_vec();
+        let mut account: u32 = 0;
+        while !pending.is_empty() {
+            let keypaths: Vec<Keypath> = pending
+                .iter()
+                .map(|simple_type| {
+                    let purpose = match simple_type {
+                        SimpleType::P2wpkhP2sh => 49,
+                        SimpleType::P2wpkh => 84,
+                        SimpleType::P2tr => 86,
+                    };
+                    Keypath::from(
+                        &[purpose + HARDENED, coin_type + HARDENED, account + HARDENED][..],
+                    )
+                })
+                .collect();
+            let xpubs = self.btc_xpubs(coin, &keypaths, xpub_type).await?;
+            if xpubs.len() != keypaths.len() {
+                return Err(Error::UnexpectedResponse);
+            }
+            let mut used = Vec::new();
+            for ((simple_type, keypath), xpub) in pending.iter().zip(&keypaths).zip(&xpubs) {
+                let xpub: Xpub = xpub.parse().map_err(|_| AddressError::InvalidXpub)?;
+                if let Some(discovered) = crate::discovery::scan_account(
+                    coin,
+                    keypath,
+                    &make_script_config_simple(*simple_type),
+                    &xpub,
+                    source,
+                    gap_limit,
+                )
+                .await?
+                {
+                    accounts.push(discovered);
+                    used.push(*simple_type);
                 }
-                pb::btc_sign_next_response::Type::Output => {
-                    let tx_output: &TxOutput = &transaction.outputs[next_response.index as usize];
-                    let request: Request = match tx_output {
-                        TxOutput::Internal(output) => {
-                            Request::BtcSignOutput(pb::BtcSignOutputRequest {
-                                ours: true,
-                                value: output.value,
-                                keypath: output.keypath.to_vec(),
-                                script_config_index: output.script_config_index,
-                                ..Default::default()
-                            })
+            }
+            pending = used;
+            account += 1;
+        }
+        Ok(accounts)
+    }
+
+    /// Retrieves a Bitcoin address at the provided keypath.
+    ///
+    /// For the simple script configs (single-sig), the keypath must follow the
+    /// BIP44/BIP49/BIP84/BIP86 conventions.
+    pub async fn btc_address(
+        &self,
+        coin: pb::BtcCoin,
+        keypath: &Keypath,
+        script_config: &pb::BtcScriptConfig,
+        display: bool,
+    ) -> Result<String, Error> {
+        match self
+            .query_proto(Request::BtcPub(pb::BtcPubRequest {
+                coin: coin as _,
+                keypath: keypath.to_vec(),
+                display,
+                output: Some(pb::btc_pub_request::Output::ScriptConfig(
+                    script_config.clone(),
+                )),
+            }))
+            .await?
+        {
+            Response::Pub(pb::PubResponse { r#pub }) => Ok(r#pub),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Like `btc_address()`, but also derives the address on the host using `derive_address()`
+    /// and fails with `AddressError::Mismatch` if the BitBox and the host disagree. If `display`
+    /// is true, the address is only shown on the BitBox after it has been verified.
+    ///
+    /// For simple script configs (single-sig), the account xpub is fetched using `btc_xpub()`.
+    pub async fn btc_address_verified(
+        &self,
+        coin: pb::BtcCoin,
+        keypath: &Keypath,
+        script_config: &pb::BtcScriptConfig,
+        display: bool,
+    ) -> Result<String, Error> {
+        let account_xpub = match &script_config.config {
+            Some(pb::btc_script_config::Config::SimpleType(_)) => {
+                Some(self.btc_account_xpub(coin, keypath).await?)
+            }
+            _ => None,
+        };
+        let expected = derive_address(coin, keypath, script_config, account_xpub.as_ref())?;
+        let address = self
+            .btc_address(coin, keypath, script_config, display)
+            .await?;
+        if address != expected {
+            return Err(AddressError::Mismatch {
+                device: address,
+                host: expected,
+            }
+            .into());
+        }
+        Ok(address)
+    }
+
+    async fn query_proto_btc(
+        &self,
+        request: pb::btc_request::Request,
+    ) -> Result<pb::btc_response::Response, Error> {
+        match self
+            .query_proto(Request::Btc(pb::BtcRequest {
+                request: Some(request),
+            }))
+            .await?
+        {
+            Response::Btc(pb::BtcResponse {
+                response: Some(response),
+            }) => Ok(response),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    async fn get_next_response(&self, request: Request) -> Result<pb::BtcSignNextResponse, Error> {
+        match self.query_proto(request).await? {
+            Response::BtcSignNext(next_response) => Ok(next_response),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    async fn get_next_response_nested(
+        &self,
+        request: pb::btc_request::Request,
+    ) -> Result<pb::BtcSignNextResponse, Error> {
+        match self.query_proto_btc(request).await? {
+            pb::btc_response::Response::SignNext(next_response) => Ok(next_response),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Sign a Bitcoin transaction. Returns one 64 byte signature (compact serlization of the R and
+    /// S values) per input, and the output scripts generated for silent payment outputs.
+    pub async fn btc_sign(
+        &self,
+        coin: pb::BtcCoin,
+        transaction: &Transaction,
+        format_unit: pb::btc_sign_init_request::FormatUnit,
+    ) -> Result<SignResult, Error> {
+        self.validate_version(">=9.4.0")?; // anti-klepto since 9.4.0
+        if transaction.script_configs.iter().any(is_taproot_simple) {
+            self.validate_version(">=9.10.0")?; // taproot since 9.10.0
+        }
+        if transaction.outputs.iter().any(|output| {
+            matches!(
+                output,
+                TxOutput::External(tx_output)
+                    if tx_output.payload.output_type == pb::BtcOutputType::OpReturn
+            )
+        }) {
+            self.validate_version(">=9.24.0")?;
+        }
+        if !transaction.payment_requests.is_empty() {
+            self.validate_version(">=9.19.0")?;
+        }
+        if !transaction.output_script_configs.is_empty() {
+            self.validate_version(">=9.22.0")?;
+        }
+        transaction.validate(coin, self.product(), self.version())?;
+
+        let silent_payment_addresses = transaction
+            .outputs
+            .iter()
+            .map(|output| match output {
+                TxOutput::SilentPayment(output) => {
+                    crate::silentpayments::Address::decode(&output.address).map(Some)
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let contains_silent_payment_outputs = silent_payment_addresses.iter().any(Option::is_some);
+        let silent_payment_inputs = if contains_silent_payment_outputs {
+            self.validate_version(">=9.21.0")?;
+            transaction
+                .inputs
+                .iter()
+                .map(|input| {
+                    Ok(crate::silentpayments::Input {
+                        prev_out_hash: &input.prev_out_hash,
+                        prev_out_index: input.prev_out_index,
+                        pubkey: input
+                            .silent_payment_pubkey
+                            .ok_or(crate::silentpayments::Error::MissingInputPubkey)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, crate::silentpayments::Error>>()?
+        } else {
+            vec![]
+        };
+
+        let mut sigs: Vec<Vec<u8>> = Vec::new();
+        let mut generated_outputs = std::collections::BTreeMap::new();
+
+        let mut next_response = self
+            .get_next_response(Request::BtcSignInit(pb::BtcSignInitRequest {
+                coin: coin as _,
+                script_configs: transaction.script_configs.clone(),
+                output_script_configs: transaction.output_script_configs.clone(),
+                version: transaction.version,
+                num_inputs: transaction.inputs.len() as _,
+                num_outputs: transaction.outputs.len() as _,
+                locktime: transaction.locktime,
+                format_unit: format_unit as _,
+                contains_silent_payment_outputs,
+            }))
+            .await?;
+
+        let mut is_inputs_pass2 = false;
+        loop {
+            match pb::btc_sign_next_response::Type::try_from(next_response.r#type)
+                .map_err(|_| Error::UnexpectedResponse)?
+            {
+                pb::btc_sign_next_response::Type::Input => {
+                    let input_index: usize = next_response.index as _;
+                    let tx_input: &TxInput = &transaction.inputs[input_index];
+
+                    let input_is_schnorr = is_schnorr(
+                        &transaction.script_configs[tx_input.script_config_index as usize],
+                    );
+                    let perform_antiklepto = is_inputs_pass2 && !input_is_schnorr;
+                    let host_nonce = if perform_antiklepto {
+                        Some(crate::antiklepto::gen_host_nonce()?)
+                    } else {
+                        None
+                    };
+                    next_response = self
+                        .get_next_response(Request::BtcSignInput(pb::BtcSignInputRequest {
+                            prev_out_hash: tx_input.prev_out_hash.clone(),
+                            prev_out_index: tx_input.prev_out_index,
+                            prev_out_value: tx_input.prev_out_value,
+                            sequence: tx_input.sequence,
+                            keypath: tx_input.keypath.to_vec(),
+                            script_config_index: tx_input.script_config_index,
+                            host_nonce_commitment: host_nonce.as_ref().map(|host_nonce| {
+                                pb::AntiKleptoHostNonceCommitment {
+                                    commitment: crate::antiklepto::host_commit(host_nonce).to_vec(),
+                                }
+                            }),
+                        }))
+                        .await?;
+
+                    if let Some(host_nonce) = host_nonce {
+                        if next_response.r#type
+                            != pb::btc_sign_next_response::Type::HostNonce as i32
+                        {
+                            return Err(Error::UnexpectedResponse);
+                        }
+                        if let Some(pb::AntiKleptoSignerCommitment { commitment }) =
+                            next_response.anti_klepto_signer_commitment
+                        {
+                            next_response = self
+                                .get_next_response_nested(
+                                    pb::btc_request::Request::AntikleptoSignature(
+                                        pb::AntiKleptoSignatureRequest {
+                                            host_nonce: host_nonce.to_vec(),
+                                        },
+                                    ),
+                                )
+                                .await?;
+                            if !next_response.has_signature {
+                                return Err(Error::UnexpectedResponse);
+                            }
+                            crate::antiklepto::verify_ecdsa(
+                                &host_nonce,
+                                &commitment,
+                                &next_response.signature,
+                            )?
+                        } else {
+                            return Err(Error::UnexpectedResponse);
+                        }
+                    }
+
+                    if is_inputs_pass2 {
+                        if !next_response.has_signature {
+                            return Err(Error::UnexpectedResponse);
+                        }
+                        sigs.push(next_response.signature.clone());
+                    }
+                    if input_index == transaction.inputs.len() - 1 {
+                        is_inputs_pass2 = true
+                    }
+                }
+                pb::btc_sign_next_response::Type::PrevtxInit => {
+                    let prevtx: &PrevTx =
+                        transaction.inputs[next_response.index as usize].get_prev_tx()?;
+                    next_response = self
+                        .get_next_response_nested(pb::btc_request::Request::PrevtxInit(
+                            pb::BtcPrevTxInitRequest {
+                                version: prevtx.version,
+                                num_inputs: prevtx.inputs.len() as _,
+                                num_outputs: prevtx.outputs.len() as _,
+                                locktime: prevtx.locktime,
+                            },
+                        ))
+                        .await?;
+                }
+                pb::btc_sign_next_response::Type::PrevtxInput => {
+                    let prevtx: &PrevTx =
+                        transaction.inputs[next_response.index as usize].get_prev_tx()?;
+                    let prevtx_input: &PrevTxInput =
+                        &prevtx.inputs[next_response.prev_index as usize];
+                    next_response = self
+                        .get_next_response_nested(pb::btc_request::Request::PrevtxInput(
+                            pb::BtcPrevTxInputRequest {
+                                prev_out_hash: prevtx_input.prev_out_hash.clone(),
+                                prev_out_index: prevtx_input.prev_out_index,
+                                signature_script: prevtx_input.signature_script.clone(),
+                                sequence: prevtx_input.sequence,
+                            },
+                        ))
+                        .await?;
+                }
+                pb::btc_sign_next_response::Type::PrevtxOutput => {
+                    let prevtx: &PrevTx =
+                        transaction.inputs[next_response.index as usize].get_prev_tx()?;
+                    let prevtx_output: &PrevTxOutput =
+                        &prevtx.outputs[next_response.prev_index as usize];
+                    next_response = self
+                        .get_next_response_nested(pb::btc_request::Request::PrevtxOutput(
+                            pb::BtcPrevTxOutputRequest {
+                                value: prevtx_output.value,
+                                pubkey_script: prevtx_output.pubkey_script.clone(),
+                            },
+                        ))
+                        .await?;
+                }
+                pb::btc_sign_next_response::Type::Output => {
+                    let output_index = next_response.index as usize;
+                    let request = transaction.sign_output_request(output_index);
+                    next_response = self.get_next_response(request).await?;
+
+                    if let Some(address) = &silent_payment_addresses[output_index] {
+                        // Number of previous silent payment outputs with the same scan pubkey.
+                        let k = silent_payment_addresses[..output_index]
+                            .iter()
+                            .flatten()
+                            .filter(|a| a.scan_pubkey == address.scan_pubkey)
+                            .count();
+                        crate::silentpayments::verify_output(
+                            &silent_payment_inputs,
+                            address,
+                            k as _,
+                            &next_response.generated_output_pkscript,
+                            &next_response.silent_payment_dleq_proof,
+                        )?;
+                        generated_outputs.insert(
+                            output_index,
+                            next_response.generated_output_pkscript.clone(),
+                        );
+                    }
+                }
+                pb::btc_sign_next_response::Type::PaymentRequest => {
+                    next_response = self
+                        .get_next_response_nested(
+                            transaction.payment_request_request(next_response.index)?,
+                        )
+                        .await?;
+                }
+                pb::btc_sign_next_response::Type::Done => break,
+                pb::btc_sign_next_response::Type::HostNonce => {
+                    return Err(Error::UnexpectedResponse);
+                }
+            }
+        }
+        Ok(SignResult {
+            signatures: sigs,
+            generated_outputs,
+        })
+    }
+
+    /// Sign a PSBT.
+    ///
+    /// If `force_script_config` is None, we attempt to infer the involved script configs. For the
+    /// simple script config (single sig), we infer the script config from the involved redeem
+    /// scripts and provided derviation paths.
+    ///
+    /// For segwit multisig (p2wsh and p2wsh-p2sh), the script config is reconstructed from the
+    /// witness script. This requires the key origin infos of all cosigner pubkeys in the PSBT
+    /// inputs/outputs and the cosigner xpubs in the PSBT global xpubs. The multisig script config
+    /// must already be registered on the device.
+    ///
+    /// Policy configs are currently not inferred and must be provided using
+    /// `force_script_config`, unless a registry is attached (see `with_registry()`): if
+    /// `force_script_config` is None, the multisig and policy script configs recorded in the
+    /// registry are checked first, and the one matching all inputs of the PSBT is used. Matching
+    /// policies requires the `miniscript` feature.
+    ///
+    /// The signatures are added to the PSBT inputs, but the inputs are not finalized. Use
+    /// `finalize_psbt()` and `extract_tx()` to get the final transaction.
+    pub async fn btc_sign_psbt(
+        &self,
+        coin: pb::BtcCoin,
+        psbt: &mut bitcoin::psbt::Psbt,
+        force_script_config: Option<pb::BtcScriptConfigWithKeypath>,
+        format_unit: pb::btc_sign_init_request::FormatUnit,
+    ) -> Result<(), Error> {
+        // since v9.15.0, the BitBox02 accepts "internal" outputs (ones sent to the BitBox02 with
+        // the keypath) even if the keypath is not a change keypath. PSBTs often contain the key
+        // origin info in outputs even in regular send-to-self outputs.
+        self.validate_version(">=9.15.0")?;
+
+        let root_fingerprint = self.root_fingerprint().await?;
+        let our_root_fingerprint = hex::decode(&root_fingerprint).unwrap();
+        let is_script_config_forced = force_script_config.is_some();
+        let force_script_config = match (force_script_config, &self.registry) {
+            (Some(script_config), _) => Some(script_config),
+            // The registry is only a hint. If it cannot be read, the script config is inferred
+            // from the PSBT as without a registry.
+            (None, Some(registry)) => registry.read_registry().ok().and_then(|data| {
+                registered_script_config_for_psbt(
+                    coin,
+                    &our_root_fingerprint,
+                    psbt,
+                    data.script_configs(&root_fingerprint),
+                )
+            }),
+            (None, None) => None,
+        };
+        let (transaction, our_keys) =
+            Transaction::from_psbt(coin, &our_root_fingerprint, psbt, force_script_config)?;
+        // Script configs inferred from the PSBT or found in the registry must be registered. The
+        // registry can be outdated, e.g. if the BitBox was reset in the meantime.
+        if !is_script_config_forced {
+            for script_config in transaction.script_configs.iter() {
+                let keypath_account: Option<Keypath> = match &script_config.script_config {
+                    Some(pb::BtcScriptConfig {
+                        config: Some(pb::btc_script_config::Config::Multisig(_)),
+                    }) => Some(script_config.keypath.as_slice().into()),
+                    // Policies are registered without keypath.
+                    Some(pb::BtcScriptConfig {
+                        config: Some(pb::btc_script_config::Config::Policy(_)),
+                    }) => None,
+                    _ => continue,
+                };
+                if !self
+                    .btc_is_script_config_registered(
+                        coin,
+                        script_config.script_config.as_ref().unwrap(),
+                        keypath_account.as_ref(),
+                    )
+                    .await?
+                {
+                    return Err(PsbtError::ScriptConfigNotRegistered.into());
+                }
+            }
+        }
+        let SignResult {
+            signatures,
+            generated_outputs,
+        } = self.btc_sign(coin, &transaction, format_unit).await?;
+        for (output_index, pkscript) in generated_outputs {
+            psbt.unsigned_tx.output[output_index].script_pubkey = pkscript.into();
+        }
+        for (psbt_input, (signature, our_key)) in
+            psbt.inputs.iter_mut().zip(signatures.iter().zip(our_keys))
+        {
+            match our_key {
+                OurKey::Segwit(pubkey, _) => {
+                    psbt_input.partial_sigs.insert(
+                        bitcoin::PublicKey::new(pubkey),
+                        bitcoin::ecdsa::Signature {
+                            signature: bitcoin::secp256k1::ecdsa::Signature::from_compact(
+                                signature,
+                            )
+                            .map_err(|_| Error::InvalidSignature)?,
+                            sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+                        },
+                    );
+                }
+                OurKey::TaprootInternal(_) => {
+                    psbt_input.tap_key_sig = Some(
+                        bitcoin::taproot::Signature::from_slice(signature)
+                            .map_err(|_| Error::InvalidSignature)?,
+                    );
+                }
+                OurKey::TaprootScript(xonly, leaf_hash, _) => {
+                    let sig = bitcoin::taproot::Signature::from_slice(signature)
+                        .map_err(|_| Error::InvalidSignature)?;
+                    psbt_input.tap_script_sigs.insert((xonly, leaf_hash), sig);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sign a version 2 PSBT (BIP-370).
+    ///
+    /// The inputs and outputs are mapped to the transaction to be signed the same way as in
+    /// `btc_sign_psbt()`, and the signatures are added to the PSBTv2 inputs.
+    pub async fn btc_sign_psbt_v2(
+        &self,
+        coin: pb::BtcCoin,
+        psbt: &mut crate::psbt_v2::PsbtV2,
+        force_script_config: Option<pb::BtcScriptConfigWithKeypath>,
+        format_unit: pb::btc_sign_init_request::FormatUnit,
+    ) -> Result<(), Error> {
+        let mut psbt_v0 = psbt.to_psbt_v0()?;
+        self.btc_sign_psbt(coin, &mut psbt_v0, force_script_config, format_unit)
+            .await?;
+        psbt.update_from_psbt_v0(psbt_v0);
+        Ok(())
+    }
+
+    /// Sign a message.
+    ///
+    /// Use `verify_message_signature()` to check the signature against the expected address.
+    pub async fn btc_sign_message(
+        &self,
+        coin: pb::BtcCoin,
+        script_config: pb::BtcScriptConfigWithKeypath,
+        msg: &[u8],
+    ) -> Result<SignMessageSignature, Error> {
+        self.validate_version(">=9.5.0")?;
+
+        let host_nonce = crate::antiklepto::gen_host_nonce()?;
+        let request = pb::BtcSignMessageRequest {
+            coin: coin as _,
+            script_config: Some(script_config),
+            msg: msg.to_vec(),
+            host_nonce_commitment: Some(pb::AntiKleptoHostNonceCommitment {
+                commitment: crate::antiklepto::host_commit(&host_nonce).to_vec(),
+            }),
+        };
+
+        let response = self
+            .query_proto_btc(pb::btc_request::Request::SignMessage(request))
+            .await?;
+        let signer_commitment = match response {
+            pb::btc_response::Response::AntikleptoSignerCommitment(
+                pb::AntiKleptoSignerCommitment { commitment },
+            ) => commitment,
+            _ => return Err(Error::UnexpectedResponse),
+        };
+
+        let request = pb::AntiKleptoSignatureRequest {
+            host_nonce: host_nonce.to_vec(),
+        };
+
+        let response = self
+            .query_proto_btc(pb::btc_request::Request::AntikleptoSignature(request))
+            .await?;
+        let signature = match response {
+            pb::btc_response::Response::SignMessage(pb::BtcSignMessageResponse { signature }) => {
+                signature
+            }
+            _ => return Err(Error::UnexpectedResponse),
+        };
+        crate::antiklepto::verify_ecdsa(&host_nonce, &signer_commitment, &signature)?;
+
+        let sig = signature[..64].to_vec();
+        let recid = signature[64];
+        let compressed: u8 = 4; // BitBox02 uses only compressed pubkeys
+        let sig65: u8 = 27 + compressed + recid;
+        let mut electrum_sig65 = vec![sig65];
+        electrum_sig65.extend_from_slice(&sig);
+        Ok(SignMessageSignature {
+            sig,
+            recid,
+            electrum_sig65,
+        })
+    }
+
+    /// Like `btc_sign_message()`, but also fetches the address of the keypath from the BitBox
+    /// (without displaying it) and checks the signature against it using
+    /// `verify_message_signature()`.
+    pub async fn btc_sign_message_verified(
+        &self,
+        coin: pb::BtcCoin,
+        script_config: pb::BtcScriptConfigWithKeypath,
+        msg: &[u8],
+    ) -> Result<SignMessageSignature, Error> {
+        let config = script_config.script_config.clone().unwrap_or_default();
+        let address = self
+            .btc_address(
+                coin,
+                &script_config.keypath.as_slice().into(),
+                &config,
+                false,
+            )
+            .await?;
+        let signature = self.btc_sign_message(coin, script_config, msg).await?;
+        verify_message_signature(coin, &config, msg, &signature, &address)?;
+        Ok(signature)
+    }
+
+    /// Signs a message according to BIP-322 using `btc_sign()`, returning the base64 encoded
+    /// signature in the requested format. Only p2wpkh and p2tr simple script configs are
+    /// supported.
+    ///
+    /// The virtual `to_sign` transaction is signed like a regular transaction, so the user
+    /// confirms it on the BitBox as a transaction with a zero amount. The signature is verified on
+    /// the host before it is returned, so a signature over a different transaction results in an
+    /// error instead of an invalid signature.
+    ///
+    /// BIP-322 requires a version 0 transaction with a bare OP_RETURN output, while the firmware
+    /// currently only signs version 1 and 2 transactions and encodes empty OP_RETURN outputs as
+    /// `OP_RETURN OP_0`. Until a firmware release supports this, `Error::Version` is returned.
+    pub async fn btc_sign_message_bip322(
+        &self,
+        coin: pb::BtcCoin,
+        script_config: pb::BtcScriptConfigWithKeypath,
+        msg: &[u8],
+        format: crate::bip322::SignatureFormat,
+    ) -> Result<String, Error> {
+        if !FIRMWARE_SUPPORTS_BIP322 {
+            return Err(Error::Version("with BIP-322 support"));
+        }
+        let keypath = Keypath::from(script_config.keypath.as_slice());
+        let config = script_config.script_config.clone().unwrap_or_default();
+        let simple_type = match config.config {
+            Some(pb::btc_script_config::Config::SimpleType(simple_type))
+                if simple_type == pb::btc_script_config::SimpleType::P2wpkh as i32
+                    || simple_type == pb::btc_script_config::SimpleType::P2tr as i32 =>
+            {
+                simple_type
+            }
+            _ => return Err(crate::bip322::Error::UnsupportedScript.into()),
+        };
+        let account_xpub = self.btc_account_xpub(coin, &keypath).await?;
+        let script_pubkey = derive_script_pubkey(&keypath, &config, Some(&account_xpub))?;
+        let to_spend = crate::bip322::to_spend(&script_pubkey, msg);
+        let mut to_sign = crate::bip322::to_sign(&to_spend);
+
+        let transaction = Transaction {
+            script_configs: vec![script_config],
+            output_script_configs: vec![],
+            version: to_sign.version.0 as _,
+            inputs: vec![TxInput {
+                prev_out_hash: (to_spend.compute_txid().as_ref() as &[u8]).to_vec(),
+                prev_out_index: 0,
+                prev_out_value: 0,
+                sequence: to_sign.input[0].sequence.to_consensus_u32(),
+                keypath: keypath.clone(),
+                script_config_index: 0,
+                prev_tx: Some((&to_spend).into()),
+                silent_payment_pubkey: None,
+            }],
+            outputs: vec![TxOutput::External(TxExternalOutput {
+                payload: Payload {
+                    data: vec![],
+                    output_type: pb::BtcOutputType::OpReturn,
+                },
+                value: 0,
+                payment_request_index: None,
+            })],
+            locktime: to_sign.lock_time.to_consensus_u32(),
+            payment_requests: vec![],
+        };
+        let SignResult { signatures, .. } = self
+            .btc_sign(
+                coin,
+                &transaction,
+                pb::btc_sign_init_request::FormatUnit::Default,
+            )
+            .await?;
+        let signature = signatures.first().ok_or(Error::UnexpectedResponse)?;
+
+        to_sign.input[0].witness = if simple_type == pb::btc_script_config::SimpleType::P2tr as i32
+        {
+            bitcoin::Witness::p2tr_key_spend(
+                &bitcoin::taproot::Signature::from_slice(signature)
+                    .map_err(|_| Error::InvalidSignature)?,
+            )
+        } else {
+            let relative = &keypath.to_vec()[keypath.hardened_prefix().to_vec().len()..];
+            let pubkey = account_xpub
+                .derive_pub(
+                    &bitcoin::secp256k1::Secp256k1::verification_only(),
+                    &relative
+                        .iter()
+                        .map(|&el| el.into())
+                        .collect::<Vec<bitcoin::bip32::ChildNumber>>(),
+                )
+                .map_err(|_| Error::UnexpectedResponse)?
+                .public_key;
+            bitcoin::Witness::p2wpkh(
+                &bitcoin::ecdsa::Signature {
+                    signature: bitcoin::secp256k1::ecdsa::Signature::from_compact(signature)
+                        .map_err(|_| Error::InvalidSignature)?,
+                    sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+                },
+                &pubkey,
+            )
+        };
+        crate::bip322::verify_to_sign(&to_spend, &to_sign)?;
+        Ok(crate::bip322::encode_signature(&to_sign, format))
+    }
+
+    /// Before a multisig or policy script config can be used to display receive addresses or sign
+    /// transactions, it must be registered on the device. This function checks if the script config
+    /// was already registered.
+    ///
+    /// `keypath_account` must be set if the script config is multisig, and can be `None` if it is a
+    /// policy.
+    pub async fn btc_is_script_config_registered(
+        &self,
+        coin: pb::BtcCoin,
+        script_config: &pb::BtcScriptConfig,
+        keypath_account: Option<&Keypath>,
+    ) -> Result<bool, Error> {
+        match self
+            .query_proto_btc(pb::btc_request::Request::IsScriptConfigRegistered(
+                pb::BtcIsScriptConfigRegisteredRequest {
+                    registration: Some(pb::BtcScriptConfigRegistration {
+                        coin: coin as _,
+                        script_config: Some(script_config.clone()),
+                        keypath: keypath_account.map_or(vec![], |kp| kp.to_vec()),
+                    }),
+                },
+            ))
+            .await?
+        {
+            pb::btc_response::Response::IsScriptConfigRegistered(response) => {
+                Ok(response.is_registered)
+            }
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Before a multisig or policy script config can be used to display receive addresses or sign
+    /// transcations, it must be registered on the device.
+    ///
+    /// If no name is provided, the user will be asked to enter it on the device instead.  If
+    /// provided, it must be non-empty, smaller or equal to 30 chars, consist only of printable
+    /// ASCII characters, and contain no whitespace other than spaces.
+    ///
+    ///
+    /// `keypath_account` must be set if the script config is multisig, and can be `None` if it is a
+    /// policy.
+    ///
+    /// If a registry is attached (see `with_registry()`), the script config is recorded in it. If
+    /// recording it fails, `Error::Registry` is returned, but the script config is registered on
+    /// the device regardless.
+    pub async fn btc_register_script_config(
+        &self,
+        coin: pb::BtcCoin,
+        script_config: &pb::BtcScriptConfig,
+        keypath_account: Option<&Keypath>,
+        xpub_type: pb::btc_register_script_config_request::XPubType,
+        name: Option<&str>,
+    ) -> Result<(), Error> {
+        // Fetched before registering, so that a failure to fetch it cannot hide a successful
+        // registration.
+        let root_fingerprint = match self.registry {
+            Some(_) => Some(self.root_fingerprint().await?),
+            None => None,
+        };
+        match self
+            .query_proto_btc(pb::btc_request::Request::RegisterScriptConfig(
+                pb::BtcRegisterScriptConfigRequest {
+                    registration: Some(pb::BtcScriptConfigRegistration {
+                        coin: coin as _,
+                        script_config: Some(script_config.clone()),
+                        keypath: keypath_account.map_or(vec![], |kp| kp.to_vec()),
+                    }),
+                    name: name.unwrap_or("").into(),
+                    xpub_type: xpub_type as _,
+                },
+            ))
+            .await?
+        {
+            pb::btc_response::Response::Success(_) => {}
+            _ => return Err(Error::UnexpectedResponse),
+        }
+        let root_fingerprint = match root_fingerprint {
+            Some(root_fingerprint) => root_fingerprint,
+            None => return Ok(()),
+        };
+        Ok(self.record_registered_script_config(
+            &root_fingerprint,
+            crate::registry::RegisteredScriptConfig {
+                registration: pb::BtcScriptConfigRegistration {
+                    coin: coin as _,
+                    script_config: Some(script_config.clone()),
+                    keypath: keypath_account.map_or(vec![], |kp| kp.to_vec()),
+                },
+                name: name.unwrap_or("").into(),
+                xpub_type,
+            },
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypath::HARDENED;
+
+    /// Checks that all inputs of the signed transaction are valid (all scripts execute correctly).
+    /// `utxos` are the outputs spent by the inputs, in the order of the inputs.
+    fn verify_transaction(tx: &bitcoin::Transaction, utxos: &[bitcoin::TxOut]) {
+        let serialized_tx = bitcoin::consensus::encode::serialize(tx);
+        let utxos_converted: Vec<bitcoinconsensus::Utxo> = utxos
+            .iter()
+            .map(|output| bitcoinconsensus::Utxo {
+                script_pubkey: output.script_pubkey.as_bytes().as_ptr(),
+                script_pubkey_len: output.script_pubkey.as_bytes().len() as u32,
+                value: output.value.to_sat() as i64,
+            })
+            .collect();
+        for (idx, output) in utxos.iter().enumerate() {
+            bitcoinconsensus::verify_with_flags(
+                output.script_pubkey.as_bytes(),
+                output.value.to_sat(),
+                serialized_tx.as_slice(),
+                Some(&utxos_converted),
+                idx,
+                bitcoinconsensus::VERIFY_ALL_PRE_TAPROOT | bitcoinconsensus::VERIFY_TAPROOT,
+            )
+            .unwrap();
+        }
+    }
+
+    /// Derives the key at `keypath` from the root key of the mnemonic "abandon abandon ... about",
+    /// see the BIP-49/84/86 test vectors.
+    fn abandon_xprv_at(keypath: &str) -> bitcoin::bip32::Xpriv {
+        use std::str::FromStr;
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        bitcoin::bip32::Xpriv::from_str("xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu")
+            .unwrap()
+            .derive_priv(
+                &secp,
+                &bitcoin::bip32::DerivationPath::from_str(keypath).unwrap(),
+            )
+            .unwrap()
+    }
+
+    fn abandon_xpub_at(keypath: &str) -> Xpub {
+        Xpub::from_priv(
+            &bitcoin::secp256k1::Secp256k1::new(),
+            &abandon_xprv_at(keypath),
+        )
+    }
+
+    #[test]
+    fn test_payload_from_pkscript() {
+        use std::str::FromStr;
+        // P2PKH
+        let addr = bitcoin::Address::from_str("1AMZK8xzHJWsuRErpGZTiW4jKz8fdfLUGE")
+            .unwrap()
+            .assume_checked();
+        let pkscript = addr.script_pubkey().into_bytes();
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: pkscript[3..23].to_vec(),
+                output_type: pb::BtcOutputType::P2pkh,
+            }
+        );
+
+        // P2SH
+        let addr = bitcoin::Address::from_str("3JFL8CgtV4ZtMFYeP5LgV4JppLkHw5Gw9T")
+            .unwrap()
+            .assume_checked();
+        let pkscript = addr.script_pubkey().into_bytes();
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: pkscript[2..22].to_vec(),
+                output_type: pb::BtcOutputType::P2sh,
+            }
+        );
+
+        // P2WPKH
+        let addr = bitcoin::Address::from_str("bc1qkl8ms75cq6ajxtny7e88z3u9hkpkvktt5jwh6u")
+            .unwrap()
+            .assume_checked();
+        let pkscript = addr.script_pubkey().into_bytes();
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: pkscript[2..].to_vec(),
+                output_type: pb::BtcOutputType::P2wpkh,
+            }
+        );
+
+        // P2WSH
+        let addr = bitcoin::Address::from_str(
+            "bc1q2fhgukymf0caaqrhfxrdju4wm94wwrch2ukntl5fuc0faz8zm49q0h6ss8",
+        )
+        .unwrap()
+        .assume_checked();
+        let pkscript = addr.script_pubkey().into_bytes();
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: pkscript[2..].to_vec(),
+                output_type: pb::BtcOutputType::P2wsh,
+            }
+        );
+
+        // P2TR
+        let addr = bitcoin::Address::from_str(
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+        )
+        .unwrap()
+        .assume_checked();
+        let pkscript = addr.script_pubkey().into_bytes();
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: pkscript[2..].to_vec(),
+                output_type: pb::BtcOutputType::P2tr,
+            }
+        );
+
+        // OP_RETURN empty (OP_0)
+        let pkscript = hex::decode("6a00").unwrap();
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: Vec::new(),
+                output_type: pb::BtcOutputType::OpReturn,
+            }
+        );
+
+        // OP_RETURN with data push
+        let pkscript = hex::decode("6a03aabbcc").unwrap();
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: vec![0xaa, 0xbb, 0xcc],
+                output_type: pb::BtcOutputType::OpReturn,
+            }
+        );
+
+        // OP_RETURN with 80-byte payload (PUSHDATA1)
+        let mut pkscript = vec![opcodes::all::OP_RETURN.to_u8(), 0x4c, 0x50];
+        pkscript.extend(std::iter::repeat_n(0xaa, 80));
+        assert_eq!(
+            Payload::from_pkscript(&pkscript).unwrap(),
+            Payload {
+                data: vec![0xaa; 80],
+                output_type: pb::BtcOutputType::OpReturn,
+            }
+        );
+
+        // Invalid OP_RETURN scripts
+        let pkscript = hex::decode("6a").unwrap();
+        assert!(matches!(
+            Payload::from_pkscript(&pkscript),
+            Err(PayloadError::InvalidOpReturn(
+                "naked OP_RETURN is not supported"
+            ))
+        ));
+
+        let pkscript = hex::decode("6a6a").unwrap();
+        assert!(matches!(
+            Payload::from_pkscript(&pkscript),
+            Err(PayloadError::InvalidOpReturn(
+                "no data push found after OP_RETURN"
+            ))
+        ));
+
+        let pkscript = hex::decode("6a0000").unwrap();
+        assert!(matches!(
+            Payload::from_pkscript(&pkscript),
+            Err(PayloadError::InvalidOpReturn(
+                "only one data push supported after OP_RETURN"
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_payment_request_to_pb() {
+        let payment_request = PaymentRequest {
+            recipient_name: "Test Merchant".into(),
+            memos: vec![PaymentRequestMemo::Text("Order #123".into())],
+            nonce: vec![],
+            total_amount: 123456,
+            signature: vec![1; 64],
+        };
+        assert_eq!(
+            pb::BtcPaymentRequestRequest::from(&payment_request),
+            pb::BtcPaymentRequestRequest {
+                recipient_name: "Test Merchant".into(),
+                memos: vec![pb::btc_payment_request_request::Memo {
+                    memo: Some(pb::btc_payment_request_request::memo::Memo::TextMemo(
+                        pb::btc_payment_request_request::memo::TextMemo {
+                            note: "Order #123".into(),
                         }
-                        TxOutput::External(output) => {
-                            Request::BtcSignOutput(pb::BtcSignOutputRequest {
-                                ours: false,
-                                value: output.value,
-                                r#type: output.payload.output_type as _,
-                                payload: output.payload.data.clone(),
-                                ..Default::default()
-                            })
+                    )),
+                }],
+                nonce: vec![],
+                total_amount: 123456,
+                signature: vec![1; 64],
+            }
+        );
+    }
+
+    // Test the requests answering the output and payment request steps of `btc_sign()`: outputs
+    // are linked to their payment request by `payment_request_index`.
+    #[test]
+    fn test_sign_payment_request_requests() {
+        let make_payment_request = |recipient_name: &str, total_amount| PaymentRequest {
+            recipient_name: recipient_name.into(),
+            memos: vec![PaymentRequestMemo::Text("Order #123".into())],
+            nonce: vec![],
+            total_amount,
+            signature: vec![1; 64],
+        };
+        let external_output = |value, payment_request_index| {
+            TxOutput::External(TxExternalOutput {
+                payload: Payload {
+                    data: vec![0x42; 20],
+                    output_type: pb::BtcOutputType::P2wpkh,
+                },
+                value,
+                payment_request_index,
+            })
+        };
+        let transaction = Transaction {
+            script_configs: vec![],
+            output_script_configs: vec![],
+            version: 2,
+            inputs: vec![],
+            outputs: vec![
+                external_output(1000, Some(1)),
+                external_output(2000, None),
+                external_output(3000, Some(0)),
+            ],
+            locktime: 0,
+            payment_requests: vec![
+                make_payment_request("First Merchant", 3000),
+                make_payment_request("Second Merchant", 1000),
+            ],
+        };
+
+        assert_eq!(
+            transaction.sign_output_request(0),
+            Request::BtcSignOutput(pb::BtcSignOutputRequest {
+                ours: false,
+                value: 1000,
+                r#type: pb::BtcOutputType::P2wpkh as _,
+                payload: vec![0x42; 20],
+                payment_request_index: Some(1),
+                ..Default::default()
+            })
+        );
+        assert!(matches!(
+            transaction.sign_output_request(1),
+            Request::BtcSignOutput(pb::BtcSignOutputRequest {
+                payment_request_index: None,
+                ..
+            })
+        ));
+
+        // The BitBox asks for the payment request referenced by the output.
+        assert_eq!(
+            transaction.payment_request_request(1).unwrap(),
+            pb::btc_request::Request::PaymentRequest(pb::BtcPaymentRequestRequest {
+                recipient_name: "Second Merchant".into(),
+                memos: vec![pb::btc_payment_request_request::Memo {
+                    memo: Some(pb::btc_payment_request_request::memo::Memo::TextMemo(
+                        pb::btc_payment_request_request::memo::TextMemo {
+                            note: "Order #123".into(),
                         }
-                    };
-                    next_response = self.get_next_response(request).await?;
+                    )),
+                }],
+                nonce: vec![],
+                total_amount: 1000,
+                signature: vec![1; 64],
+            })
+        );
+        assert!(matches!(
+            transaction.payment_request_request(0).unwrap(),
+            pb::btc_request::Request::PaymentRequest(pb::BtcPaymentRequestRequest {
+                total_amount: 3000,
+                ..
+            })
+        ));
+        assert!(matches!(
+            transaction.payment_request_request(2),
+            Err(Error::BtcSign(_))
+        ));
+    }
+
+    // Test that the multisig script config is reconstructed from the witness script and the
+    // PSBT global xpubs.
+    #[test]
+    fn test_transaction_from_psbt_multisig() {
+        use bitcoin::bip32::{DerivationPath, Xpriv};
+        use miniscript::psbt::PsbtExt;
+        use std::str::FromStr;
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let keypath_account = DerivationPath::from_str("m/48'/1'/0'/2'").unwrap();
+        let keys: Vec<(Fingerprint, Xpub)> = [1u8, 2, 3]
+            .iter()
+            .map(|&seed| {
+                let xprv = Xpriv::new_master(bitcoin::NetworkKind::Test, &[seed; 32]).unwrap();
+                let account_xprv = xprv.derive_priv(&secp, &keypath_account).unwrap();
+                (
+                    xprv.fingerprint(&secp),
+                    Xpub::from_priv(&secp, &account_xprv),
+                )
+            })
+            .collect();
+        let our_root_fingerprint = keys[0].0;
+
+        let make_psbt = |descriptor_template: &str| {
+            let keys_str: Vec<String> = keys
+                .iter()
+                .map(|(fingerprint, xpub)| {
+                    format!("[{}/48'/1'/0'/2']{}/<0;1>/*", fingerprint, xpub)
+                })
+                .collect();
+            let reversed_keys_str: Vec<String> = keys_str.iter().rev().cloned().collect();
+            let descriptor = miniscript::Descriptor::<miniscript::DescriptorPublicKey>::from_str(
+                &descriptor_template
+                    .replace("KEYS_REVERSED", &reversed_keys_str.join(","))
+                    .replace("KEYS", &keys_str.join(",")),
+            )
+            .unwrap();
+            let [descriptor_receive, descriptor_change]: [_; 2] = descriptor
+                .into_single_descriptors()
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let input_descriptor = descriptor_receive.at_derivation_index(0).unwrap();
+            let change_descriptor = descriptor_change.at_derivation_index(0).unwrap();
+
+            let tx = bitcoin::Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: vec![bitcoin::TxIn {
+                    previous_output: bitcoin::OutPoint::from_str(
+                        "3131313131313131313131313131313131313131313131313131313131313131:0",
+                    )
+                    .unwrap(),
+                    ..Default::default()
+                }],
+                output: vec![bitcoin::TxOut {
+                    value: bitcoin::Amount::from_sat(70_000_000),
+                    script_pubkey: change_descriptor.script_pubkey(),
+                }],
+            };
+            let mut psbt = bitcoin::psbt::Psbt::from_unsigned_tx(tx).unwrap();
+            psbt.inputs[0].witness_utxo = Some(bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(100_000_000),
+                script_pubkey: input_descriptor.script_pubkey(),
+            });
+            psbt.update_input_with_descriptor(0, &input_descriptor)
+                .unwrap();
+            psbt.update_output_with_descriptor(0, &change_descriptor)
+                .unwrap();
+            for (fingerprint, xpub) in keys.iter() {
+                psbt.xpub
+                    .insert(*xpub, (*fingerprint, keypath_account.clone()));
+            }
+            psbt
+        };
+
+        let mut sorted_xpubs: Vec<Xpub> = keys.iter().map(|(_, xpub)| *xpub).collect();
+        sorted_xpubs.sort_by_key(|xpub| xpub.encode());
+        let our_xpub_index = sorted_xpubs
+            .iter()
+            .position(|xpub| xpub == &keys[0].1)
+            .unwrap();
+
+        for (descriptor_template, script_type) in [
+            (
+                "wsh(sortedmulti(2,KEYS))",
+                pb::btc_script_config::multisig::ScriptType::P2wsh,
+            ),
+            (
+                "sh(wsh(sortedmulti(2,KEYS)))",
+                pb::btc_script_config::multisig::ScriptType::P2wshP2sh,
+            ),
+        ] {
+            let mut psbt = make_psbt(descriptor_template);
+            let (transaction, _our_keys) = Transaction::from_psbt(
+                pb::BtcCoin::Tbtc,
+                our_root_fingerprint.as_bytes(),
+                &psbt,
+                None,
+            )
+            .unwrap();
+            assert_eq!(
+                transaction.script_configs,
+                vec![pb::BtcScriptConfigWithKeypath {
+                    script_config: Some(make_script_config_multisig(
+                        2,
+                        &sorted_xpubs,
+                        our_xpub_index as _,
+                        script_type,
+                    )),
+                    keypath: keypath_account.to_u32_vec(),
+                }]
+            );
+            assert_eq!(transaction.inputs[0].script_config_index, 0);
+            assert!(matches!(
+                &transaction.outputs[0],
+                TxOutput::Internal(TxInternalOutput {
+                    script_config_index: 0,
+                    ..
+                })
+            ));
+
+            // A cosigner xpub is missing.
+            psbt.xpub.remove(&keys[2].1);
+            assert!(matches!(
+                Transaction::from_psbt(
+                    pb::BtcCoin::Tbtc,
+                    our_root_fingerprint.as_bytes(),
+                    &psbt,
+                    None
+                ),
+                Err(PsbtError::MultisigXpubNotFound)
+            ));
+
+            // The registered script config is found without the cosigner xpubs. Registered
+            // configs of a different coin or script type do not match.
+            let registered =
+                |coin: pb::BtcCoin, script_type| crate::registry::RegisteredScriptConfig {
+                    registration: pb::BtcScriptConfigRegistration {
+                        coin: coin as _,
+                        script_config: Some(make_script_config_multisig(
+                            2,
+                            &sorted_xpubs,
+                            our_xpub_index as _,
+                            script_type,
+                        )),
+                        keypath: keypath_account.to_u32_vec(),
+                    },
+                    name: "multisig".into(),
+                    xpub_type: pb::btc_register_script_config_request::XPubType::AutoXpubTpub,
+                };
+            let other_script_type = match script_type {
+                pb::btc_script_config::multisig::ScriptType::P2wsh => {
+                    pb::btc_script_config::multisig::ScriptType::P2wshP2sh
                 }
-                pb::btc_sign_next_response::Type::Done => break,
-                pb::btc_sign_next_response::Type::HostNonce => {
-                    return Err(Error::UnexpectedResponse);
+                pb::btc_script_config::multisig::ScriptType::P2wshP2sh => {
+                    pb::btc_script_config::multisig::ScriptType::P2wsh
+                }
+            };
+            let script_config = registered_script_config_for_psbt(
+                pb::BtcCoin::Tbtc,
+                our_root_fingerprint.as_bytes(),
+                &psbt,
+                &[
+                    registered(pb::BtcCoin::Btc, script_type),
+                    registered(pb::BtcCoin::Tbtc, other_script_type),
+                    registered(pb::BtcCoin::Tbtc, script_type),
+                ],
+            );
+            assert_eq!(script_config.as_ref(), Some(&transaction.script_configs[0]));
+            assert!(Transaction::from_psbt(
+                pb::BtcCoin::Tbtc,
+                our_root_fingerprint.as_bytes(),
+                &psbt,
+                script_config,
+            )
+            .is_ok());
+            assert_eq!(
+                registered_script_config_for_psbt(
+                    pb::BtcCoin::Tbtc,
+                    our_root_fingerprint.as_bytes(),
+                    &psbt,
+                    &[registered(pb::BtcCoin::Tbtc, other_script_type)],
+                ),
+                None
+            );
+        }
+
+        // Unsorted multisig scripts are not supported. At least one of the two key orders results
+        // in an unsorted script.
+        let psbt = ["wsh(multi(2,KEYS))", "wsh(multi(2,KEYS_REVERSED))"]
+            .into_iter()
+            .map(make_psbt)
+            .find(|psbt| {
+                let (_, pubkeys) =
+                    parse_multisig_script(psbt.inputs[0].witness_script.as_ref().unwrap()).unwrap();
+                !pubkeys
+                    .windows(2)
+                    .all(|w| w[0].serialize() < w[1].serialize())
+            })
+            .unwrap();
+        assert!(matches!(
+            Transaction::from_psbt(
+                pb::BtcCoin::Tbtc,
+                our_root_fingerprint.as_bytes(),
+                &psbt,
+                None
+            ),
+            Err(PsbtError::InvalidMultisigScript)
+        ));
+    }
+
+    // Test that a PSBT containing only p2wpkh inputs is converted correctly to a transaction to be
+    // signed by the BitBox.
+    #[test]
+    fn test_transaction_from_psbt_p2wpkh() {
+        use std::str::FromStr;
+
+        // Based on mnemonic:
+        // route glue else try obey local kidney future teach unaware pulse exclude.
+        let psbt_str = "cHNidP8BAHECAAAAAfbXTun4YYxDroWyzRq3jDsWFVlsZ7HUzxiORY/iR4goAAAAAAD9////AuLCAAAAAAAAFgAUg3w5W0zt3AmxRmgA5Q6wZJUDRhUowwAAAAAAABYAFJjQqUoXDcwUEqfExu9pnaSn5XBct0ElAAABAR+ghgEAAAAAABYAFHn03igII+hp819N2Zlb5LnN8atRAQDfAQAAAAABAZ9EJlMJnXF5bFVrb1eFBYrEev3pg35WpvS3RlELsMMrAQAAAAD9////AqCGAQAAAAAAFgAUefTeKAgj6GnzX03ZmVvkuc3xq1EoRs4JAAAAABYAFKG2PzjYjknaA6lmXFqPaSgHwXX9AkgwRQIhAL0v0r3LisQ9KOlGzMhM/xYqUmrv2a5sORRlkX1fqDC8AiB9XqxSNEdb4mPnp7ylF1cAlbAZ7jMhgIxHUXylTww3bwEhA0AEOM0yYEpexPoKE3vT51uxZ+8hk9sOEfBFKOeo6oDDAAAAACIGAyNQfmAT/YLmZaxxfDwClmVNt2BkFnfQu/i8Uc/hHDUiGBKiwYlUAACAAQAAgAAAAIAAAAAAAAAAAAAAIgIDnxFM7Qr9LvJwQDB9GozdTRIe3MYVuHOqT7dU2EuvHrIYEqLBiVQAAIABAACAAAAAgAEAAAAAAAAAAA==";
+
+        let expected_transaction = Transaction {
+            script_configs: vec![pb::BtcScriptConfigWithKeypath {
+                script_config: Some(pb::BtcScriptConfig {
+                    config: Some(pb::btc_script_config::Config::SimpleType(
+                        pb::btc_script_config::SimpleType::P2wpkh as _,
+                    )),
+                }),
+                keypath: vec![84 + HARDENED, 1 + HARDENED, HARDENED],
+            }],
+            output_script_configs: vec![],
+            version: 2,
+            inputs: vec![TxInput {
+                prev_out_hash: vec![
+                    246, 215, 78, 233, 248, 97, 140, 67, 174, 133, 178, 205, 26, 183, 140, 59, 22,
+                    21, 89, 108, 103, 177, 212, 207, 24, 142, 69, 143, 226, 71, 136, 40,
+                ],
+                prev_out_index: 0,
+                prev_out_value: 100000,
+                sequence: 4294967293,
+                keypath: "m/84'/1'/0'/0/0".try_into().unwrap(),
+                script_config_index: 0,
+                prev_tx: Some(PrevTx {
+                    version: 1,
+                    inputs: vec![PrevTxInput {
+                        prev_out_hash: vec![
+                            159, 68, 38, 83, 9, 157, 113, 121, 108, 85, 107, 111, 87, 133, 5, 138,
+                            196, 122, 253, 233, 131, 126, 86, 166, 244, 183, 70, 81, 11, 176, 195,
+                            43,
+                        ],
+                        prev_out_index: 1,
+                        signature_script: vec![],
+                        sequence: 4294967293,
+                    }],
+                    outputs: vec![
+                        PrevTxOutput {
+                            value: 100000,
+                            pubkey_script: vec![
+                                0, 20, 121, 244, 222, 40, 8, 35, 232, 105, 243, 95, 77, 217, 153,
+                                91, 228, 185, 205, 241, 171, 81,
+                            ],
+                        },
+                        PrevTxOutput {
+                            value: 164513320,
+                            pubkey_script: vec![
+                                0, 20, 161, 182, 63, 56, 216, 142, 73, 218, 3, 169, 102, 92, 90,
+                                143, 105, 40, 7, 193, 117, 253,
+                            ],
+                        },
+                    ],
+                    locktime: 0,
+                }),
+                silent_payment_pubkey: Some(
+                    bitcoin::secp256k1::PublicKey::from_str(
+                        "0323507e6013fd82e665ac717c3c0296654db760641677d0bbf8bc51cfe11c3522",
+                    )
+                    .unwrap(),
+                ),
+            }],
+            outputs: vec![
+                TxOutput::External(TxExternalOutput {
+                    payload: Payload {
+                        data: vec![
+                            131, 124, 57, 91, 76, 237, 220, 9, 177, 70, 104, 0, 229, 14, 176, 100,
+                            149, 3, 70, 21,
+                        ],
+                        output_type: pb::BtcOutputType::P2wpkh,
+                    },
+                    value: 49890,
+                    payment_request_index: None,
+                }),
+                TxOutput::Internal(TxInternalOutput {
+                    keypath: "m/84'/1'/0'/1/0".try_into().unwrap(),
+                    value: 49960,
+                    script_config_index: 0,
+                    output_script_config_index: None,
+                }),
+            ],
+            locktime: 2441655,
+            payment_requests: vec![],
+        };
+        let our_root_fingerprint = hex::decode("12a2c189").unwrap();
+        let psbt = bitcoin::psbt::Psbt::from_str(psbt_str).unwrap();
+        let (transaction, _our_keys) =
+            Transaction::from_psbt(pb::BtcCoin::Tbtc, &our_root_fingerprint, &psbt, None).unwrap();
+        assert_eq!(transaction, expected_transaction);
+
+        let summary = psbt_summary(
+            pb::BtcCoin::Tbtc,
+            &Fingerprint::from_str("12a2c189").unwrap(),
+            &psbt,
+            None,
+        )
+        .unwrap();
+        assert_eq!(summary.outputs[1].kind, OutputKind::Change);
+        assert_eq!(summary.amount_sent, 49890);
+        assert_eq!(summary.fee, 150);
+        assert_eq!(summary.vsize, Some(141));
+        assert!(summary.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_silent_payment_pubkey() {
+        use std::str::FromStr;
+
+        let pubkey = bitcoin::secp256k1::PublicKey::from_str(
+            "0323507e6013fd82e665ac717c3c0296654db760641677d0bbf8bc51cfe11c3522",
+        )
+        .unwrap();
+        let our_key = OurKey::Segwit(pubkey, "m/49'/1'/0'/0/0".try_into().unwrap());
+        let utxo = |script_pubkey: bitcoin::ScriptBuf| bitcoin::TxOut {
+            value: bitcoin::Amount::from_sat(100000),
+            script_pubkey,
+        };
+
+        let p2wpkh =
+            bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::CompressedPublicKey(pubkey).wpubkey_hash());
+        assert_eq!(
+            silent_payment_pubkey(&utxo(p2wpkh.clone()), None, &our_key),
+            Some(pubkey)
+        );
+
+        // p2sh-p2wpkh is eligible.
+        let p2sh_p2wpkh = bitcoin::ScriptBuf::new_p2sh(&p2wpkh.script_hash());
+        assert_eq!(
+            silent_payment_pubkey(&utxo(p2sh_p2wpkh.clone()), Some(&p2wpkh), &our_key),
+            Some(pubkey)
+        );
+        assert_eq!(
+            silent_payment_pubkey(&utxo(p2sh_p2wpkh), None, &our_key),
+            None
+        );
+
+        // p2wsh-p2sh multisig is not.
+        let witness_script = bitcoin::blockdata::script::Builder::new()
+            .push_int(1)
+            .push_key(&bitcoin::PublicKey::new(pubkey))
+            .push_int(1)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        let p2wsh = bitcoin::ScriptBuf::new_p2wsh(&witness_script.wscript_hash());
+        let p2wsh_p2sh = bitcoin::ScriptBuf::new_p2sh(&p2wsh.script_hash());
+        assert_eq!(
+            silent_payment_pubkey(&utxo(p2wsh_p2sh), Some(&p2wsh), &our_key),
+            None
+        );
+        assert_eq!(silent_payment_pubkey(&utxo(p2wsh), None, &our_key), None);
+    }
+
+    #[test]
+    fn test_finalize_psbt() {
+        use bitcoin::bip32::{DerivationPath, Xpriv};
+        use bitcoin::{transaction, Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut};
+        use std::str::FromStr;
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = Xpriv::new_master(bitcoin::Network::Testnet, &[1u8; 32]).unwrap();
+        let fingerprint = xprv.fingerprint(&secp);
+        let pubkey_at = |path: &DerivationPath| {
+            bitcoin::bip32::Xpub::from_priv(&secp, &xprv.derive_priv(&secp, path).unwrap())
+                .public_key
+        };
+
+        let p2wpkh_path = DerivationPath::from_str("m/84'/1'/0'/0/0").unwrap();
+        let p2wpkh_pubkey = pubkey_at(&p2wpkh_path);
+        let p2wpkh_p2sh_path = DerivationPath::from_str("m/49'/1'/0'/0/0").unwrap();
+        let p2wpkh_p2sh_pubkey = pubkey_at(&p2wpkh_p2sh_path);
+        let p2wpkh_p2sh_redeem_script =
+            ScriptBuf::new_p2wpkh(&bitcoin::CompressedPublicKey(p2wpkh_p2sh_pubkey).wpubkey_hash());
+        let p2tr_path = DerivationPath::from_str("m/86'/1'/0'/0/0").unwrap();
+        let p2tr_pubkey = pubkey_at(&p2tr_path).x_only_public_key().0;
+
+        let prev_tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: ScriptBuf::new_p2wpkh(
+                        &bitcoin::CompressedPublicKey(p2wpkh_pubkey).wpubkey_hash(),
+                    ),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: ScriptBuf::new_p2sh(&p2wpkh_p2sh_redeem_script.script_hash()),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: ScriptBuf::new_p2tr(&secp, p2tr_pubkey, None),
+                },
+            ],
+        };
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: (0..3)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint {
+                        txid: prev_tx.compute_txid(),
+                        vout,
+                    },
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence(0xFFFFFFFF),
+                    witness: bitcoin::Witness::default(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(250_000),
+                script_pubkey: prev_tx.output[0].script_pubkey.clone(),
+            }],
+        };
+        let mut psbt = bitcoin::psbt::Psbt::from_unsigned_tx(tx).unwrap();
+        for (input, utxo) in psbt.inputs.iter_mut().zip(prev_tx.output.iter()) {
+            input.witness_utxo = Some(utxo.clone());
+        }
+        psbt.inputs[0]
+            .bip32_derivation
+            .insert(p2wpkh_pubkey, (fingerprint, p2wpkh_path));
+        psbt.inputs[1]
+            .bip32_derivation
+            .insert(p2wpkh_p2sh_pubkey, (fingerprint, p2wpkh_p2sh_path));
+        psbt.inputs[1].redeem_script = Some(p2wpkh_p2sh_redeem_script.clone());
+        psbt.inputs[2].tap_internal_key = Some(p2tr_pubkey);
+        psbt.inputs[2]
+            .tap_key_origins
+            .insert(p2tr_pubkey, (vec![], (fingerprint, p2tr_path)));
+
+        // Not signed yet.
+        assert!(matches!(
+            finalize_psbt(&mut psbt.clone()),
+            Err(PsbtError::Finalize(0, _))
+        ));
+        assert!(matches!(
+            extract_tx(psbt.clone()),
+            Err(PsbtError::Finalize(0, _))
+        ));
+
+        psbt.sign(&xprv, &secp).unwrap();
+        finalize_psbt(&mut psbt).unwrap();
+
+        for input in psbt.inputs.iter() {
+            assert!(input.final_script_witness.is_some());
+            assert!(input.partial_sigs.is_empty());
+            assert!(input.bip32_derivation.is_empty());
+            assert!(input.tap_key_sig.is_none());
+            assert!(input.redeem_script.is_none());
+        }
+        assert!(psbt.inputs[0].final_script_sig.is_none());
+        assert_eq!(
+            psbt.inputs[1].final_script_sig.as_ref().unwrap().as_bytes()[1..],
+            p2wpkh_p2sh_redeem_script.as_bytes()[..],
+        );
+        assert!(psbt.inputs[2].final_script_sig.is_none());
+        // Finalizing again is a no-op.
+        let finalized_psbt = psbt.clone();
+        finalize_psbt(&mut psbt).unwrap();
+        assert_eq!(psbt, finalized_psbt);
+
+        let tx = extract_tx(psbt).unwrap();
+        verify_transaction(&tx, &prev_tx.output);
+    }
+
+    #[test]
+    fn test_assemble_signed_tx() {
+        use bitcoin::hashes::Hash;
+        use bitcoin::key::TapTweak;
+        use bitcoin::sighash::{Prevouts, SighashCache};
+        use bitcoin::{transaction, Amount, ScriptBuf, TxOut};
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let script_config = |simple_type, keypath: &str| pb::BtcScriptConfigWithKeypath {
+            script_config: Some(make_script_config_simple(simple_type)),
+            keypath: Keypath::try_from(keypath).unwrap().to_vec(),
+        };
+
+        let script_configs = vec![
+            script_config(pb::btc_script_config::SimpleType::P2wpkh, "m/84'/0'/0'"),
+            script_config(pb::btc_script_config::SimpleType::P2wpkhP2sh, "m/49'/0'/0'"),
+            script_config(pb::btc_script_config::SimpleType::P2tr, "m/86'/0'/0'"),
+        ];
+        let account_xpubs: Vec<Xpub> = ["m/84'/0'/0'", "m/49'/0'/0'", "m/86'/0'/0'"]
+            .iter()
+            .map(|path| abandon_xpub_at(path))
+            .collect();
+        let output_script_configs = vec![script_config(
+            pb::btc_script_config::SimpleType::P2tr,
+            "m/86'/0'/1'",
+        )];
+        let output_account_xpubs = vec![abandon_xpub_at("m/86'/0'/1'")];
+        let input_keypaths = ["m/84'/0'/0'/0/0", "m/49'/0'/0'/0/0", "m/86'/0'/0'/0/0"];
+
+        let prev_tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: input_keypaths
+                .iter()
+                .zip(&script_configs)
+                .zip(&account_xpubs)
+                .map(|((keypath, script_config), xpub)| TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: derive_script_pubkey(
+                        &Keypath::try_from(*keypath).unwrap(),
+                        script_config.script_config.as_ref().unwrap(),
+                        Some(xpub),
+                    )
+                    .unwrap(),
+                })
+                .collect(),
+        };
+        assert_eq!(
+            address_from_script_pubkey(pb::BtcCoin::Btc, &prev_tx.output[1].script_pubkey).unwrap(),
+            "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf"
+        );
+
+        let transaction = Transaction {
+            script_configs,
+            output_script_configs,
+            version: 2,
+            inputs: input_keypaths
+                .iter()
+                .enumerate()
+                .map(|(index, keypath)| TxInput {
+                    prev_out_hash: (prev_tx.compute_txid().as_ref() as &[u8]).to_vec(),
+                    prev_out_index: index as u32,
+                    prev_out_value: 100_000,
+                    sequence: 0xFFFFFFFD,
+                    keypath: Keypath::try_from(*keypath).unwrap(),
+                    script_config_index: index as u32,
+                    prev_tx: Some(PrevTx::from(&prev_tx)),
+                    silent_payment_pubkey: None,
+                })
+                .collect(),
+            outputs: vec![
+                TxOutput::Internal(TxInternalOutput {
+                    keypath: "m/84'/0'/0'/1/0".try_into().unwrap(),
+                    value: 100_000,
+                    script_config_index: 0,
+                    output_script_config_index: None,
+                }),
+                TxOutput::Internal(TxInternalOutput {
+                    keypath: "m/86'/0'/1'/0/0".try_into().unwrap(),
+                    value: 100_000,
+                    script_config_index: 0,
+                    output_script_config_index: Some(0),
+                }),
+                TxOutput::External(TxExternalOutput {
+                    payload: Payload {
+                        data: vec![1; 32],
+                        output_type: pb::BtcOutputType::P2wsh,
+                    },
+                    value: 99_000,
+                    payment_request_index: None,
+                }),
+            ],
+            locktime: 800_000,
+            payment_requests: vec![],
+        };
+
+        // Wrong number of account xpubs or signatures.
+        let unsigned = SignResult {
+            signatures: vec![vec![1; 64]; 3],
+            generated_outputs: Default::default(),
+        };
+        assert!(matches!(
+            assemble_signed_tx(
+                &transaction,
+                &unsigned,
+                &account_xpubs[..2],
+                &output_account_xpubs
+            ),
+            Err(Error::AssembleTx(AssembleTxError::XpubCount))
+        ));
+        assert!(matches!(
+            assemble_signed_tx(
+                &transaction,
+                &SignResult {
+                    signatures: vec![vec![1; 64]; 2],
+                    generated_outputs: Default::default(),
+                },
+                &account_xpubs,
+                &output_account_xpubs
+            ),
+            Err(Error::AssembleTx(AssembleTxError::SignatureCount))
+        ));
+
+        // Sign the transaction like the BitBox would.
+        let unsigned_tx = assemble_signed_tx(
+            &transaction,
+            &unsigned,
+            &account_xpubs,
+            &output_account_xpubs,
+        )
+        .unwrap();
+        assert_eq!(unsigned_tx.output.len(), 3);
+        assert_eq!(
+            unsigned_tx.output[2].script_pubkey,
+            ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::from_byte_array([1; 32]))
+        );
+        let mut sighash_cache = SighashCache::new(&unsigned_tx);
+        let signatures = input_keypaths
+            .iter()
+            .enumerate()
+            .map(|(index, keypath)| {
+                let privkey = abandon_xprv_at(keypath).private_key;
+                let pubkey = bitcoin::CompressedPublicKey(privkey.public_key(&secp));
+                if index == 2 {
+                    let sighash = sighash_cache
+                        .taproot_key_spend_signature_hash(
+                            index,
+                            &Prevouts::All(&prev_tx.output),
+                            bitcoin::TapSighashType::Default,
+                        )
+                        .unwrap();
+                    let keypair = bitcoin::key::Keypair::from_secret_key(&secp, &privkey)
+                        .tap_tweak(&secp, None)
+                        .to_inner();
+                    let msg = bitcoin::secp256k1::Message::from_digest(sighash.to_byte_array());
+                    secp.sign_schnorr_no_aux_rand(&msg, &keypair)
+                        .serialize()
+                        .to_vec()
+                } else {
+                    let sighash = sighash_cache
+                        .p2wpkh_signature_hash(
+                            index,
+                            &ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash()),
+                            Amount::from_sat(100_000),
+                            bitcoin::EcdsaSighashType::All,
+                        )
+                        .unwrap();
+                    let msg = bitcoin::secp256k1::Message::from_digest(sighash.to_byte_array());
+                    secp.sign_ecdsa(&msg, &privkey).serialize_compact().to_vec()
                 }
-                _ => return Err(Error::UnexpectedResponse),
-            }
-        }
-        Ok(sigs)
+            })
+            .collect();
+        let tx = assemble_signed_tx(
+            &transaction,
+            &SignResult {
+                signatures,
+                generated_outputs: Default::default(),
+            },
+            &account_xpubs,
+            &output_account_xpubs,
+        )
+        .unwrap();
+        assert_eq!(tx.compute_txid(), unsigned_tx.compute_txid());
+        assert_eq!(tx.lock_time.to_consensus_u32(), 800_000);
+
+        verify_transaction(&tx, &prev_tx.output);
     }
 
-    /// Sign a PSBT.
-    ///
-    /// If `force_script_config` is None, we attempt to infer the involved script configs. For the
-    /// simple script config (single sig), we infer the script config from the involved redeem
-    /// scripts and provided derviation paths.
-    ///
-    /// Multisig and policy configs are currently not inferred and must be provided using
-    /// `force_script_config`.
-    pub async fn btc_sign_psbt(
-        &self,
-        coin: pb::BtcCoin,
-        psbt: &mut bitcoin::psbt::Psbt,
-        force_script_config: Option<pb::BtcScriptConfigWithKeypath>,
-        format_unit: pb::btc_sign_init_request::FormatUnit,
-    ) -> Result<(), Error> {
-        // since v9.15.0, the BitBox02 accepts "internal" outputs (ones sent to the BitBox02 with
-        // the keypath) even if the keypath is not a change keypath. PSBTs often contain the key
-        // origin info in outputs even in regular send-to-self outputs.
-        self.validate_version(">=9.15.0")?;
+    // Test that outputs to a different account of our keystore reference output script configs.
+    #[test]
+    fn test_transaction_from_psbt_output_script_configs() {
+        use bitcoin::bip32::{DerivationPath, Xpriv};
+        use bitcoin::{transaction, Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut};
+        use std::str::FromStr;
 
-        let our_root_fingerprint = hex::decode(self.root_fingerprint().await?).unwrap();
-        let (transaction, our_keys) =
-            Transaction::from_psbt(&our_root_fingerprint, psbt, force_script_config)?;
-        let signatures = self.btc_sign(coin, &transaction, format_unit).await?;
-        for (psbt_input, (signature, our_key)) in
-            psbt.inputs.iter_mut().zip(signatures.iter().zip(our_keys))
-        {
-            match our_key {
-                OurKey::Segwit(pubkey, _) => {
-                    psbt_input.partial_sigs.insert(
-                        bitcoin::PublicKey::new(pubkey),
-                        bitcoin::ecdsa::Signature {
-                            signature: bitcoin::secp256k1::ecdsa::Signature::from_compact(
-                                signature,
-                            )
-                            .map_err(|_| Error::InvalidSignature)?,
-                            sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = Xpriv::new_master(bitcoin::Network::Testnet, &[1u8; 32]).unwrap();
+        let fingerprint = xprv.fingerprint(&secp);
+        let pubkey_at = |path: &DerivationPath| {
+            bitcoin::bip32::Xpub::from_priv(&secp, &xprv.derive_priv(&secp, path).unwrap())
+                .public_key
+        };
+        let p2wpkh =
+            |pubkey| ScriptBuf::new_p2wpkh(&bitcoin::CompressedPublicKey(pubkey).wpubkey_hash());
+
+        let input_path = DerivationPath::from_str("m/84'/1'/0'/0/0").unwrap();
+        let input_pubkey = pubkey_at(&input_path);
+        let output_paths = [
+            // Taproot change of the same account.
+            "m/86'/1'/0'/1/0",
+            // Different accounts.
+            "m/84'/1'/1'/0/0",
+            "m/86'/1'/1'/0/0",
+            "m/84'/1'/1'/1/0",
+        ]
+        .map(|path| DerivationPath::from_str(path).unwrap());
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: bitcoin::Txid::from_str(
+                        "3131313131313131313131313131313131313131313131313131313131313131",
+                    )
+                    .unwrap(),
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0xFFFFFFFF),
+                witness: bitcoin::Witness::default(),
+            }],
+            output: output_paths
+                .iter()
+                .map(|path| {
+                    let pubkey = pubkey_at(path);
+                    TxOut {
+                        value: Amount::from_sat(10_000),
+                        script_pubkey: if path.to_string().starts_with("86'") {
+                            ScriptBuf::new_p2tr(&secp, pubkey.x_only_public_key().0, None)
+                        } else {
+                            p2wpkh(pubkey)
                         },
-                    );
-                }
-                OurKey::TaprootInternal(_) => {
-                    psbt_input.tap_key_sig = Some(
-                        bitcoin::taproot::Signature::from_slice(signature)
-                            .map_err(|_| Error::InvalidSignature)?,
-                    );
-                }
-                OurKey::TaprootScript(xonly, leaf_hash, _) => {
-                    let sig = bitcoin::taproot::Signature::from_slice(signature)
-                        .map_err(|_| Error::InvalidSignature)?;
-                    psbt_input.tap_script_sigs.insert((xonly, leaf_hash), sig);
-                }
+                    }
+                })
+                .collect(),
+        };
+        let mut psbt = bitcoin::psbt::Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: p2wpkh(input_pubkey),
+        });
+        psbt.inputs[0]
+            .bip32_derivation
+            .insert(input_pubkey, (fingerprint, input_path));
+        for (psbt_output, path) in psbt.outputs.iter_mut().zip(output_paths.iter()) {
+            let pubkey = pubkey_at(path);
+            if path.to_string().starts_with("86'") {
+                let xonly = pubkey.x_only_public_key().0;
+                psbt_output.tap_internal_key = Some(xonly);
+                psbt_output
+                    .tap_key_origins
+                    .insert(xonly, (vec![], (fingerprint, path.clone())));
+            } else {
+                psbt_output
+                    .bip32_derivation
+                    .insert(pubkey, (fingerprint, path.clone()));
             }
         }
-        Ok(())
-    }
-
-    /// Sign a message.
-    pub async fn btc_sign_message(
-        &self,
-        coin: pb::BtcCoin,
-        script_config: pb::BtcScriptConfigWithKeypath,
-        msg: &[u8],
-    ) -> Result<SignMessageSignature, Error> {
-        self.validate_version(">=9.5.0")?;
 
-        let host_nonce = crate::antiklepto::gen_host_nonce()?;
-        let request = pb::BtcSignMessageRequest {
-            coin: coin as _,
-            script_config: Some(script_config),
-            msg: msg.to_vec(),
-            host_nonce_commitment: Some(pb::AntiKleptoHostNonceCommitment {
-                commitment: crate::antiklepto::host_commit(&host_nonce).to_vec(),
-            }),
+        let (transaction, _our_keys) =
+            Transaction::from_psbt(pb::BtcCoin::Tbtc, fingerprint.as_bytes(), &psbt, None).unwrap();
+        let config = |simple_type, keypath: &str| pb::BtcScriptConfigWithKeypath {
+            script_config: Some(make_script_config_simple(simple_type)),
+            keypath: Keypath::try_from(keypath).unwrap().to_vec(),
         };
+        assert_eq!(
+            transaction.script_configs,
+            vec![
+                config(pb::btc_script_config::SimpleType::P2wpkh, "m/84'/1'/0'"),
+                config(pb::btc_script_config::SimpleType::P2tr, "m/86'/1'/0'"),
+            ]
+        );
+        assert_eq!(
+            transaction.output_script_configs,
+            vec![
+                config(pb::btc_script_config::SimpleType::P2wpkh, "m/84'/1'/1'"),
+                config(pb::btc_script_config::SimpleType::P2tr, "m/86'/1'/1'"),
+            ]
+        );
+        let indices: Vec<(u32, Option<u32>)> = transaction
+            .outputs
+            .iter()
+            .map(|output| match output {
+                TxOutput::Internal(output) => (
+                    output.script_config_index,
+                    output.output_script_config_index,
+                ),
+                _ => panic!("expected internal output"),
+            })
+            .collect();
+        assert_eq!(
+            indices,
+            vec![(1, None), (0, Some(0)), (0, Some(1)), (0, Some(0))]
+        );
+    }
 
-        let response = self
-            .query_proto_btc(pb::btc_request::Request::SignMessage(request))
-            .await?;
-        let signer_commitment = match response {
-            pb::btc_response::Response::AntikleptoSignerCommitment(
-                pb::AntiKleptoSignerCommitment { commitment },
-            ) => commitment,
-            _ => return Err(Error::UnexpectedResponse),
+    #[test]
+    fn test_derive_address_simple() {
+        let derive = |coin, keypath: &str, simple_type, account_keypath: &str| {
+            derive_address(
+                coin,
+                &keypath.try_into().unwrap(),
+                &make_script_config_simple(simple_type),
+                Some(&abandon_xpub_at(account_keypath)),
+            )
         };
 
-        let request = pb::AntiKleptoSignatureRequest {
-            host_nonce: host_nonce.to_vec(),
-        };
+        assert_eq!(
+            derive(
+                pb::BtcCoin::Btc,
+                "m/84'/0'/0'/0/0",
+                pb::btc_script_config::SimpleType::P2wpkh,
+                "m/84'/0'/0'"
+            )
+            .unwrap(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            derive(
+                pb::BtcCoin::Btc,
+                "m/49'/0'/0'/0/0",
+                pb::btc_script_config::SimpleType::P2wpkhP2sh,
+                "m/49'/0'/0'"
+            )
+            .unwrap(),
+            "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf"
+        );
+        assert_eq!(
+            derive(
+                pb::BtcCoin::Btc,
+                "m/86'/0'/0'/0/0",
+                pb::btc_script_config::SimpleType::P2tr,
+                "m/86'/0'/0'"
+            )
+            .unwrap(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
 
-        let response = self
-            .query_proto_btc(pb::btc_request::Request::AntikleptoSignature(request))
-            .await?;
-        let signature = match response {
-            pb::btc_response::Response::SignMessage(pb::BtcSignMessageResponse { signature }) => {
-                signature
-            }
-            _ => return Err(Error::UnexpectedResponse),
-        };
-        crate::antiklepto::verify_ecdsa(&host_nonce, &signer_commitment, &signature)?;
+        // Other coins encode the same scripts with different prefixes.
+        for (coin, p2wpkh_prefix, p2sh_prefix) in [
+            (pb::BtcCoin::Tbtc, "tb1q", "2"),
+            (pb::BtcCoin::Rbtc, "bcrt1q", "2"),
+            (pb::BtcCoin::Ltc, "ltc1q", "M"),
+            (pb::BtcCoin::Tltc, "tltc1q", "Q"),
+        ] {
+            assert!(derive(
+                coin,
+                "m/84'/1'/0'/1/3",
+                pb::btc_script_config::SimpleType::P2wpkh,
+                "m/84'/1'/0'"
+            )
+            .unwrap()
+            .starts_with(p2wpkh_prefix));
+            assert!(derive(
+                coin,
+                "m/49'/1'/0'/1/3",
+                pb::btc_script_config::SimpleType::P2wpkhP2sh,
+                "m/49'/1'/0'"
+            )
+            .unwrap()
+            .starts_with(p2sh_prefix));
+        }
 
-        let sig = signature[..64].to_vec();
-        let recid = signature[64];
-        let compressed: u8 = 4; // BitBox02 uses only compressed pubkeys
-        let sig65: u8 = 27 + compressed + recid;
-        let mut electrum_sig65 = vec![sig65];
-        electrum_sig65.extend_from_slice(&sig);
-        Ok(SignMessageSignature {
-            sig,
-            recid,
-            electrum_sig65,
-        })
+        assert_eq!(
+            derive_address(
+                pb::BtcCoin::Btc,
+                &"m/84'/0'/0'/0/0".try_into().unwrap(),
+                &make_script_config_simple(pb::btc_script_config::SimpleType::P2wpkh),
+                None,
+            ),
+            Err(AddressError::MissingXpub)
+        );
+        assert_eq!(
+            derive(
+                pb::BtcCoin::Btc,
+                "m/84'/0'/0'/0",
+                pb::btc_script_config::SimpleType::P2wpkh,
+                "m/84'/0'/0'"
+            ),
+            Err(AddressError::InvalidKeypath)
+        );
     }
 
-    /// Before a multisig or policy script config can be used to display receive addresses or sign
-    /// transactions, it must be registered on the device. This function checks if the script config
-    /// was already registered.
-    ///
-    /// `keypath_account` must be set if the script config is multisig, and can be `None` if it is a
-    /// policy.
-    pub async fn btc_is_script_config_registered(
-        &self,
-        coin: pb::BtcCoin,
-        script_config: &pb::BtcScriptConfig,
-        keypath_account: Option<&Keypath>,
-    ) -> Result<bool, Error> {
-        match self
-            .query_proto_btc(pb::btc_request::Request::IsScriptConfigRegistered(
-                pb::BtcIsScriptConfigRegisteredRequest {
-                    registration: Some(pb::BtcScriptConfigRegistration {
-                        coin: coin as _,
-                        script_config: Some(script_config.clone()),
-                        keypath: keypath_account.map_or(vec![], |kp| kp.to_vec()),
-                    }),
-                },
-            ))
-            .await?
-        {
-            pb::btc_response::Response::IsScriptConfigRegistered(response) => {
-                Ok(response.is_registered)
+    #[test]
+    fn test_derive_address_multisig() {
+        use std::str::FromStr;
+
+        let xpubs: Vec<Xpub> = [
+            "tpubDFgycCkexSxkdZfeyaasDHityE97kiYM1BeCNoivDHvydGugKtoNobt4vEX6YSHNPy2cqmWQHKjKxciJuocepsGPGxcDZVmiMBnxgA1JKQk",
+            "tpubDCNtvuCS9oj3psPNfXZXuGjcQ5rSBi3MzigjBqqwQohWWetoRdLzT5v2uJq6KBTwxj1FYvuPTr7RoWkN4cmubDy5wW8SU3q9xYnDRpQepiT",
+        ]
+        .iter()
+        .map(|xpub| Xpub::from_str(xpub).unwrap())
+        .collect();
+        for (script_type, descriptor) in [
+            (
+                pb::btc_script_config::multisig::ScriptType::P2wsh,
+                "wsh(sortedmulti(1,{0}/1/7,{1}/1/7))",
+            ),
+            (
+                pb::btc_script_config::multisig::ScriptType::P2wshP2sh,
+                "sh(wsh(sortedmulti(1,{0}/1/7,{1}/1/7)))",
+            ),
+        ] {
+            let descriptor: miniscript::Descriptor<miniscript::DescriptorPublicKey> = descriptor
+                .replace("{0}", &xpubs[0].to_string())
+                .replace("{1}", &xpubs[1].to_string())
+                .parse()
+                .unwrap();
+            let expected = descriptor
+                .at_derivation_index(0)
+                .unwrap()
+                .address(bitcoin::Network::Testnet)
+                .unwrap()
+                .to_string();
+            assert_eq!(
+                derive_address(
+                    pb::BtcCoin::Tbtc,
+                    &"m/48'/1'/0'/2'/1/7".try_into().unwrap(),
+                    &make_script_config_multisig(1, &xpubs, 1, script_type),
+                    None,
+                )
+                .unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "miniscript")]
+    #[test]
+    fn test_derive_address_policy() {
+        use std::str::FromStr;
+
+        let our_xpub = Xpub::from_str("tpubDFgycCkexSxkdZfeyaasDHityE97kiYM1BeCNoivDHvydGugKtoNobt4vEX6YSHNPy2cqmWQHKjKxciJuocepsGPGxcDZVmiMBnxgA1JKQk").unwrap();
+        let some_xpub = Xpub::from_str("tpubDCNtvuCS9oj3psPNfXZXuGjcQ5rSBi3MzigjBqqwQohWWetoRdLzT5v2uJq6KBTwxj1FYvuPTr7RoWkN4cmubDy5wW8SU3q9xYnDRpQepiT").unwrap();
+        let keys = [
+            KeyOriginInfo {
+                root_fingerprint: Some(Fingerprint::from_str("4c00739d").unwrap()),
+                keypath: Some("m/48'/1'/0'/3'".try_into().unwrap()),
+                xpub: our_xpub,
+            },
+            KeyOriginInfo {
+                root_fingerprint: None,
+                keypath: None,
+                xpub: some_xpub,
+            },
+        ];
+        for template in [
+            "wsh(or_b(pk(@0/<0;1>/*),s:pk(@1/<0;1>/*)))",
+            "tr(@0/<0;1>/*,pk(@1/<0;1>/*))",
+        ] {
+            let policy = crate::wallet_policy::WalletPolicy {
+                template: template.into(),
+                keys: keys.to_vec(),
+            };
+            let descriptor: miniscript::Descriptor<miniscript::DescriptorPublicKey> =
+                policy.to_descriptor().parse().unwrap();
+            let descriptors = descriptor.into_single_descriptors().unwrap();
+            for (change, descriptor) in descriptors.iter().enumerate() {
+                let expected = descriptor
+                    .at_derivation_index(5)
+                    .unwrap()
+                    .address(bitcoin::Network::Testnet)
+                    .unwrap()
+                    .to_string();
+                assert_eq!(
+                    derive_address(
+                        pb::BtcCoin::Tbtc,
+                        &format!("m/48'/1'/0'/3'/{}/5", change)
+                            .as_str()
+                            .try_into()
+                            .unwrap(),
+                        &policy.script_config(),
+                        None,
+                    )
+                    .unwrap(),
+                    expected
+                );
             }
-            _ => Err(Error::UnexpectedResponse),
         }
     }
 
-    /// Before a multisig or policy script config can be used to display receive addresses or sign
-    /// transcations, it must be registered on the device.
-    ///
-    /// If no name is provided, the user will be asked to enter it on the device instead.  If
-    /// provided, it must be non-empty, smaller or equal to 30 chars, consist only of printable
-    /// ASCII characters, and contain no whitespace other than spaces.
-    ///
-    ///
-    /// `keypath_account` must be set if the script config is multisig, and can be `None` if it is a
-    /// policy.
-    pub async fn btc_register_script_config(
-        &self,
-        coin: pb::BtcCoin,
-        script_config: &pb::BtcScriptConfig,
-        keypath_account: Option<&Keypath>,
-        xpub_type: pb::btc_register_script_config_request::XPubType,
-        name: Option<&str>,
-    ) -> Result<(), Error> {
-        match self
-            .query_proto_btc(pb::btc_request::Request::RegisterScriptConfig(
-                pb::BtcRegisterScriptConfigRequest {
-                    registration: Some(pb::BtcScriptConfigRegistration {
-                        coin: coin as _,
-                        script_config: Some(script_config.clone()),
-                        keypath: keypath_account.map_or(vec![], |kp| kp.to_vec()),
-                    }),
-                    name: name.unwrap_or("").into(),
-                    xpub_type: xpub_type as _,
+    #[test]
+    fn test_verify_message_signature() {
+        use bitcoin::hashes::Hash;
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let sign = |keypath: &str, msg_hash: [u8; 32]| {
+            let privkey = abandon_xprv_at(keypath).private_key;
+            let (recid, sig) = secp
+                .sign_ecdsa_recoverable(
+                    &bitcoin::secp256k1::Message::from_digest(msg_hash),
+                    &privkey,
+                )
+                .serialize_compact();
+            let recid = recid.to_i32() as u8;
+            let mut electrum_sig65 = vec![27 + 4 + recid];
+            electrum_sig65.extend_from_slice(&sig);
+            SignMessageSignature {
+                sig: sig.to_vec(),
+                recid,
+                electrum_sig65,
+            }
+        };
+        let msg_hash =
+            bitcoin::hashes::sha256d::Hash::hash(b"\x18Bitcoin Signed Message:\n\x07message")
+                .to_byte_array();
+        let p2wpkh = make_script_config_simple(pb::btc_script_config::SimpleType::P2wpkh);
+        let p2wpkh_p2sh = make_script_config_simple(pb::btc_script_config::SimpleType::P2wpkhP2sh);
+
+        let signature = sign("m/84'/0'/0'/0/0", msg_hash);
+        let address = "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu";
+        assert!(verify_message_signature(
+            pb::BtcCoin::Btc,
+            &p2wpkh,
+            b"message",
+            &signature,
+            address
+        )
+        .is_ok());
+        // Wrong message.
+        assert!(matches!(
+            verify_message_signature(pb::BtcCoin::Btc, &p2wpkh, b"massage", &signature, address),
+            Err(Error::SignatureVerification(_))
+        ));
+        // Wrong script type.
+        assert!(matches!(
+            verify_message_signature(
+                pb::BtcCoin::Btc,
+                &p2wpkh_p2sh,
+                b"message",
+                &signature,
+                address
+            ),
+            Err(Error::SignatureVerification(_))
+        ));
+
+        let signature = sign("m/49'/0'/0'/0/0", msg_hash);
+        assert!(verify_message_signature(
+            pb::BtcCoin::Btc,
+            &p2wpkh_p2sh,
+            b"message",
+            &signature,
+            "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf"
+        )
+        .is_ok());
+
+        // Uncompressed pubkey flag.
+        let mut uncompressed = signature.clone();
+        uncompressed.electrum_sig65[0] -= 4;
+        assert!(matches!(
+            verify_message_signature(
+                pb::BtcCoin::Btc,
+                &p2wpkh_p2sh,
+                b"message",
+                &uncompressed,
+                "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf"
+            ),
+            Err(Error::SignatureVerification(_))
+        ));
+    }
+
+    fn validation_test_transaction() -> Transaction {
+        let prev_tx = PrevTx {
+            version: 1,
+            inputs: vec![PrevTxInput {
+                prev_out_hash: vec![b'1'; 32],
+                prev_out_index: 0,
+                signature_script: b"some signature script".to_vec(),
+                sequence: 0xFFFFFFFF,
+            }],
+            outputs: vec![PrevTxOutput {
+                value: 60005000,
+                pubkey_script: b"some pubkey script".to_vec(),
+            }],
+            locktime: 0,
+        };
+        let input = |keypath: &str, script_config_index| TxInput {
+            prev_out_hash: hex::decode(
+                "c58b7e3f1200e0c0ec9a5e81e925baface2cc1d4715514f2d8205be2508b48ee",
+            )
+            .unwrap(),
+            prev_out_index: 0,
+            prev_out_value: 60005000,
+            sequence: 0xFFFFFFFF,
+            keypath: keypath.try_into().unwrap(),
+            script_config_index,
+            prev_tx: Some(prev_tx.clone()),
+            silent_payment_pubkey: None,
+        };
+        Transaction {
+            script_configs: vec![
+                pb::BtcScriptConfigWithKeypath {
+                    script_config: Some(make_script_config_simple(
+                        pb::btc_script_config::SimpleType::P2wpkh,
+                    )),
+                    keypath: Keypath::try_from("m/84'/0'/0'").unwrap().to_vec(),
                 },
-            ))
-            .await?
-        {
-            pb::btc_response::Response::Success(_) => Ok(()),
-            _ => Err(Error::UnexpectedResponse),
+                pb::BtcScriptConfigWithKeypath {
+                    script_config: Some(make_script_config_simple(
+                        pb::btc_script_config::SimpleType::P2wpkhP2sh,
+                    )),
+                    keypath: Keypath::try_from("m/49'/0'/0'").unwrap().to_vec(),
+                },
+            ],
+            output_script_configs: vec![],
+            version: 1,
+            inputs: vec![input("m/84'/0'/0'/0/0", 0), input("m/49'/0'/0'/0/1", 1)],
+            outputs: vec![
+                TxOutput::Internal(TxInternalOutput {
+                    keypath: "m/84'/0'/0'/1/0".try_into().unwrap(),
+                    value: 100000000,
+                    script_config_index: 0,
+                    output_script_config_index: None,
+                }),
+                TxOutput::External(TxExternalOutput {
+                    payload: Payload {
+                        data: vec![1; 32],
+                        output_type: pb::BtcOutputType::P2wsh,
+                    },
+                    value: 20000000,
+                    payment_request_index: None,
+                }),
+            ],
+            locktime: 0,
+            payment_requests: vec![],
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::keypath::HARDENED;
 
     #[test]
-    fn test_payload_from_pkscript() {
-        use std::str::FromStr;
-        // P2PKH
-        let addr = bitcoin::Address::from_str("1AMZK8xzHJWsuRErpGZTiW4jKz8fdfLUGE")
-            .unwrap()
-            .assume_checked();
-        let pkscript = addr.script_pubkey().into_bytes();
+    fn test_transaction_validate() {
+        let version = semver::Version::new(9, 24, 0);
+        let validate = |tx: &Transaction| {
+            tx.validate(pb::BtcCoin::Btc, crate::Product::BitBox02Multi, &version)
+        };
+        assert_eq!(validate(&validation_test_transaction()), Ok(()));
+
         assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: pkscript[3..23].to_vec(),
-                output_type: pb::BtcOutputType::P2pkh,
-            }
+            validation_test_transaction().validate(
+                pb::BtcCoin::Btc,
+                crate::Product::BitBox02Multi,
+                &semver::Version::new(9, 3, 0)
+            ),
+            Err(TxValidationError::Version(">=9.4.0"))
         );
-
-        // P2SH
-        let addr = bitcoin::Address::from_str("3JFL8CgtV4ZtMFYeP5LgV4JppLkHw5Gw9T")
-            .unwrap()
-            .assume_checked();
-        let pkscript = addr.script_pubkey().into_bytes();
         assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: pkscript[2..22].to_vec(),
-                output_type: pb::BtcOutputType::P2sh,
-            }
+            validation_test_transaction().validate(
+                pb::BtcCoin::Ltc,
+                crate::Product::BitBox02BtcOnly,
+                &version
+            ),
+            Err(TxValidationError::UnsupportedCoin)
         );
 
-        // P2WPKH
-        let addr = bitcoin::Address::from_str("bc1qkl8ms75cq6ajxtny7e88z3u9hkpkvktt5jwh6u")
-            .unwrap()
-            .assume_checked();
-        let pkscript = addr.script_pubkey().into_bytes();
+        let mut tx = validation_test_transaction();
+        tx.version = 3;
+        assert_eq!(validate(&tx), Err(TxValidationError::InvalidVersion(3)));
+
+        let mut tx = validation_test_transaction();
+        tx.inputs.clear();
+        assert_eq!(validate(&tx), Err(TxValidationError::NoInputs));
+
+        let mut tx = validation_test_transaction();
+        tx.script_configs[1] = pb::BtcScriptConfigWithKeypath {
+            script_config: Some(make_script_config_multisig(
+                1,
+                &[],
+                0,
+                pb::btc_script_config::multisig::ScriptType::P2wsh,
+            )),
+            keypath: Keypath::try_from("m/48'/0'/0'/2'").unwrap().to_vec(),
+        };
+        assert_eq!(validate(&tx), Err(TxValidationError::MixedScriptConfigs));
+
+        // Coin type mismatch, account out of range and purpose mismatch.
+        for keypath in ["m/84'/1'/0'", "m/84'/0'/100'", "m/49'/0'/0'"] {
+            let mut tx = validation_test_transaction();
+            tx.script_configs[0].keypath = Keypath::try_from(keypath).unwrap().to_vec();
+            assert_eq!(
+                validate(&tx),
+                Err(TxValidationError::InvalidScriptConfig(0))
+            );
+        }
+
+        let mut tx = validation_test_transaction();
+        tx.inputs[1].script_config_index = 2;
         assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: pkscript[2..].to_vec(),
-                output_type: pb::BtcOutputType::P2wpkh,
-            }
+            validate(&tx),
+            Err(TxValidationError::InputScriptConfigIndex(1))
         );
 
-        // P2WSH
-        let addr = bitcoin::Address::from_str(
-            "bc1q2fhgukymf0caaqrhfxrdju4wm94wwrch2ukntl5fuc0faz8zm49q0h6ss8",
-        )
-        .unwrap()
-        .assume_checked();
-        let pkscript = addr.script_pubkey().into_bytes();
+        let mut tx = validation_test_transaction();
+        tx.inputs[0].sequence = 0xFFFFFFFD;
+        assert_eq!(validate(&tx), Ok(()));
+        tx.inputs[0].sequence = 0xFFFFFFFC;
+        assert_eq!(validate(&tx), Err(TxValidationError::InputSequence(0)));
+
+        for keypath in [
+            "m/84'/0'/0'/2/0",
+            "m/84'/0'/0'/0/10000",
+            "m/84'/0'/1'/0/0",
+            "m/84'/0'/0'/0",
+        ] {
+            let mut tx = validation_test_transaction();
+            tx.inputs[0].keypath = keypath.try_into().unwrap();
+            assert_eq!(validate(&tx), Err(TxValidationError::InputKeypath(0)));
+        }
+
+        let mut tx = validation_test_transaction();
+        tx.inputs[0].prev_tx = None;
+        assert_eq!(validate(&tx), Err(TxValidationError::InputMissingPrevTx(0)));
+
+        let mut tx = validation_test_transaction();
+        tx.inputs[0].prev_out_value += 1;
+        assert_eq!(validate(&tx), Err(TxValidationError::InputInvalidPrevTx(0)));
+
+        let mut tx = validation_test_transaction();
+        tx.inputs[0].prev_out_hash = vec![0; 32];
+        assert_eq!(validate(&tx), Err(TxValidationError::InputInvalidPrevTx(0)));
+
+        let mut tx = validation_test_transaction();
+        if let TxOutput::Internal(output) = &mut tx.outputs[0] {
+            output.output_script_config_index = Some(0);
+        }
         assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: pkscript[2..].to_vec(),
-                output_type: pb::BtcOutputType::P2wsh,
-            }
+            validate(&tx),
+            Err(TxValidationError::OutputScriptConfigIndex(0))
         );
 
-        // P2TR
-        let addr = bitcoin::Address::from_str(
-            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
-        )
-        .unwrap()
-        .assume_checked();
-        let pkscript = addr.script_pubkey().into_bytes();
+        let mut tx = validation_test_transaction();
+        let mut output_script_config = tx.script_configs[0].clone();
+        output_script_config.keypath = Keypath::try_from("m/84'/1'/0'").unwrap().to_vec();
+        tx.output_script_configs.push(output_script_config);
         assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: pkscript[2..].to_vec(),
-                output_type: pb::BtcOutputType::P2tr,
-            }
+            validate(&tx),
+            Err(TxValidationError::InvalidOutputScriptConfig(0))
         );
 
-        // OP_RETURN empty (OP_0)
-        let pkscript = hex::decode("6a00").unwrap();
-        assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: Vec::new(),
+        let mut tx = validation_test_transaction();
+        if let TxOutput::Internal(output) = &mut tx.outputs[0] {
+            output.keypath = "m/49'/0'/0'/1/0".try_into().unwrap();
+        }
+        assert_eq!(validate(&tx), Err(TxValidationError::OutputKeypath(0)));
+
+        let mut tx = validation_test_transaction();
+        if let TxOutput::External(output) = &mut tx.outputs[1] {
+            output.payload.output_type = pb::BtcOutputType::P2wpkh;
+        }
+        assert_eq!(validate(&tx), Err(TxValidationError::OutputPayload(1)));
+
+        let mut tx = validation_test_transaction();
+        if let TxOutput::External(output) = &mut tx.outputs[1] {
+            output.value = 0;
+        }
+        assert_eq!(validate(&tx), Err(TxValidationError::OutputZeroValue(1)));
+
+        let mut tx = validation_test_transaction();
+        tx.outputs.push(TxOutput::External(TxExternalOutput {
+            payload: Payload {
+                data: vec![0xaa; 80],
                 output_type: pb::BtcOutputType::OpReturn,
-            }
+            },
+            value: 0,
+            payment_request_index: None,
+        }));
+        assert_eq!(validate(&tx), Ok(()));
+        assert_eq!(
+            tx.validate(
+                pb::BtcCoin::Btc,
+                crate::Product::BitBox02Multi,
+                &semver::Version::new(9, 23, 0)
+            ),
+            Err(TxValidationError::Version(">=9.24.0"))
         );
+        if let TxOutput::External(output) = &mut tx.outputs[2] {
+            output.payload.data.push(0xaa);
+        }
+        assert_eq!(validate(&tx), Err(TxValidationError::OutputOpReturn(2)));
 
-        // OP_RETURN with data push
-        let pkscript = hex::decode("6a03aabbcc").unwrap();
+        let mut tx = validation_test_transaction();
+        if let TxOutput::External(output) = &mut tx.outputs[1] {
+            output.payment_request_index = Some(0);
+        }
         assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: vec![0xaa, 0xbb, 0xcc],
-                output_type: pb::BtcOutputType::OpReturn,
-            }
+            validate(&tx),
+            Err(TxValidationError::OutputPaymentRequestIndex(1))
         );
+    }
 
-        // OP_RETURN with 80-byte payload (PUSHDATA1)
-        let mut pkscript = vec![opcodes::all::OP_RETURN.to_u8(), 0x4c, 0x50];
-        pkscript.extend(std::iter::repeat_n(0xaa, 80));
+    #[test]
+    fn test_transaction_summary() {
+        use bitcoin::hashes::Hash;
+
+        let tx = validation_test_transaction();
+        let summary = tx.summary(pb::BtcCoin::Btc).unwrap();
+        let p2wsh_address = bitcoin::Address::from_script(
+            &bitcoin::ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::from_byte_array([1; 32])),
+            bitcoin::Network::Bitcoin,
+        )
+        .unwrap()
+        .to_string();
         assert_eq!(
-            Payload::from_pkscript(&pkscript).unwrap(),
-            Payload {
-                data: vec![0xaa; 80],
-                output_type: pb::BtcOutputType::OpReturn,
+            summary,
+            TxSummary {
+                outputs: vec![
+                    OutputSummary {
+                        kind: OutputKind::Change,
+                        value: 100000000,
+                    },
+                    OutputSummary {
+                        kind: OutputKind::External {
+                            address: p2wsh_address,
+                        },
+                        value: 20000000,
+                    },
+                ],
+                total_in: 120010000,
+                total_out: 120000000,
+                amount_sent: 20000000,
+                fee: 10000,
+                // 42 WU base, 272 WU p2wpkh input, 364 WU p2wpkh-p2sh input, 124 WU p2wpkh
+                // output and 172 WU p2wsh output.
+                vsize: Some(244),
+                fee_rate: Some(10000.0 / 244.0),
+                warnings: vec![TxWarning::MixedScriptTypes],
             }
         );
 
-        // Invalid OP_RETURN scripts
-        let pkscript = hex::decode("6a").unwrap();
-        assert!(matches!(
-            Payload::from_pkscript(&pkscript),
-            Err(PayloadError::InvalidOpReturn(
-                "naked OP_RETURN is not supported"
-            ))
-        ));
+        // Receive address of the same account, other account, p2pkh and OP_RETURN outputs, high
+        // fee.
+        let mut tx = validation_test_transaction();
+        tx.inputs.truncate(1);
+        tx.output_script_configs = vec![pb::BtcScriptConfigWithKeypath {
+            script_config: Some(make_script_config_simple(
+                pb::btc_script_config::SimpleType::P2tr,
+            )),
+            keypath: Keypath::try_from("m/86'/0'/1'").unwrap().to_vec(),
+        }];
+        tx.outputs = vec![
+            TxOutput::Internal(TxInternalOutput {
+                keypath: "m/84'/0'/0'/0/5".try_into().unwrap(),
+                value: 1000,
+                script_config_index: 0,
+                output_script_config_index: None,
+            }),
+            TxOutput::Internal(TxInternalOutput {
+                keypath: "m/86'/0'/1'/0/0".try_into().unwrap(),
+                value: 2000,
+                script_config_index: 0,
+                output_script_config_index: Some(0),
+            }),
+            TxOutput::External(TxExternalOutput {
+                payload: Payload {
+                    data: hex::decode("62e907b15cbf27d5425399ebf6f0fb50ebb88f18").unwrap(),
+                    output_type: pb::BtcOutputType::P2pkh,
+                },
+                value: 50000000,
+                payment_request_index: None,
+            }),
+            TxOutput::External(TxExternalOutput {
+                payload: Payload {
+                    data: b"hello".to_vec(),
+                    output_type: pb::BtcOutputType::OpReturn,
+                },
+                value: 0,
+                payment_request_index: None,
+            }),
+        ];
+        let summary = tx.summary(pb::BtcCoin::Btc).unwrap();
+        assert_eq!(
+            summary
+                .outputs
+                .iter()
+                .map(|output| output.kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                OutputKind::SameAccount,
+                OutputKind::OtherAccount {
+                    account_keypath: "m/86'/0'/1'".try_into().unwrap(),
+                },
+                OutputKind::External {
+                    address: "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".into(),
+                },
+                OutputKind::OpReturn {
+                    data: b"hello".to_vec(),
+                },
+            ]
+        );
+        assert_eq!(summary.fee, 60005000 - 50003000);
+        assert_eq!(summary.amount_sent, 50000000);
+        assert_eq!(
+            summary.warnings,
+            vec![TxWarning::HighFee {
+                percentage: 100.0 * 10002000.0 / 50000000.0
+            }]
+        );
 
-        let pkscript = hex::decode("6a6a").unwrap();
-        assert!(matches!(
-            Payload::from_pkscript(&pkscript),
-            Err(PayloadError::InvalidOpReturn(
-                "no data push found after OP_RETURN"
-            ))
-        ));
+        // Inputs from two accounts of the same script type.
+        let mut tx = validation_test_transaction();
+        tx.script_configs[1] = pb::BtcScriptConfigWithKeypath {
+            script_config: Some(make_script_config_simple(
+                pb::btc_script_config::SimpleType::P2wpkh,
+            )),
+            keypath: Keypath::try_from("m/84'/0'/1'").unwrap().to_vec(),
+        };
+        tx.inputs[1].keypath = "m/84'/0'/1'/0/0".try_into().unwrap();
+        assert_eq!(tx.summary(pb::BtcCoin::Btc).unwrap().warnings, vec![]);
 
-        let pkscript = hex::decode("6a0000").unwrap();
+        // Litecoin addresses.
+        let summary = validation_test_transaction()
+            .summary(pb::BtcCoin::Ltc)
+            .unwrap();
         assert!(matches!(
-            Payload::from_pkscript(&pkscript),
-            Err(PayloadError::InvalidOpReturn(
-                "only one data push supported after OP_RETURN"
-            ))
+            &summary.outputs[1].kind,
+            OutputKind::External { address } if address.starts_with("ltc1q")
         ));
-    }
 
-    // Test that a PSBT containing only p2wpkh inputs is converted correctly to a transaction to be
-    // signed by the BitBox.
-    #[test]
-    fn test_transaction_from_psbt_p2wpkh() {
-        use std::str::FromStr;
+        // The input value is taken from the previous transaction.
+        let mut tx = validation_test_transaction();
+        tx.inputs[0].prev_out_value = 0;
+        assert_eq!(tx.summary(pb::BtcCoin::Btc).unwrap().total_in, 120010000);
 
-        // Based on mnemonic:
-        // route glue else try obey local kidney future teach unaware pulse exclude.
-        let psbt_str = "cHNidP8BAHECAAAAAfbXTun4YYxDroWyzRq3jDsWFVlsZ7HUzxiORY/iR4goAAAAAAD9////AuLCAAAAAAAAFgAUg3w5W0zt3AmxRmgA5Q6wZJUDRhUowwAAAAAAABYAFJjQqUoXDcwUEqfExu9pnaSn5XBct0ElAAABAR+ghgEAAAAAABYAFHn03igII+hp819N2Zlb5LnN8atRAQDfAQAAAAABAZ9EJlMJnXF5bFVrb1eFBYrEev3pg35WpvS3RlELsMMrAQAAAAD9////AqCGAQAAAAAAFgAUefTeKAgj6GnzX03ZmVvkuc3xq1EoRs4JAAAAABYAFKG2PzjYjknaA6lmXFqPaSgHwXX9AkgwRQIhAL0v0r3LisQ9KOlGzMhM/xYqUmrv2a5sORRlkX1fqDC8AiB9XqxSNEdb4mPnp7ylF1cAlbAZ7jMhgIxHUXylTww3bwEhA0AEOM0yYEpexPoKE3vT51uxZ+8hk9sOEfBFKOeo6oDDAAAAACIGAyNQfmAT/YLmZaxxfDwClmVNt2BkFnfQu/i8Uc/hHDUiGBKiwYlUAACAAQAAgAAAAIAAAAAAAAAAAAAAIgIDnxFM7Qr9LvJwQDB9GozdTRIe3MYVuHOqT7dU2EuvHrIYEqLBiVQAAIABAACAAAAAgAEAAAAAAAAAAA==";
+        // Multisig inputs: the size can't be estimated.
+        let mut tx = validation_test_transaction();
+        tx.script_configs[0].script_config = Some(make_script_config_multisig(
+            1,
+            &[],
+            0,
+            pb::btc_script_config::multisig::ScriptType::P2wsh,
+        ));
+        let summary = tx.summary(pb::BtcCoin::Btc).unwrap();
+        assert_eq!(summary.vsize, None);
+        assert_eq!(summary.fee_rate, None);
 
-        let expected_transaction = Transaction {
-            script_configs: vec![pb::BtcScriptConfigWithKeypath {
-                script_config: Some(pb::BtcScriptConfig {
-                    config: Some(pb::btc_script_config::Config::SimpleType(
-                        pb::btc_script_config::SimpleType::P2wpkh as _,
-                    )),
-                }),
-                keypath: vec![84 + HARDENED, 1 + HARDENED, HARDENED],
-            }],
-            version: 2,
-            inputs: vec![TxInput {
-                prev_out_hash: vec![
-                    246, 215, 78, 233, 248, 97, 140, 67, 174, 133, 178, 205, 26, 183, 140, 59, 22,
-                    21, 89, 108, 103, 177, 212, 207, 24, 142, 69, 143, 226, 71, 136, 40,
-                ],
-                prev_out_index: 0,
-                prev_out_value: 100000,
-                sequence: 4294967293,
-                keypath: "m/84'/1'/0'/0/0".try_into().unwrap(),
-                script_config_index: 0,
-                prev_tx: Some(PrevTx {
-                    version: 1,
-                    inputs: vec![PrevTxInput {
-                        prev_out_hash: vec![
-                            159, 68, 38, 83, 9, 157, 113, 121, 108, 85, 107, 111, 87, 133, 5, 138,
-                            196, 122, 253, 233, 131, 126, 86, 166, 244, 183, 70, 81, 11, 176, 195,
-                            43,
-                        ],
-                        prev_out_index: 1,
-                        signature_script: vec![],
-                        sequence: 4294967293,
-                    }],
-                    outputs: vec![
-                        PrevTxOutput {
-                            value: 100000,
-                            pubkey_script: vec![
-                                0, 20, 121, 244, 222, 40, 8, 35, 232, 105, 243, 95, 77, 217, 153,
-                                91, 228, 185, 205, 241, 171, 81,
-                            ],
-                        },
-                        PrevTxOutput {
-                            value: 164513320,
-                            pubkey_script: vec![
-                                0, 20, 161, 182, 63, 56, 216, 142, 73, 218, 3, 169, 102, 92, 90,
-                                143, 105, 40, 7, 193, 117, 253,
-                            ],
-                        },
-                    ],
-                    locktime: 0,
-                }),
-            }],
-            outputs: vec![
-                TxOutput::External(TxExternalOutput {
-                    payload: Payload {
-                        data: vec![
-                            131, 124, 57, 91, 76, 237, 220, 9, 177, 70, 104, 0, 229, 14, 176, 100,
-                            149, 3, 70, 21,
-                        ],
-                        output_type: pb::BtcOutputType::P2wpkh,
-                    },
-                    value: 49890,
-                }),
-                TxOutput::Internal(TxInternalOutput {
-                    keypath: "m/84'/1'/0'/1/0".try_into().unwrap(),
-                    value: 49960,
-                    script_config_index: 0,
-                }),
-            ],
-            locktime: 2441655,
-        };
-        let our_root_fingerprint = hex::decode("12a2c189").unwrap();
-        let psbt = bitcoin::psbt::Psbt::from_str(psbt_str).unwrap();
-        let (transaction, _our_keys) =
-            Transaction::from_psbt(&our_root_fingerprint, &psbt, None).unwrap();
-        assert_eq!(transaction, expected_transaction);
+        let mut tx = validation_test_transaction();
+        if let TxOutput::External(output) = &mut tx.outputs[1] {
+            output.value = 30000000;
+        }
+        assert_eq!(
+            tx.summary(pb::BtcCoin::Btc),
+            Err(TxValidationError::InsufficientInputs)
+        );
     }
 }
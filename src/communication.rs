@@ -6,7 +6,6 @@ use crate::util::Threading;
 use async_trait::async_trait;
 use thiserror::Error;
 
-#[cfg(any(feature = "wasm", feature = "usb", feature = "simulator"))]
 pub const FIRMWARE_CMD: u8 = 0x80 + 0x40 + 0x01;
 
 #[derive(Error, Debug)]
@@ -157,14 +156,17 @@ pub enum Product {
     BitBox02NovaBtcOnly,
 }
 
-#[derive(Debug)]
+/// Device information which is available before pairing.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Info {
+    /// Firmware version.
     pub version: semver::Version,
+    /// Which product the device is.
     pub product: Product,
-    #[allow(dead_code)]
+    /// True if the device is unlocked, i.e. the password was entered.
     pub unlocked: bool,
-    // Is None before firmware version 9.20.0.
-    #[allow(dead_code)]
+    /// True if the device is initialized, i.e. has a seed and a backup. Is None before firmware
+    /// version 9.20.0.
     pub initialized: Option<bool>,
 }
 
@@ -174,7 +176,7 @@ pub struct HwwCommunication<R: Runtime> {
     marker: std::marker::PhantomData<R>,
 }
 
-async fn get_info(communication: &dyn ReadWrite) -> Result<Info, Error> {
+pub async fn get_info(communication: &dyn ReadWrite) -> Result<Info, Error> {
     let response = communication.query(&[HWW_INFO]).await?;
     let (version_str_len, response) = (
         *response.first().ok_or(Error::Info)? as usize,
@@ -271,3 +273,86 @@ impl<R: Runtime> HwwCommunication<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockReadWrite(Vec<u8>);
+
+    impl Threading for MockReadWrite {}
+
+    #[cfg_attr(feature = "multithreaded", async_trait)]
+    #[cfg_attr(not(feature="multithreaded"), async_trait(?Send))]
+    impl ReadWrite for MockReadWrite {
+        fn write(&self, msg: &[u8]) -> Result<usize, Error> {
+            assert_eq!(msg, &[HWW_INFO]);
+            Ok(msg.len())
+        }
+
+        async fn read(&self) -> Result<Vec<u8>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn info_response(version: &str, rest: &[u8]) -> MockReadWrite {
+        let mut response = vec![version.len() as u8];
+        response.extend_from_slice(version.as_bytes());
+        response.extend_from_slice(rest);
+        MockReadWrite(response)
+    }
+
+    #[tokio::test]
+    async fn test_get_info() {
+        let info = get_info(&info_response("v9.20.0", &[0x02, 0x00, 0x00, 0x01]))
+            .await
+            .unwrap();
+        assert_eq!(
+            info,
+            Info {
+                version: semver::Version::new(9, 20, 0),
+                product: Product::BitBox02NovaMulti,
+                unlocked: false,
+                initialized: Some(true),
+            }
+        );
+
+        // No initialized byte before v9.20.0.
+        let info = get_info(&info_response("v9.19.0", &[0x00, 0x01, 0x01]))
+            .await
+            .unwrap();
+        assert_eq!(
+            info,
+            Info {
+                version: semver::Version::new(9, 19, 0),
+                product: Product::BitBox02BtcOnly,
+                unlocked: true,
+                initialized: None,
+            }
+        );
+
+        // Unknown platform.
+        let info = get_info(&info_response("v9.20.0", &[0x05, 0x00, 0x01, 0x00]))
+            .await
+            .unwrap();
+        assert_eq!(info.product, Product::Unknown);
+        assert_eq!(info.initialized, Some(false));
+
+        // Invalid responses.
+        assert!(get_info(&info_response("9.20.0", &[0x00, 0x00, 0x00]))
+            .await
+            .is_err());
+        assert!(get_info(&info_response("v9.20.0", &[0x00, 0x00]))
+            .await
+            .is_err());
+        assert!(get_info(&info_response("v9.20.0", &[0x00, 0x00, 0x02]))
+            .await
+            .is_err());
+        assert!(
+            get_info(&info_response("v9.20.0", &[0x00, 0x00, 0x00, 0x02]))
+                .await
+                .is_err()
+        );
+        assert!(get_info(&MockReadWrite(vec![])).await.is_err());
+    }
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sets up a multisig or policy wallet on several BitBoxes at once: the xpubs are collected from
+//! each device, the script config is assembled for each device (with its own `our_xpub_index`) and
+//! registered on each device where it is not registered yet.
+
+use bitcoin::bip32::Fingerprint;
+
+use std::str::FromStr;
+
+use crate::btc::KeyOriginInfo;
+use crate::error::Error;
+use crate::pb;
+use crate::pb::btc_register_script_config_request::XPubType;
+use crate::pb::btc_script_config::multisig::ScriptType;
+use crate::runtime::Runtime;
+use crate::wallet_export::{Wallet, WalletScript};
+use crate::{Keypath, PairedBitBox};
+
+async fn root_fingerprint<R: Runtime>(device: &PairedBitBox<R>) -> Result<Fingerprint, Error> {
+    Fingerprint::from_str(&device.root_fingerprint().await?).map_err(|_| Error::UnexpectedResponse)
+}
+
+/// Returns the key of `device` at `account_keypath` with its origin info, e.g. to assemble a
+/// policy involving several BitBoxes.
+pub async fn device_key<R: Runtime>(
+    device: &PairedBitBox<R>,
+    coin: pb::BtcCoin,
+    account_keypath: &Keypath,
+) -> Result<KeyOriginInfo, Error> {
+    Ok(KeyOriginInfo {
+        root_fingerprint: Some(root_fingerprint(device).await?),
+        keypath: Some(account_keypath.clone()),
+        xpub: device.btc_account_xpub(coin, account_keypath).await?,
+    })
+}
+
+/// Registers `wallet` on all `devices` on which it is not registered yet. Each device must hold
+/// one of the keys of the wallet, identified by its root fingerprint.
+///
+/// Returns for each device whether the wallet was registered by this call (`false` if it already
+/// was registered).
+pub async fn register_wallet<R: Runtime>(
+    devices: &[&PairedBitBox<R>],
+    wallet: &Wallet,
+) -> Result<Vec<bool>, Error> {
+    // Assemble all script configs first, so that nothing is registered if the wallet does not
+    // fit one of the devices.
+    let mut script_configs = Vec::with_capacity(devices.len());
+    for device in devices {
+        script_configs.push(wallet.script_config(&root_fingerprint(device).await?)?);
+    }
+    let mut registered = Vec::with_capacity(devices.len());
+    for (device, (script_config, keypath_account)) in devices.iter().zip(&script_configs) {
+        if device
+            .btc_is_script_config_registered(wallet.coin, script_config, keypath_account.as_ref())
+            .await?
+        {
+            registered.push(false);
+            continue;
+        }
+        device
+            .btc_register_script_config(
+                wallet.coin,
+                script_config,
+                keypath_account.as_ref(),
+                wallet.xpub_type,
+                (!wallet.name.is_empty()).then_some(wallet.name.as_str()),
+            )
+            .await?;
+        registered.push(true);
+    }
+    Ok(registered)
+}
+
+/// Parameters of a multisig wallet set up with `setup_multisig()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultisigSetup {
+    pub coin: pb::BtcCoin,
+    /// Number of signatures required to spend.
+    pub threshold: u32,
+    pub script_type: ScriptType,
+    /// Account keypath of the keys on the devices, e.g. `m/48'/0'/0'/2'`.
+    pub account_keypath: Keypath,
+    pub xpub_type: XPubType,
+    /// Name of the wallet shown on the devices. Empty to let the user enter a name on each device.
+    pub name: String,
+}
+
+/// Sets up a `setup.threshold`-of-n multisig wallet between `devices` and the external
+/// `cosigners`: the xpub at `setup.account_keypath` is fetched from each device, and the wallet is
+/// registered on each device using `register_wallet()`.
+///
+/// The keys of the returned wallet are sorted by their serialized xpub, the order in which
+/// `btc_sign_psbt()` infers multisig script configs from PSBTs, so that the registered script
+/// config is found when signing. Its descriptor (`Wallet::descriptor()`) or one of the export
+/// formats of `wallet_export` can be used to set up the wallet in other software.
+pub async fn setup_multisig<R: Runtime>(
+    devices: &[&PairedBitBox<R>],
+    cosigners: &[KeyOriginInfo],
+    setup: &MultisigSetup,
+) -> Result<Wallet, Error> {
+    let mut keys = Vec::with_capacity(devices.len() + cosigners.len());
+    for device in devices {
+        keys.push(device_key(device, setup.coin, &setup.account_keypath).await?);
+    }
+    keys.extend_from_slice(cosigners);
+    keys.sort_by_key(|key| key.xpub.encode());
+    let wallet = Wallet {
+        name: setup.name.clone(),
+        coin: setup.coin,
+        xpub_type: setup.xpub_type,
+        script: WalletScript::Multisig {
+            threshold: setup.threshold,
+            script_type: setup.script_type,
+            keys,
+        },
+    };
+    register_wallet(devices, &wallet).await?;
+    Ok(wallet)
+}
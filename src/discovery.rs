@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Account discovery when restoring a wallet, following the BIP-44 account discovery procedure,
+//! see <https://github.com/bitcoin/bips/blob/master/bip-0044.mediawiki#account-discovery>.
+//!
+//! The account xpubs are fetched from the BitBox in batches (see
+//! `PairedBitBox::btc_discover_accounts()`), the receive and change addresses are derived on the
+//! host and their usage is looked up in a `UtxoSource`, e.g. an Electrum server or a block
+//! explorer.
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use bitcoin::bip32::Xpub;
+
+use std::collections::HashSet;
+
+use crate::btc::{derive_address, AddressError};
+use crate::keypath::HARDENED;
+use crate::pb;
+use crate::{Keypath, Threading};
+
+/// Number of consecutive unused addresses after which a chain is considered to be fully scanned.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Largest accepted gap limit, which bounds the number of addresses looked up per batch.
+pub const MAX_GAP_LIMIT: u32 = 1000;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("the gap limit must be between 1 and {MAX_GAP_LIMIT}")]
+    InvalidGapLimit,
+    #[error("address derivation failed: {0}")]
+    Address(#[from] AddressError),
+    #[error("UTXO source error: {0}")]
+    Source(String),
+}
+
+/// Provides the usage information of addresses during account discovery.
+#[cfg_attr(feature = "multithreaded", async_trait)]
+#[cfg_attr(not(feature="multithreaded"), async_trait(?Send))]
+pub trait UtxoSource: Threading {
+    /// Returns for each of the given addresses whether it has a transaction history. The result
+    /// must have the same length and order as `addresses`.
+    async fn used(&self, addresses: &[String]) -> Result<Vec<bool>, String>;
+}
+
+/// `UtxoSource` backed by a fixed set of used addresses. Useful for tests and for wallets which
+/// already know their address history.
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryUtxoSource {
+    used: HashSet<String>,
+}
+
+impl InMemoryUtxoSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks an address as used.
+    pub fn insert(&mut self, address: &str) {
+        self.used.insert(address.into());
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for InMemoryUtxoSource {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        InMemoryUtxoSource {
+            used: iter.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Threading for InMemoryUtxoSource {}
+
+#[cfg_attr(feature = "multithreaded", async_trait)]
+#[cfg_attr(not(feature="multithreaded"), async_trait(?Send))]
+impl UtxoSource for InMemoryUtxoSource {
+    async fn used(&self, addresses: &[String]) -> Result<Vec<bool>, String> {
+        Ok(addresses
+            .iter()
+            .map(|address| self.used.contains(address))
+            .collect())
+    }
+}
+
+/// A used account found during account discovery.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredAccount {
+    /// Account keypath, e.g. `m/84'/0'/0'`.
+    pub keypath: Keypath,
+    pub script_config: pb::BtcScriptConfig,
+    pub xpub: Xpub,
+    /// Index of the receive address following the last used receive address.
+    pub next_receive_index: u32,
+    /// Index of the change address following the last used change address.
+    pub next_change_index: u32,
+}
+
+pub(crate) fn check_gap_limit(gap_limit: u32) -> Result<(), Error> {
+    if gap_limit == 0 || gap_limit > MAX_GAP_LIMIT {
+        return Err(Error::InvalidGapLimit);
+    }
+    Ok(())
+}
+
+/// Scans one chain (0 for receive, 1 for change) of an account until `gap_limit` consecutive
+/// unused addresses are found. Returns the index following the last used address, or 0 if no
+/// address of the chain is used. The scan also ends at the last unhardened index.
+async fn scan_chain(
+    coin: pb::BtcCoin,
+    account_keypath: &Keypath,
+    chain: u32,
+    script_config: &pb::BtcScriptConfig,
+    account_xpub: &Xpub,
+    source: &dyn UtxoSource,
+    gap_limit: u32,
+) -> Result<u32, Error> {
+    let mut next_index: u32 = 0;
+    let mut start: u32 = 0;
+    loop {
+        let end = start
+            .checked_add(gap_limit)
+            .map_or(HARDENED, |end| end.min(HARDENED));
+        let addresses = (start..end)
+            .map(|index| {
+                let mut keypath = account_keypath.to_vec();
+                keypath.extend([chain, index]);
+                derive_address(
+                    coin,
+                    &keypath.as_slice().into(),
+                    script_config,
+                    Some(account_xpub),
+                )
+            })
+            .collect::<Result<Vec<String>, AddressError>>()?;
+        let used = source.used(&addresses).await.map_err(Error::Source)?;
+        if used.len() != addresses.len() {
+            return Err(Error::Source(format!(
+                "expected {} results, got {}",
+                addresses.len(),
+                used.len()
+            )));
+        }
+        if let Some(last_used) = used.iter().rposition(|&used| used) {
+            next_index = start + last_used as u32 + 1;
+        }
+        start = end;
+        if start - next_index >= gap_limit || start == HARDENED {
+            return Ok(next_index);
+        }
+    }
+}
+
+/// Scans the receive and change addresses of an account. Returns `None` if none of its addresses
+/// have been used.
+///
+/// `account_xpub` is the xpub at `account_keypath`, e.g. as returned by
+/// `PairedBitBox::btc_xpubs()`.
+pub async fn scan_account(
+    coin: pb::BtcCoin,
+    account_keypath: &Keypath,
+    script_config: &pb::BtcScriptConfig,
+    account_xpub: &Xpub,
+    source: &dyn UtxoSource,
+    gap_limit: u32,
+) -> Result<Option<DiscoveredAccount>, Error> {
+    check_gap_limit(gap_limit)?;
+    let mut next_indices = [0u32; 2];
+    for (chain, next_index) in next_indices.iter_mut().enumerate() {
+        *next_index = scan_chain(
+            coin,
+            account_keypath,
+            chain as u32,
+            script_config,
+            account_xpub,
+            source,
+            gap_limit,
+        )
+        .await?;
+    }
+    let [next_receive_index, next_change_index] = next_indices;
+    if next_receive_index == 0 && next_change_index == 0 {
+        return Ok(None);
+    }
+    Ok(Some(DiscoveredAccount {
+        keypath: account_keypath.clone(),
+        script_config: script_config.clone(),
+        xpub: *account_xpub,
+        next_receive_index,
+        next_change_index,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btc::make_script_config_simple;
+    use std::str::FromStr;
+
+    // Account xpub at m/84'/0'/0' of the mnemonic "abandon abandon ... about", see BIP-84.
+    const XPUB: &str = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+
+    fn address(keypath: &str) -> String {
+        derive_address(
+            pb::BtcCoin::Btc,
+            &keypath.try_into().unwrap(),
+            &make_script_config_simple(pb::btc_script_config::SimpleType::P2wpkh),
+            Some(&Xpub::from_str(XPUB).unwrap()),
+        )
+        .unwrap()
+    }
+
+    async fn scan(
+        source: &InMemoryUtxoSource,
+        gap_limit: u32,
+    ) -> Result<Option<DiscoveredAccount>, Error> {
+        scan_account(
+            pb::BtcCoin::Btc,
+            &"m/84'/0'/0'".try_into().unwrap(),
+            &make_script_config_simple(pb::btc_script_config::SimpleType::P2wpkh),
+            &Xpub::from_str(XPUB).unwrap(),
+            source,
+            gap_limit,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_scan_account() {
+        assert_eq!(
+            address("m/84'/0'/0'/0/0"),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+
+        // Unused account.
+        assert_eq!(scan(&InMemoryUtxoSource::new(), 20).await, Ok(None));
+
+        // Used addresses within the gap limit are found, also across several batches.
+        let source: InMemoryUtxoSource = [
+            address("m/84'/0'/0'/0/0"),
+            address("m/84'/0'/0'/0/15"),
+            address("m/84'/0'/0'/0/30"),
+            address("m/84'/0'/0'/1/3"),
+        ]
+        .into_iter()
+        .collect();
+        let account = scan(&source, 20).await.unwrap().unwrap();
+        assert_eq!(account.keypath, "m/84'/0'/0'".try_into().unwrap());
+        assert_eq!(account.xpub.to_string(), XPUB);
+        assert_eq!(account.next_receive_index, 31);
+        assert_eq!(account.next_change_index, 4);
+
+        // With a smaller gap limit, the address at index 30 is not reached.
+        let account = scan(&source, 10).await.unwrap().unwrap();
+        assert_eq!(account.next_receive_index, 16);
+
+        // Only change addresses used.
+        let source: InMemoryUtxoSource = [address("m/84'/0'/0'/1/0")].into_iter().collect();
+        let account = scan(&source, 5).await.unwrap().unwrap();
+        assert_eq!(account.next_receive_index, 0);
+        assert_eq!(account.next_change_index, 1);
+
+        assert_eq!(scan(&source, 0).await, Err(Error::InvalidGapLimit));
+        assert_eq!(
+            scan(&source, MAX_GAP_LIMIT + 1).await,
+            Err(Error::InvalidGapLimit)
+        );
+        assert_eq!(scan(&source, u32::MAX).await, Err(Error::InvalidGapLimit));
+    }
+}
@@ -66,9 +66,17 @@ pub enum Error {
     #[error("noise config error: {0}")]
     #[cfg_attr(feature = "wasm", assoc(js_code = "noise-config".into()))]
     NoiseConfig(#[from] crate::noise::ConfigError),
+    /// Reading or storing the registry failed. If returned by `btc_register_script_config()`, the
+    /// device registration succeeded.
+    #[error("registry error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = String::from("registry-") + _0.js_code().into()))]
+    Registry(#[from] crate::registry::Error),
     #[error("pairing code rejected by user")]
     #[cfg_attr(feature = "wasm", assoc(js_code = "pairing-rejected".into()))]
     NoisePairingRejected,
+    #[error("failed generating host entropy")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "host-entropy".into()))]
+    HostEntropy,
     #[error("BitBox returned an unexpected response")]
     #[cfg_attr(feature = "wasm", assoc(js_code = "unexpected-response".into()))]
     UnexpectedResponse,
@@ -84,12 +92,48 @@ pub enum Error {
     #[error("PSBT error: {0}")]
     #[cfg_attr(feature = "wasm", assoc(js_code = String::from("psbt-") + _0.js_code().into()))]
     Psbt(#[from] crate::btc::PsbtError),
+    #[error("PSBTv2 error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "psbt-v2".into()))]
+    PsbtV2(#[from] crate::psbt_v2::Error),
+    #[error("transaction validation error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = String::from("tx-validation-") + _0.js_code().into()))]
+    TxValidation(#[from] crate::btc::TxValidationError),
+    #[error("could not assemble the signed transaction: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = String::from("assemble-tx-") + _0.js_code().into()))]
+    AssembleTx(#[from] crate::btc::AssembleTxError),
+    #[error("address error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = String::from("address-") + _0.js_code().into()))]
+    Address(#[from] crate::btc::AddressError),
+    #[error("BIP-322 error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "bip322".into()))]
+    Bip322(#[from] crate::bip322::Error),
+    #[error("wallet policy error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "wallet-policy".into()))]
+    WalletPolicy(#[from] crate::wallet_policy::Error),
+    #[error("wallet export error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "wallet-export".into()))]
+    WalletExport(#[from] crate::wallet_export::Error),
+    #[error("account discovery error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "discovery".into()))]
+    Discovery(#[from] crate::discovery::Error),
     #[error("Unexpected signature format returned by BitBox")]
     #[cfg_attr(feature = "wasm", assoc(js_code = "keypath-parse".into()))]
     InvalidSignature,
+    #[error("Signature verification failed: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "signature-verification".into()))]
+    SignatureVerification(String),
     #[error("Antiklepto verification failed: {0}")]
     #[cfg_attr(feature = "wasm", assoc(js_code = "antiklepto".into()))]
     AntiKlepto(#[from] crate::antiklepto::Error),
+    #[error("Silent payment error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "silent-payment".into()))]
+    SilentPayment(#[from] crate::silentpayments::Error),
+    #[error("bootloader error: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "bootloader".into()))]
+    Bootloader(#[from] crate::bootloader::Error),
+    #[error("invalid Bluetooth firmware: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "bluetooth-firmware".into()))]
+    BluetoothFirmware(&'static str),
     #[error("EIP-712 typed message processing error: {0}")]
     #[cfg_attr(feature = "wasm", assoc(js_code = "eth-typed-message".into()))]
     EthTypedMessage(String),
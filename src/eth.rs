@@ -147,6 +147,57 @@ pub fn eth_identify_case(recipient_address: &str) -> pb::EthAddressCase {
     }
 }
 
+fn keccak256(data: &[&[u8]]) -> [u8; 32] {
+    use tiny_keccak::{Hasher, Keccak};
+    let mut keccak = Keccak::v256();
+    for part in data {
+        keccak.update(part);
+    }
+    let mut output = [0u8; 32];
+    keccak.finalize(&mut output);
+    output
+}
+
+/// Verifies a signature returned by `PairedBitBox::eth_sign_message()` on the host: the pubkey is
+/// recovered from the signature over the EIP-191 prefixed message and the Ethereum address it
+/// corresponds to must be equal to `address` (hex with `0x` prefix, in any case).
+pub fn verify_message_signature(
+    msg: &[u8],
+    signature: &[u8; 65],
+    address: &str,
+) -> Result<(), Error> {
+    use bitcoin::secp256k1::{ecdsa, Message, Secp256k1};
+
+    let msg_hash = keccak256(&[
+        b"\x19Ethereum Signed Message:\n",
+        msg.len().to_string().as_bytes(),
+        msg,
+    ]);
+    // The recID is returned with 27 added, but accept the plain recID as well.
+    let recid = match signature[64] {
+        recid @ 0..=1 => recid,
+        recid @ 27..=28 => recid - 27,
+        _ => return Err(Error::InvalidSignature),
+    };
+    let signature = ecdsa::RecoverableSignature::from_compact(
+        &signature[..64],
+        ecdsa::RecoveryId::from_i32(recid as i32).map_err(|_| Error::InvalidSignature)?,
+    )
+    .map_err(|_| Error::InvalidSignature)?;
+    let pubkey = Secp256k1::verification_only()
+        .recover_ecdsa(&Message::from_digest(msg_hash), &signature)
+        .map_err(|_| Error::InvalidSignature)?;
+    let recovered_address = hex::encode(&keccak256(&[&pubkey.serialize_uncompressed()[1..]])[12..]);
+    let expected_address = address.strip_prefix("0x").unwrap_or(address);
+    if !recovered_address.eq_ignore_ascii_case(expected_address) {
+        return Err(Error::SignatureVerification(format!(
+            "the signature was made by the key of 0x{}, expected {}",
+            recovered_address, address
+        )));
+    }
+    Ok(())
+}
+
 #[cfg(feature = "rlp")]
 impl TryFrom<&[u8]> for Transaction {
     type Error = ();
@@ -616,6 +667,8 @@ impl<R: Runtime> PairedBitBox<R> {
     /// len(msg) in the hardware, e.g. "\x19Ethereum\n5hello" (yes, the len prefix is the ascii
     /// representation with no fixed size or delimiter).  It returns a 65 byte signature (R, S, and
     /// 1 byte recID). 27 is added to the recID to denote an uncompressed pubkey.
+    ///
+    /// Use `verify_message_signature()` to check the signature against the expected address.
     pub async fn eth_sign_message(
         &self,
         chain_id: u64,
@@ -642,6 +695,21 @@ impl<R: Runtime> PairedBitBox<R> {
         Ok(signature)
     }
 
+    /// Like `eth_sign_message()`, but also fetches the address of the keypath from the BitBox
+    /// (without displaying it) and checks the signature against it using
+    /// `verify_message_signature()`.
+    pub async fn eth_sign_message_verified(
+        &self,
+        chain_id: u64,
+        keypath: &Keypath,
+        msg: &[u8],
+    ) -> Result<[u8; 65], Error> {
+        let address = self.eth_address(chain_id, keypath, false).await?;
+        let signature = self.eth_sign_message(chain_id, keypath, msg).await?;
+        verify_message_signature(msg, &signature, &address)?;
+        Ok(signature)
+    }
+
     /// Signs an Ethereum EIP-712 typed message. It returns a 65 byte signature (R, S, and 1 byte
     /// recID). 27 is added to the recID to denote an uncompressed pubkey.
     /// If `use_antiklepto` is false, signing is deterministic and requires firmware >=9.26.0.
@@ -1393,4 +1461,40 @@ mod tests {
             pb::EthAddressCase::Mixed
         );
     }
+
+    #[test]
+    fn test_verify_message_signature() {
+        // Test vector of web3.eth.accounts.sign("Some data", privateKey), see
+        // https://web3js.readthedocs.io/en/v1.10.0/web3-eth-accounts.html#sign.
+        let signature: [u8; 65] = hex::decode("b91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a0291c").unwrap().try_into().unwrap();
+        let address = "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23";
+        assert!(verify_message_signature(b"Some data", &signature, address).is_ok());
+        assert!(
+            verify_message_signature(b"Some data", &signature, &address.to_lowercase()).is_ok()
+        );
+
+        let mut signature_without_offset = signature;
+        signature_without_offset[64] -= 27;
+        assert!(verify_message_signature(b"Some data", &signature_without_offset, address).is_ok());
+
+        assert!(matches!(
+            verify_message_signature(b"Other data", &signature, address),
+            Err(Error::SignatureVerification(_))
+        ));
+        assert!(matches!(
+            verify_message_signature(
+                b"Some data",
+                &signature,
+                "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            ),
+            Err(Error::SignatureVerification(_))
+        ));
+
+        let mut invalid_recid = signature;
+        invalid_recid[64] = 5;
+        assert!(matches!(
+            verify_message_signature(b"Some data", &invalid_recid, address),
+            Err(Error::InvalidSignature)
+        ));
+    }
 }
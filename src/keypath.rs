@@ -66,6 +66,12 @@ impl From<&bitcoin::bip32::DerivationPath> for Keypath {
     }
 }
 
+impl From<&[u32]> for Keypath {
+    fn from(value: &[u32]) -> Self {
+        Keypath(value.to_vec())
+    }
+}
+
 impl From<&Keypath> for crate::pb::Keypath {
     fn from(value: &Keypath) -> Self {
         crate::pb::Keypath {
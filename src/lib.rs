@@ -5,16 +5,27 @@
 #[cfg(all(feature = "wasm", feature = "multithreaded"))]
 compile_error!("wasm and multithreaded can't both be active");
 
+pub mod attestation;
+pub mod backup;
+pub mod bip322;
+pub mod bluetooth;
+pub mod bootloader;
 pub mod btc;
 pub mod cardano;
+pub mod coordinator;
+pub mod discovery;
 pub mod error;
 pub mod eth;
 mod noise;
+pub mod psbt_v2;
+pub mod registry;
 pub mod runtime;
 #[cfg(feature = "simulator")]
 pub mod simulator;
 #[cfg(feature = "usb")]
 pub mod usb;
+pub mod wallet_export;
+pub mod wallet_policy;
 #[cfg(feature = "wasm")]
 pub mod wasm;
 
@@ -22,6 +33,7 @@ mod antiklepto;
 mod communication;
 mod constants;
 mod keypath;
+mod silentpayments;
 mod u2fframing;
 mod util;
 
@@ -49,13 +61,13 @@ pub use util::Threading;
 
 use communication::HwwCommunication;
 
-pub use communication::Product;
+pub use communication::{Error as CommunicationError, Info, Product, ReadWrite};
 
 const OP_I_CAN_HAS_HANDSHAEK: u8 = b'h';
 const OP_HER_COMEZ_TEH_HANDSHAEK: u8 = b'H';
 const OP_I_CAN_HAS_PAIRIN_VERIFICASHUN: u8 = b'v';
 const OP_NOISE_MSG: u8 = b'n';
-const _OP_ATTESTATION: u8 = b'a';
+const OP_ATTESTATION: u8 = b'a';
 const OP_UNLOCK: u8 = b'u';
 
 const RESPONSE_SUCCESS: u8 = 0x00;
@@ -74,6 +86,32 @@ pub struct BitBox<R: Runtime> {
 
 pub type PairingCode = String;
 
+/// Length of the seed created by the device in `set_password()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeedLength {
+    /// 16 bytes of entropy, i.e. a 12 word recovery phrase. Requires firmware version >=9.6.0.
+    Words12,
+    /// 32 bytes of entropy, i.e. a 24 word recovery phrase.
+    Words24,
+}
+
+/// Queries the device info (version, product, unlocked and initialized state) without unlocking
+/// the device or starting the pairing. Use this to decide whether the device still needs to be set
+/// up before calling `unlock_and_pair()`.
+///
+/// `transport` is the raw connection to the device exchanging 64 byte U2F HID packets, e.g. a HID
+/// device or a simulator connection.
+pub async fn probe(transport: Box<dyn ReadWrite>) -> Result<Info, Error> {
+    let comm = communication::U2fHidCommunication::from(transport, communication::FIRMWARE_CMD);
+    Ok(communication::get_info(&comm).await?)
+}
+
+/// Like `probe()`, using a BitBox02 HID device. Use `usb::get_any_bitbox02()` to find one.
+#[cfg(feature = "usb")]
+pub async fn probe_hid_device(device: hidapi::HidDevice) -> Result<Info, Error> {
+    probe(Box::new(crate::usb::HidDevice::new(device))).await
+}
+
 impl<R: Runtime> BitBox<R> {
     async fn from(
         device: Box<dyn communication::ReadWrite>,
@@ -114,6 +152,43 @@ impl<R: Runtime> BitBox<R> {
         Self::from(comm, noise_config).await
     }
 
+    /// Returns the device info (version, product, unlocked and initialized state). This is
+    /// available before unlocking and pairing.
+    pub fn info(&self) -> &Info {
+        &self.communication.info
+    }
+
+    /// Performs the device attestation, checking that the device is a genuine BitBox. The device
+    /// signs a random challenge with its attestation key, whose certificate is verified against
+    /// the known BitBox root pubkeys in `attestation::ROOT_PUBKEYS`.
+    ///
+    /// This should be called before `unlock_and_pair()`. If the result is not verified, the device
+    /// might not be a genuine BitBox and the user should be warned.
+    pub async fn perform_attestation(&self) -> Result<attestation::AttestationResult, Error> {
+        self.perform_attestation_with_root_pubkeys(attestation::ROOT_PUBKEYS)
+            .await
+    }
+
+    /// Like `perform_attestation()`, but verifies the certificate against the given root pubkeys
+    /// instead of `attestation::ROOT_PUBKEYS`.
+    pub async fn perform_attestation_with_root_pubkeys(
+        &self,
+        root_pubkeys: &[attestation::RootPubkey],
+    ) -> Result<attestation::AttestationResult, Error> {
+        let mut challenge = [0u8; 32];
+        getrandom::getrandom(&mut challenge).map_err(|_| Error::HostEntropy)?;
+        let mut request = vec![OP_ATTESTATION];
+        request.extend_from_slice(&challenge);
+        let response = self.communication.query(&request).await?;
+        match response.split_first() {
+            Some((&RESPONSE_SUCCESS, response)) => {
+                attestation::verify(&challenge, response, root_pubkeys)
+                    .ok_or(Error::UnexpectedResponse)
+            }
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
     /// Invokes the device unlock and pairing.
     pub async fn unlock_and_pair(self) -> Result<PairingBitBox<R>, Error> {
         self.communication
@@ -270,6 +345,7 @@ pub struct PairedBitBox<R: Runtime> {
     communication: communication::HwwCommunication<R>,
     noise_send: Mutex<CipherState>,
     noise_recv: Mutex<CipherState>,
+    registry: Option<Box<dyn registry::ScriptConfigRegistry>>,
 }
 
 impl<R: Runtime> PairedBitBox<R> {
@@ -279,6 +355,7 @@ impl<R: Runtime> PairedBitBox<R> {
             communication,
             noise_send: Mutex::new(send),
             noise_recv: Mutex::new(recv),
+            registry: None,
         }
     }
 
@@ -386,13 +463,12 @@ impl<R: Runtime> PairedBitBox<R> {
 
     /// Restore from recovery words on the Bitbox.
     pub async fn restore_from_mnemonic(&self) -> Result<(), Error> {
-        let now = std::time::SystemTime::now();
-        let duration_since_epoch = now.duration_since(std::time::UNIX_EPOCH).unwrap();
+        let (timestamp, timezone_offset) = util::timestamp_and_timezone_offset();
         match self
             .query_proto(Request::RestoreFromMnemonic(
                 pb::RestoreFromMnemonicRequest {
-                    timestamp: duration_since_epoch.as_secs() as u32,
-                    timezone_offset: chrono::Local::now().offset().local_minus_utc(),
+                    timestamp,
+                    timezone_offset,
                 },
             ))
             .await?
@@ -402,6 +478,99 @@ impl<R: Runtime> PairedBitBox<R> {
         }
     }
 
+    /// Sets the device name. The name is shown on the device and stored in microSD card backups.
+    pub async fn set_device_name(&self, name: &str) -> Result<(), Error> {
+        match self
+            .query_proto(Request::DeviceName(pb::SetDeviceNameRequest {
+                name: name.into(),
+            }))
+            .await?
+        {
+            Response::Success(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Invokes the password setup workflow on an uninitialized device. After the user has chosen
+    /// a password, the device creates a new seed, mixing in random entropy supplied by the host.
+    ///
+    /// The device is not fully initialized until a backup is created using `create_backup()`.
+    pub async fn set_password(&self, seed_length: SeedLength) -> Result<(), Error> {
+        let mut entropy = match seed_length {
+            SeedLength::Words12 => {
+                self.validate_version(">=9.6.0")?;
+                vec![0u8; 16]
+            }
+            SeedLength::Words24 => vec![0u8; 32],
+        };
+        getrandom::getrandom(&mut entropy).map_err(|_| Error::HostEntropy)?;
+        match self
+            .query_proto(Request::SetPassword(pb::SetPasswordRequest { entropy }))
+            .await?
+        {
+            Response::Success(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Sets up an uninitialized device: sets the device name, invokes the password setup which
+    /// creates a new seed, and creates the microSD card backup. The user is guided through the
+    /// workflows on the device.
+    ///
+    /// Returns whether the device reports to be initialized afterwards.
+    pub async fn setup(&self, name: &str, seed_length: SeedLength) -> Result<bool, Error> {
+        self.set_device_name(name).await?;
+        self.set_password(seed_length).await?;
+        self.create_backup().await?;
+        Ok(self.device_info().await?.initialized)
+    }
+
+    /// Queries a request after which the device reboots if the user confirms. The device may
+    /// disconnect before responding, which is treated as success.
+    async fn query_proto_reboot(&self, request: Request) -> Result<(), Error> {
+        match self.query_proto(request).await {
+            Ok(Response::Success(_)) => Ok(()),
+            Ok(_) => Err(Error::UnexpectedResponse),
+            Err(Error::Communication(communication::Error::Read | communication::Error::Write)) => {
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Invokes the factory reset workflow on the device. If the user confirms, the device is
+    /// wiped and reboots, closing the connection. Do not use this instance afterwards.
+    pub async fn reset(&self) -> Result<(), Error> {
+        self.query_proto_reboot(Request::Reset(pb::ResetRequest {}))
+            .await
+    }
+
+    /// Reboots the device after the user confirms. With `Purpose::Upgrade`, the device reboots into
+    /// the bootloader to install a firmware upgrade. With `Purpose::Settings`, the user is shown a
+    /// message that the device reboots to access advanced settings in the bootloader.
+    ///
+    /// The connection is closed when the device reboots. Do not use this instance afterwards.
+    pub async fn reboot(&self, purpose: pb::reboot_request::Purpose) -> Result<(), Error> {
+        self.query_proto_reboot(Request::Reboot(pb::RebootRequest {
+            purpose: purpose as _,
+        }))
+        .await
+    }
+
+    /// Enables or disables the optional BIP39 passphrase after the user confirms. If enabled, the
+    /// user is asked to enter the passphrase when unlocking the device.
+    pub async fn set_mnemonic_passphrase_enabled(&self, enabled: bool) -> Result<(), Error> {
+        match self
+            .query_proto(Request::SetMnemonicPassphraseEnabled(
+                pb::SetMnemonicPassphraseEnabledRequest { enabled },
+            ))
+            .await?
+        {
+            Response::Success(_) => Ok(()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
     /// Invokes the password change workflow on the device.
     /// Requires firmware version >=9.25.0.
     pub async fn change_password(&self) -> Result<(), Error> {
@@ -431,4 +600,44 @@ impl<R: Runtime> PairedBitBox<R> {
             _ => Err(Error::UnexpectedResponse),
         }
     }
+
+    /// Invokes the BIP85-LN workflow on the device, returning 32 bytes of entropy derived at the
+    /// given account number, to be used as a Lightning wallet seed. The user is asked to confirm
+    /// on the device.
+    pub async fn bip85_app_ln(&self, account_number: u32) -> Result<[u8; 32], Error> {
+        self.validate_version(">=9.18.0")?;
+        match self
+            .query_proto(Request::Bip85(pb::Bip85Request {
+                app: Some(pb::bip85_request::App::Ln(pb::bip85_request::AppLn {
+                    account_number,
+                })),
+            }))
+            .await?
+        {
+            Response::Bip85(pb::Bip85Response {
+                app: Some(pb::bip85_response::App::Ln(entropy)),
+            }) => entropy
+                .as_slice()
+                .try_into()
+                .map_err(|_| Error::UnexpectedResponse),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Retrieves the key used by Electrum to encrypt the wallet file, derived at the given
+    /// keypath. The user is asked to confirm on the device.
+    pub async fn electrum_encryption_key(&self, keypath: &Keypath) -> Result<String, Error> {
+        self.validate_version(">=9.5.0")?;
+        match self
+            .query_proto(Request::ElectrumEncryptionKey(
+                pb::ElectrumEncryptionKeyRequest {
+                    keypath: keypath.to_vec(),
+                },
+            ))
+            .await?
+        {
+            Response::ElectrumEncryptionKey(pb::ElectrumEncryptionKeyResponse { key }) => Ok(key),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
 }
@@ -0,0 +1,679 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Support for version 2 PSBTs, see <https://github.com/bitcoin/bips/blob/master/bip-0370.mediawiki>.
+//!
+//! rust-bitcoin only supports version 0 PSBTs. The per-input and per-output fields which are common
+//! to both versions (UTXOs, key origins, signatures, etc.) are parsed using rust-bitcoin's
+//! `psbt::Input` and `psbt::Output`, while the fields which replace the global unsigned transaction
+//! of version 0 PSBTs are parsed here.
+
+use thiserror::Error;
+
+use bitcoin::base64::{prelude::BASE64_STANDARD, Engine};
+use bitcoin::bip32::{KeySource, Xpub};
+use bitcoin::consensus::encode::{deserialize_partial, serialize, VarInt};
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{raw, Psbt};
+use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Txid};
+
+use std::collections::BTreeMap;
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const PSBT_GLOBAL_VERSION: u8 = 0xfb;
+
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0e;
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0f;
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+const PSBT_OUT_SCRIPT: u8 = 0x04;
+// See BIP-375. Silent payment outputs do not have a script until the output is generated.
+const PSBT_OUT_SP_V0_INFO: u8 = 0x09;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid PSBTv2: {0}")]
+    Invalid(&'static str),
+    #[error("invalid PSBTv2: {0}")]
+    Psbt(String),
+    #[error("the inputs have incompatible locktime requirements")]
+    IncompatibleLocktimes,
+}
+
+// Key (including the key type) and value pairs of a PSBT map.
+type RawMap = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Input of a version 2 PSBT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputV2 {
+    pub previous_output: OutPoint,
+    /// If None, the sequence is 0xFFFFFFFF.
+    pub sequence: Option<Sequence>,
+    pub required_time_locktime: Option<absolute::Time>,
+    pub required_height_locktime: Option<absolute::Height>,
+    /// Fields shared with version 0 PSBTs (UTXOs, key origins, signatures, etc.).
+    pub input: bitcoin::psbt::Input,
+}
+
+/// Output of a version 2 PSBT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputV2 {
+    pub amount: Amount,
+    /// Empty for silent payment outputs which were not generated yet.
+    pub script_pubkey: ScriptBuf,
+    /// Fields shared with version 0 PSBTs (key origins, etc.).
+    pub output: bitcoin::psbt::Output,
+}
+
+impl OutputV2 {
+    fn is_silent_payment(&self) -> bool {
+        self.output.unknown.contains_key(&raw::Key {
+            type_value: PSBT_OUT_SP_V0_INFO,
+            key: vec![],
+        })
+    }
+}
+
+/// A version 2 PSBT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PsbtV2 {
+    pub tx_version: transaction::Version,
+    pub fallback_locktime: Option<absolute::LockTime>,
+    pub tx_modifiable: Option<u8>,
+    pub xpub: BTreeMap<Xpub, KeySource>,
+    pub proprietary: BTreeMap<raw::ProprietaryKey, Vec<u8>>,
+    pub unknown: BTreeMap<raw::Key, Vec<u8>>,
+    pub inputs: Vec<InputV2>,
+    pub outputs: Vec<OutputV2>,
+}
+
+fn read_bytes(data: &mut &[u8]) -> Result<Vec<u8>, Error> {
+    let (VarInt(len), consumed) = deserialize_partial::<VarInt>(data)
+        .map_err(|_| Error::Invalid("unexpected end of data"))?;
+    let rest = &data[consumed..];
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|&len| len <= rest.len())
+        .ok_or(Error::Invalid("unexpected end of data"))?;
+    let (bytes, rest) = rest.split_at(len);
+    *data = rest;
+    Ok(bytes.to_vec())
+}
+
+fn read_map(data: &mut &[u8]) -> Result<RawMap, Error> {
+    let mut map = RawMap::new();
+    loop {
+        let key = read_bytes(data)?;
+        if key.is_empty() {
+            return Ok(map);
+        }
+        let value = read_bytes(data)?;
+        if map.insert(key, value).is_some() {
+            return Err(Error::Invalid("duplicate key"));
+        }
+    }
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend(serialize(&VarInt(bytes.len() as u64)));
+    out.extend_from_slice(bytes);
+}
+
+fn write_map(out: &mut Vec<u8>, map: &RawMap) {
+    for (key, value) in map.iter() {
+        write_bytes(out, key);
+        write_bytes(out, value);
+    }
+    out.push(0x00);
+}
+
+fn take_field(map: &mut RawMap, key_type: u8) -> Option<Vec<u8>> {
+    map.remove([key_type].as_slice())
+}
+
+fn take_u32(map: &mut RawMap, key_type: u8) -> Result<Option<u32>, Error> {
+    take_field(map, key_type)
+        .map(|value| {
+            <[u8; 4]>::try_from(value.as_slice())
+                .map(u32::from_le_bytes)
+                .map_err(|_| Error::Invalid("invalid 32 bit value"))
+        })
+        .transpose()
+}
+
+fn take_compact_size(map: &mut RawMap, key_type: u8) -> Result<Option<u64>, Error> {
+    take_field(map, key_type)
+        .map(|value| match deserialize_partial::<VarInt>(&value) {
+            Ok((VarInt(n), consumed)) if consumed == value.len() => Ok(n),
+            _ => Err(Error::Invalid("invalid compact size value")),
+        })
+        .transpose()
+}
+
+fn read_maps(data: &mut &[u8], count: u64) -> Result<Vec<RawMap>, Error> {
+    let mut maps = Vec::new();
+    for _ in 0..count {
+        maps.push(read_map(data)?);
+    }
+    Ok(maps)
+}
+
+impl InputV2 {
+    fn take_from_map(map: &mut RawMap) -> Result<Self, Error> {
+        let txid = take_field(map, PSBT_IN_PREVIOUS_TXID)
+            .ok_or(Error::Invalid("missing previous txid"))?;
+        let txid = Txid::from_byte_array(
+            txid.try_into()
+                .map_err(|_| Error::Invalid("invalid previous txid"))?,
+        );
+        let vout =
+            take_u32(map, PSBT_IN_OUTPUT_INDEX)?.ok_or(Error::Invalid("missing output index"))?;
+        let required_time_locktime = take_u32(map, PSBT_IN_REQUIRED_TIME_LOCKTIME)?
+            .map(absolute::Time::from_consensus)
+            .transpose()
+            .map_err(|_| Error::Invalid("invalid required time locktime"))?;
+        let required_height_locktime = take_u32(map, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)?
+            .map(absolute::Height::from_consensus)
+            .transpose()
+            .map_err(|_| Error::Invalid("invalid required height locktime"))?;
+        Ok(InputV2 {
+            previous_output: OutPoint { txid, vout },
+            sequence: take_u32(map, PSBT_IN_SEQUENCE)?.map(Sequence),
+            required_time_locktime,
+            required_height_locktime,
+            input: Default::default(),
+        })
+    }
+
+    fn put_into_map(&self, map: &mut RawMap) {
+        map.insert(
+            vec![PSBT_IN_PREVIOUS_TXID],
+            self.previous_output.txid.to_byte_array().to_vec(),
+        );
+        map.insert(
+            vec![PSBT_IN_OUTPUT_INDEX],
+            self.previous_output.vout.to_le_bytes().to_vec(),
+        );
+        if let Some(sequence) = self.sequence {
+            map.insert(vec![PSBT_IN_SEQUENCE], sequence.0.to_le_bytes().to_vec());
+        }
+        if let Some(time) = self.required_time_locktime {
+            map.insert(
+                vec![PSBT_IN_REQUIRED_TIME_LOCKTIME],
+                time.to_consensus_u32().to_le_bytes().to_vec(),
+            );
+        }
+        if let Some(height) = self.required_height_locktime {
+            map.insert(
+                vec![PSBT_IN_REQUIRED_HEIGHT_LOCKTIME],
+                height.to_consensus_u32().to_le_bytes().to_vec(),
+            );
+        }
+    }
+}
+
+impl OutputV2 {
+    fn take_from_map(map: &mut RawMap) -> Result<Self, Error> {
+        let amount = take_field(map, PSBT_OUT_AMOUNT).ok_or(Error::Invalid("missing amount"))?;
+        let amount = <[u8; 8]>::try_from(amount.as_slice())
+            .ok()
+            .and_then(|amount| u64::try_from(i64::from_le_bytes(amount)).ok())
+            .ok_or(Error::Invalid("invalid amount"))?;
+        let script_pubkey = match take_field(map, PSBT_OUT_SCRIPT) {
+            Some(script) => script.into(),
+            None if map.contains_key([PSBT_OUT_SP_V0_INFO].as_slice()) => ScriptBuf::new(),
+            None => return Err(Error::Invalid("missing output script")),
+        };
+        Ok(OutputV2 {
+            amount: Amount::from_sat(amount),
+            script_pubkey,
+            output: Default::default(),
+        })
+    }
+
+    fn put_into_map(&self, map: &mut RawMap) {
+        map.insert(
+            vec![PSBT_OUT_AMOUNT],
+            (self.amount.to_sat() as i64).to_le_bytes().to_vec(),
+        );
+        if !(self.script_pubkey.is_empty() && self.is_silent_payment()) {
+            map.insert(vec![PSBT_OUT_SCRIPT], self.script_pubkey.to_bytes());
+        }
+    }
+}
+
+impl PsbtV2 {
+    /// Parses a binary version 2 PSBT.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let mut data = bytes
+            .strip_prefix(PSBT_MAGIC)
+            .ok_or(Error::Invalid("invalid magic"))?;
+        let mut global = read_map(&mut data)?;
+        if take_u32(&mut global, PSBT_GLOBAL_VERSION)? != Some(2) {
+            return Err(Error::Invalid("not a version 2 PSBT"));
+        }
+        if global.contains_key([PSBT_GLOBAL_UNSIGNED_TX].as_slice()) {
+            return Err(Error::Invalid("unsigned tx not allowed"));
+        }
+        let tx_version = take_u32(&mut global, PSBT_GLOBAL_TX_VERSION)?
+            .ok_or(Error::Invalid("missing tx version"))?;
+        let fallback_locktime = take_u32(&mut global, PSBT_GLOBAL_FALLBACK_LOCKTIME)?
+            .map(absolute::LockTime::from_consensus);
+        let input_count = take_compact_size(&mut global, PSBT_GLOBAL_INPUT_COUNT)?
+            .ok_or(Error::Invalid("missing input count"))?;
+        let output_count = take_compact_size(&mut global, PSBT_GLOBAL_OUTPUT_COUNT)?
+            .ok_or(Error::Invalid("missing output count"))?;
+        let tx_modifiable = match take_field(&mut global, PSBT_GLOBAL_TX_MODIFIABLE).as_deref() {
+            None => None,
+            Some(&[flags]) => Some(flags),
+            Some(_) => return Err(Error::Invalid("invalid tx modifiable flags")),
+        };
+
+        let mut input_maps = read_maps(&mut data, input_count)?;
+        let mut output_maps = read_maps(&mut data, output_count)?;
+        if !data.is_empty() {
+            return Err(Error::Invalid("trailing data"));
+        }
+        let inputs = input_maps
+            .iter_mut()
+            .map(InputV2::take_from_map)
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = output_maps
+            .iter_mut()
+            .map(OutputV2::take_from_map)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut psbt = PsbtV2 {
+            tx_version: transaction::Version(tx_version as i32),
+            fallback_locktime,
+            tx_modifiable,
+            xpub: BTreeMap::new(),
+            proprietary: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+            inputs,
+            outputs,
+        };
+
+        // With the version 2 fields removed, the remaining fields form a valid version 0 PSBT once
+        // the unsigned tx is added, which we let rust-bitcoin parse.
+        global.insert(
+            vec![PSBT_GLOBAL_UNSIGNED_TX],
+            serialize(&psbt.unsigned_tx(absolute::LockTime::ZERO)),
+        );
+        let mut psbt_v0 = PSBT_MAGIC.to_vec();
+        for map in std::iter::once(&global)
+            .chain(&input_maps)
+            .chain(&output_maps)
+        {
+            write_map(&mut psbt_v0, map);
+        }
+        let psbt_v0 = Psbt::deserialize(&psbt_v0).map_err(|err| Error::Psbt(err.to_string()))?;
+        psbt.xpub = psbt_v0.xpub;
+        psbt.proprietary = psbt_v0.proprietary;
+        psbt.unknown = psbt_v0.unknown;
+        for (input, psbt_input) in psbt.inputs.iter_mut().zip(psbt_v0.inputs) {
+            input.input = psbt_input;
+        }
+        for (output, psbt_output) in psbt.outputs.iter_mut().zip(psbt_v0.outputs) {
+            output.output = psbt_output;
+        }
+        Ok(psbt)
+    }
+
+    /// Serializes the PSBT in the binary format.
+    pub fn serialize(&self) -> Vec<u8> {
+        let psbt_v0 = self.psbt_v0(absolute::LockTime::ZERO).serialize();
+        // rust-bitcoin's serialization is always well-formed.
+        let mut data = &psbt_v0[PSBT_MAGIC.len()..];
+        let mut global = read_map(&mut data).unwrap();
+        take_field(&mut global, PSBT_GLOBAL_UNSIGNED_TX);
+        global.insert(vec![PSBT_GLOBAL_VERSION], 2u32.to_le_bytes().to_vec());
+        global.insert(
+            vec![PSBT_GLOBAL_TX_VERSION],
+            self.tx_version.0.to_le_bytes().to_vec(),
+        );
+        if let Some(locktime) = self.fallback_locktime {
+            global.insert(
+                vec![PSBT_GLOBAL_FALLBACK_LOCKTIME],
+                locktime.to_consensus_u32().to_le_bytes().to_vec(),
+            );
+        }
+        global.insert(
+            vec![PSBT_GLOBAL_INPUT_COUNT],
+            serialize(&VarInt(self.inputs.len() as u64)),
+        );
+        global.insert(
+            vec![PSBT_GLOBAL_OUTPUT_COUNT],
+            serialize(&VarInt(self.outputs.len() as u64)),
+        );
+        if let Some(flags) = self.tx_modifiable {
+            global.insert(vec![PSBT_GLOBAL_TX_MODIFIABLE], vec![flags]);
+        }
+
+        let mut result = PSBT_MAGIC.to_vec();
+        write_map(&mut result, &global);
+        for input in self.inputs.iter() {
+            let mut map = read_map(&mut data).unwrap();
+            input.put_into_map(&mut map);
+            write_map(&mut result, &map);
+        }
+        for output in self.outputs.iter() {
+            let mut map = read_map(&mut data).unwrap();
+            output.put_into_map(&mut map);
+            write_map(&mut result, &map);
+        }
+        result
+    }
+
+    /// Determines the locktime of the transaction as specified in BIP-370: if any input requires a
+    /// locktime, the maximum required height locktime is used if all of these inputs support a height
+    /// locktime, otherwise the maximum required time locktime if they all support a time locktime.
+    /// Otherwise, the fallback locktime is used, defaulting to 0.
+    pub fn locktime(&self) -> Result<absolute::LockTime, Error> {
+        let constrained: Vec<&InputV2> = self
+            .inputs
+            .iter()
+            .filter(|input| {
+                input.required_time_locktime.is_some() || input.required_height_locktime.is_some()
+            })
+            .collect();
+        if constrained.is_empty() {
+            return Ok(self.fallback_locktime.unwrap_or(absolute::LockTime::ZERO));
+        }
+        if let Some(heights) = constrained
+            .iter()
+            .map(|input| input.required_height_locktime)
+            .collect::<Option<Vec<_>>>()
+        {
+            return Ok(heights.into_iter().max().unwrap().into());
+        }
+        if let Some(times) = constrained
+            .iter()
+            .map(|input| input.required_time_locktime)
+            .collect::<Option<Vec<_>>>()
+        {
+            return Ok(times.into_iter().max().unwrap().into());
+        }
+        Err(Error::IncompatibleLocktimes)
+    }
+
+    fn unsigned_tx(&self, lock_time: absolute::LockTime) -> bitcoin::Transaction {
+        bitcoin::Transaction {
+            version: self.tx_version,
+            lock_time,
+            input: self
+                .inputs
+                .iter()
+                .map(|input| TxIn {
+                    previous_output: input.previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: input.sequence.unwrap_or(Sequence::MAX),
+                    witness: Default::default(),
+                })
+                .collect(),
+            output: self
+                .outputs
+                .iter()
+                .map(|output| TxOut {
+                    value: output.amount,
+                    script_pubkey: output.script_pubkey.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn psbt_v0(&self, lock_time: absolute::LockTime) -> Psbt {
+        Psbt {
+            unsigned_tx: self.unsigned_tx(lock_time),
+            version: 0,
+            xpub: self.xpub.clone(),
+            proprietary: self.proprietary.clone(),
+            unknown: self.unknown.clone(),
+            inputs: self
+                .inputs
+                .iter()
+                .map(|input| input.input.clone())
+                .collect(),
+            outputs: self
+                .outputs
+                .iter()
+                .map(|output| output.output.clone())
+                .collect(),
+        }
+    }
+
+    /// Converts a version 0 PSBT to a version 2 PSBT. The locktime of the unsigned transaction
+    /// becomes the fallback locktime.
+    pub fn from_psbt_v0(psbt: Psbt) -> Self {
+        PsbtV2 {
+            tx_version: psbt.unsigned_tx.version,
+            fallback_locktime: Some(psbt.unsigned_tx.lock_time),
+            tx_modifiable: None,
+            xpub: psbt.xpub,
+            proprietary: psbt.proprietary,
+            unknown: psbt.unknown,
+            inputs: psbt
+                .unsigned_tx
+                .input
+                .into_iter()
+                .zip(psbt.inputs)
+                .map(|(tx_input, input)| InputV2 {
+                    previous_output: tx_input.previous_output,
+                    sequence: Some(tx_input.sequence),
+                    required_time_locktime: None,
+                    required_height_locktime: None,
+                    input,
+                })
+                .collect(),
+            outputs: psbt
+                .unsigned_tx
+                .output
+                .into_iter()
+                .zip(psbt.outputs)
+                .map(|(tx_output, output)| OutputV2 {
+                    amount: tx_output.value,
+                    script_pubkey: tx_output.script_pubkey,
+                    output,
+                })
+                .collect(),
+        }
+    }
+
+    /// Converts the PSBT to a version 0 PSBT, with the unsigned transaction built from the
+    /// version 2 fields.
+    pub fn to_psbt_v0(&self) -> Result<Psbt, Error> {
+        Ok(self.psbt_v0(self.locktime()?))
+    }
+
+    /// Updates the PSBT with the input and output fields of a version 0 PSBT obtained with
+    /// `to_psbt_v0()`, e.g. after it has been signed. The output scripts are updated as well, as
+    /// silent payment output scripts are only known after signing.
+    pub fn update_from_psbt_v0(&mut self, psbt: Psbt) {
+        for (input, psbt_input) in self.inputs.iter_mut().zip(psbt.inputs) {
+            input.input = psbt_input;
+        }
+        for ((output, psbt_output), tx_output) in self
+            .outputs
+            .iter_mut()
+            .zip(psbt.outputs)
+            .zip(psbt.unsigned_tx.output)
+        {
+            output.output = psbt_output;
+            output.script_pubkey = tx_output.script_pubkey;
+        }
+    }
+}
+
+impl std::str::FromStr for PsbtV2 {
+    type Err = Error;
+
+    /// Parses a base64 encoded version 2 PSBT.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = BASE64_STANDARD
+            .decode(s)
+            .map_err(|_| Error::Invalid("invalid base64"))?;
+        PsbtV2::deserialize(&bytes)
+    }
+}
+
+impl std::fmt::Display for PsbtV2 {
+    /// Encodes the PSBT in base64.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", BASE64_STANDARD.encode(self.serialize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // PSBTv0 from `btc::tests::test_transaction_from_psbt_p2wpkh`.
+    const PSBT_V0: &str = "cHNidP8BAHECAAAAAfbXTun4YYxDroWyzRq3jDsWFVlsZ7HUzxiORY/iR4goAAAAAAD9////AuLCAAAAAAAAFgAUg3w5W0zt3AmxRmgA5Q6wZJUDRhUowwAAAAAAABYAFJjQqUoXDcwUEqfExu9pnaSn5XBct0ElAAABAR+ghgEAAAAAABYAFHn03igII+hp819N2Zlb5LnN8atRAQDfAQAAAAABAZ9EJlMJnXF5bFVrb1eFBYrEev3pg35WpvS3RlELsMMrAQAAAAD9////AqCGAQAAAAAAFgAUefTeKAgj6GnzX03ZmVvkuc3xq1EoRs4JAAAAABYAFKG2PzjYjknaA6lmXFqPaSgHwXX9AkgwRQIhAL0v0r3LisQ9KOlGzMhM/xYqUmrv2a5sORRlkX1fqDC8AiB9XqxSNEdb4mPnp7ylF1cAlbAZ7jMhgIxHUXylTww3bwEhA0AEOM0yYEpexPoKE3vT51uxZ+8hk9sOEfBFKOeo6oDDAAAAACIGAyNQfmAT/YLmZaxxfDwClmVNt2BkFnfQu/i8Uc/hHDUiGBKiwYlUAACAAQAAgAAAAIAAAAAAAAAAAAAAIgIDnxFM7Qr9LvJwQDB9GozdTRIe3MYVuHOqT7dU2EuvHrIYEqLBiVQAAIABAACAAAAAgAEAAAAAAAAAAA==";
+
+    #[test]
+    fn test_roundtrip() {
+        let psbt_v0 = Psbt::from_str(PSBT_V0).unwrap();
+        let psbt = PsbtV2::from_psbt_v0(psbt_v0.clone());
+        assert_eq!(psbt.inputs.len(), 1);
+        assert_eq!(psbt.outputs.len(), 2);
+        assert_eq!(psbt.inputs[0].sequence, Some(Sequence(0xfffffffd)));
+        assert_eq!(psbt.outputs[0].amount, Amount::from_sat(49890));
+        assert_eq!(
+            psbt.fallback_locktime,
+            Some(absolute::LockTime::from_consensus(2441655))
+        );
+
+        let encoded = psbt.to_string();
+        let decoded = PsbtV2::from_str(&encoded).unwrap();
+        assert_eq!(decoded, psbt);
+        assert_eq!(decoded.to_string(), encoded);
+        assert_eq!(decoded.to_psbt_v0().unwrap(), psbt_v0);
+
+        let bytes = psbt.serialize();
+        let mut data = &bytes[PSBT_MAGIC.len()..];
+        let global = read_map(&mut data).unwrap();
+        assert!(!global.contains_key([PSBT_GLOBAL_UNSIGNED_TX].as_slice()));
+        assert_eq!(
+            global.get([PSBT_GLOBAL_VERSION].as_slice()),
+            Some(&vec![2, 0, 0, 0])
+        );
+        assert_eq!(
+            global.get([PSBT_GLOBAL_INPUT_COUNT].as_slice()),
+            Some(&vec![1])
+        );
+        assert_eq!(
+            global.get([PSBT_GLOBAL_OUTPUT_COUNT].as_slice()),
+            Some(&vec![2])
+        );
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        // Version 0 PSBT.
+        assert!(matches!(
+            PsbtV2::from_str(PSBT_V0),
+            Err(Error::Invalid("not a version 2 PSBT"))
+        ));
+
+        let psbt = PsbtV2::from_psbt_v0(Psbt::from_str(PSBT_V0).unwrap());
+        let bytes = psbt.serialize();
+        // Truncated.
+        assert!(PsbtV2::deserialize(&bytes[..bytes.len() - 1]).is_err());
+        // Trailing data.
+        let mut trailing = bytes.clone();
+        trailing.push(0x00);
+        assert!(matches!(
+            PsbtV2::deserialize(&trailing),
+            Err(Error::Invalid("trailing data"))
+        ));
+
+        // Missing required output field.
+        let mut missing_script = psbt.clone();
+        missing_script.outputs[0].script_pubkey = ScriptBuf::new();
+        let mut bytes = missing_script.serialize();
+        // Remove the empty PSBT_OUT_SCRIPT entry (key length 1, key 0x04, value length 0).
+        let pos = bytes
+            .windows(3)
+            .rposition(|w| w == [0x01, PSBT_OUT_SCRIPT, 0x00])
+            .unwrap();
+        bytes.drain(pos..pos + 3);
+        assert!(matches!(
+            PsbtV2::deserialize(&bytes),
+            Err(Error::Invalid("missing output script"))
+        ));
+    }
+
+    #[test]
+    fn test_silent_payment_output_without_script() {
+        let mut psbt = PsbtV2::from_psbt_v0(Psbt::from_str(PSBT_V0).unwrap());
+        psbt.outputs[0].script_pubkey = ScriptBuf::new();
+        psbt.outputs[0].output.unknown.insert(
+            raw::Key {
+                type_value: PSBT_OUT_SP_V0_INFO,
+                key: vec![],
+            },
+            vec![0x02; 66],
+        );
+        let bytes = psbt.serialize();
+        assert!(!bytes.windows(3).any(|w| w == [0x01, PSBT_OUT_SCRIPT, 0x00]));
+        assert_eq!(PsbtV2::deserialize(&bytes).unwrap(), psbt);
+    }
+
+    #[test]
+    fn test_locktime() {
+        let mut psbt = PsbtV2::from_psbt_v0(Psbt::from_str(PSBT_V0).unwrap());
+        let input = psbt.inputs[0].clone();
+        psbt.inputs = vec![input.clone(), input.clone()];
+        let height = |h| Some(absolute::Height::from_consensus(h).unwrap());
+        let time = |t| Some(absolute::Time::from_consensus(t).unwrap());
+
+        // No requirements: fallback locktime.
+        assert_eq!(
+            psbt.locktime().unwrap(),
+            absolute::LockTime::from_consensus(2441655)
+        );
+        psbt.fallback_locktime = None;
+        assert_eq!(psbt.locktime().unwrap(), absolute::LockTime::ZERO);
+
+        // Only heights: max height.
+        psbt.inputs[0].required_height_locktime = height(1000);
+        assert_eq!(
+            psbt.locktime().unwrap(),
+            absolute::LockTime::from_consensus(1000)
+        );
+        psbt.inputs[1].required_height_locktime = height(2000);
+        assert_eq!(
+            psbt.locktime().unwrap(),
+            absolute::LockTime::from_consensus(2000)
+        );
+
+        // Both supported by all inputs: height is preferred.
+        psbt.inputs[0].required_time_locktime = time(500_000_001);
+        psbt.inputs[1].required_time_locktime = time(500_000_002);
+        assert_eq!(
+            psbt.locktime().unwrap(),
+            absolute::LockTime::from_consensus(2000)
+        );
+
+        // Only time supported by all inputs.
+        psbt.inputs[1].required_height_locktime = None;
+        assert_eq!(
+            psbt.locktime().unwrap(),
+            absolute::LockTime::from_consensus(500_000_002)
+        );
+
+        // Incompatible.
+        psbt.inputs[0].required_time_locktime = None;
+        assert!(matches!(psbt.locktime(), Err(Error::IncompatibleLocktimes)));
+        assert!(psbt.to_psbt_v0().is_err());
+    }
+}
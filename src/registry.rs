@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-side registry of the script configs (multisig and policies) registered on BitBoxes.
+//!
+//! The BitBox only answers whether a given script config is registered, so the host needs to
+//! remember the script configs along with their name, xpub type and account keypath between
+//! sessions. A registry is attached with `PairedBitBox::with_registry()`. Afterwards, successful
+//! `btc_register_script_config()` calls are recorded automatically, and `btc_sign_psbt()` uses the
+//! recorded script configs to find the script config of a PSBT if none is forced.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use prost::Message;
+
+#[cfg(feature = "wasm")]
+use enum_assoc::Assoc;
+
+use crate::error::Error as CrateError;
+use crate::pb;
+use crate::pb::btc_register_script_config_request::XPubType;
+use crate::runtime::Runtime;
+use crate::util::Threading;
+use crate::PairedBitBox;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Assoc), func(pub const fn js_code(&self) -> &'static str))]
+pub enum Error {
+    #[error("could not read the registry: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "read"))]
+    Read(String),
+    #[error("could not store the registry: {0}")]
+    #[cfg_attr(feature = "wasm", assoc(js_code = "store"))]
+    Store(String),
+}
+
+/// A script config registered on a BitBox.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "StoredScriptConfig", into = "StoredScriptConfig")]
+pub struct RegisteredScriptConfig {
+    /// Coin, script config and account keypath, as passed to `btc_register_script_config()`.
+    pub registration: pb::BtcScriptConfigRegistration,
+    pub name: String,
+    pub xpub_type: XPubType,
+}
+
+/// Serialized form of `RegisteredScriptConfig`. The registration is stored protobuf encoded, as
+/// the protobuf messages do not implement serde.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredScriptConfig {
+    registration: String,
+    name: String,
+    xpub_type: i32,
+}
+
+impl From<RegisteredScriptConfig> for StoredScriptConfig {
+    fn from(value: RegisteredScriptConfig) -> Self {
+        StoredScriptConfig {
+            registration: hex::encode(value.registration.encode_to_vec()),
+            name: value.name,
+            xpub_type: value.xpub_type as _,
+        }
+    }
+}
+
+impl TryFrom<StoredScriptConfig> for RegisteredScriptConfig {
+    type Error = String;
+
+    fn try_from(value: StoredScriptConfig) -> Result<Self, Self::Error> {
+        let registration = hex::decode(&value.registration).map_err(|e| e.to_string())?;
+        Ok(RegisteredScriptConfig {
+            registration: pb::BtcScriptConfigRegistration::decode(registration.as_slice())
+                .map_err(|e| e.to_string())?,
+            name: value.name,
+            xpub_type: XPubType::try_from(value.xpub_type).map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RegistryData {
+    /// Registered script configs by the hex encoded root fingerprint of the BitBox.
+    pub devices: BTreeMap<String, Vec<RegisteredScriptConfig>>,
+}
+
+impl RegistryData {
+    /// Returns the script configs registered on the BitBox with this root fingerprint.
+    pub fn script_configs(&self, root_fingerprint: &str) -> &[RegisteredScriptConfig] {
+        self.devices
+            .get(root_fingerprint)
+            .map_or(&[], |script_configs| script_configs.as_slice())
+    }
+
+    /// Records a script config. An existing entry with the same registration is replaced, e.g. to
+    /// update the name.
+    pub fn insert(&mut self, root_fingerprint: &str, script_config: RegisteredScriptConfig) {
+        let script_configs = self.devices.entry(root_fingerprint.into()).or_default();
+        match script_configs
+            .iter_mut()
+            .find(|existing| existing.registration == script_config.registration)
+        {
+            Some(existing) => *existing = script_config,
+            None => script_configs.push(script_config),
+        }
+    }
+}
+
+pub trait ScriptConfigRegistry: Threading {
+    fn read_registry(&self) -> Result<RegistryData, Error>;
+    fn store_registry(&self, data: &RegistryData) -> Result<(), Error>;
+}
+
+/// Registry which is kept in memory only.
+#[derive(Default)]
+pub struct InMemoryRegistry {
+    data: Mutex<RegistryData>,
+}
+
+impl InMemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Threading for InMemoryRegistry {}
+
+impl ScriptConfigRegistry for InMemoryRegistry {
+    fn read_registry(&self) -> Result<RegistryData, Error> {
+        Ok(self.data.lock().unwrap().clone())
+    }
+
+    fn store_registry(&self, data: &RegistryData) -> Result<(), Error> {
+        *self.data.lock().unwrap() = data.clone();
+        Ok(())
+    }
+}
+
+pub struct PersistedRegistry {
+    config_dir: String,
+}
+
+impl Threading for PersistedRegistry {}
+
+impl PersistedRegistry {
+    /// Creates a new persisting registry, which stores the registered script configs in
+    /// "bitbox_registry.json" in the provided directory.
+    pub fn new(config_dir: &str) -> PersistedRegistry {
+        PersistedRegistry {
+            config_dir: config_dir.into(),
+        }
+    }
+}
+
+impl ScriptConfigRegistry for PersistedRegistry {
+    fn read_registry(&self) -> Result<RegistryData, Error> {
+        let config_path = std::path::Path::new(&self.config_dir).join("bitbox_registry.json");
+
+        if !config_path.exists() {
+            return Ok(RegistryData::default());
+        }
+
+        let contents =
+            std::fs::read_to_string(config_path).map_err(|e| Error::Read(e.to_string()))?;
+        serde_json::from_str::<RegistryData>(&contents).map_err(|e| Error::Read(e.to_string()))
+    }
+
+    fn store_registry(&self, data: &RegistryData) -> Result<(), Error> {
+        let config_path = std::path::Path::new(&self.config_dir).join("bitbox_registry.json");
+
+        let data = serde_json::to_string(data).map_err(|e| Error::Store(e.to_string()))?;
+        std::fs::write(config_path, data).map_err(|e| Error::Store(e.to_string()))
+    }
+}
+
+impl<R: Runtime> PairedBitBox<R> {
+    /// Attaches a registry of the registered script configs, see the `registry` module.
+    pub fn with_registry(mut self, registry: Box<dyn ScriptConfigRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Returns the recorded script configs registered on this BitBox, or an empty list if no
+    /// registry is attached.
+    pub async fn btc_registered_script_configs(
+        &self,
+    ) -> Result<Vec<RegisteredScriptConfig>, CrateError> {
+        let registry = match &self.registry {
+            Some(registry) => registry,
+            None => return Ok(vec![]),
+        };
+        let data = registry.read_registry()?;
+        Ok(data
+            .script_configs(&self.root_fingerprint().await?)
+            .to_vec())
+    }
+
+    /// Records a script config after it was registered on this BitBox with the given root
+    /// fingerprint.
+    pub(crate) fn record_registered_script_config(
+        &self,
+        root_fingerprint: &str,
+        script_config: RegisteredScriptConfig,
+    ) -> Result<(), Error> {
+        if let Some(registry) = &self.registry {
+            let mut data = registry.read_registry()?;
+            data.insert(root_fingerprint, script_config);
+            registry.store_registry(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Checks each recorded script config of this BitBox with `btc_is_script_config_registered()`
+    /// and removes the ones which are not registered anymore, e.g. after the device was reset.
+    /// Returns the remaining script configs.
+    pub async fn btc_sync_registry(&self) -> Result<Vec<RegisteredScriptConfig>, CrateError> {
+        let registry = match &self.registry {
+            Some(registry) => registry,
+            None => return Ok(vec![]),
+        };
+        let root_fingerprint = self.root_fingerprint().await?;
+        let mut data = registry.read_registry()?;
+        let mut registered = Vec::new();
+        for script_config in data.script_configs(&root_fingerprint) {
+            let registration = &script_config.registration;
+            let keypath_account = (!registration.keypath.is_empty())
+                .then(|| crate::Keypath::from(registration.keypath.as_slice()));
+            if self
+                .btc_is_script_config_registered(
+                    pb::BtcCoin::try_from(registration.coin).map_err(|_| CrateError::Unknown)?,
+                    registration
+                        .script_config
+                        .as_ref()
+                        .ok_or(CrateError::Unknown)?,
+                    keypath_account.as_ref(),
+                )
+                .await?
+            {
+                registered.push(script_config.clone());
+            }
+        }
+        if registered.is_empty() {
+            data.devices.remove(&root_fingerprint);
+        } else {
+            data.devices
+                .insert(root_fingerprint.clone(), registered.clone());
+        }
+        registry.store_registry(&data)?;
+        Ok(registered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btc::make_script_config_policy;
+
+    fn script_config(name: &str) -> RegisteredScriptConfig {
+        let xpub = "tpubDFgycCkexSxkdZfeyaasDHityE97kiYM1BeCNoivDHvydGugKtoNobt4vEX6YSHNPy2cqmWQHKjKxciJuocepsGPGxcDZVmiMBnxgA1JKQk".parse().unwrap();
+        RegisteredScriptConfig {
+            registration: pb::BtcScriptConfigRegistration {
+                coin: pb::BtcCoin::Tbtc as _,
+                script_config: Some(make_script_config_policy(
+                    "wsh(pk(@0/**))",
+                    &[crate::btc::KeyOriginInfo {
+                        root_fingerprint: Some([1, 2, 3, 4].into()),
+                        keypath: Some("m/48'/1'/0'/2'".try_into().unwrap()),
+                        xpub,
+                    }],
+                )),
+                keypath: vec![],
+            },
+            name: name.into(),
+            xpub_type: XPubType::AutoXpubTpub,
+        }
+    }
+
+    #[test]
+    fn test_registry_data() {
+        let mut data = RegistryData::default();
+        assert!(data.script_configs("01020304").is_empty());
+        data.insert("01020304", script_config("first"));
+        // Same registration: the name is updated.
+        data.insert("01020304", script_config("renamed"));
+        assert_eq!(data.script_configs("01020304"), &[script_config("renamed")]);
+        assert!(data.script_configs("aabbccdd").is_empty());
+
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<RegistryData>(&json).unwrap(), data);
+        assert!(serde_json::from_str::<RegistryData>(
+            r#"{"devices": {"01020304": [{"registration": "zz", "name": "", "xpub_type": 0}]}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_persisted_registry() {
+        let dir = std::env::temp_dir().join(format!("bitbox-registry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let registry = PersistedRegistry::new(dir.to_str().unwrap());
+        assert_eq!(registry.read_registry().unwrap(), RegistryData::default());
+
+        let mut data = RegistryData::default();
+        data.insert("01020304", script_config("first"));
+        registry.store_registry(&data).unwrap();
+        assert_eq!(
+            PersistedRegistry::new(dir.to_str().unwrap())
+                .read_registry()
+                .unwrap(),
+            data
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(
+            registry.store_registry(&data),
+            Err(Error::Store(_))
+        ));
+    }
+}
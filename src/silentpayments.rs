@@ -0,0 +1,353 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-side verification of silent payment outputs generated by the BitBox, see
+//! <https://github.com/bitcoin/bips/blob/master/bip-0352.mediawiki>.
+//!
+//! The BitBox generates the output pubkey of a silent payment and proves with a DLEQ proof
+//! (<https://github.com/bitcoin/bips/blob/master/bip-0374.mediawiki>) that the ECDH share was
+//! computed with the private keys of the inputs. The host must verify this before broadcasting the
+//! transaction, as an incorrect output would send the coins to an unspendable address.
+
+use thiserror::Error;
+
+use bitcoin::hashes::sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32m, ByteIterExt, Fe32, Fe32IterExt, Hrp};
+
+use std::io::Write;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid silent payment address")]
+    InvalidAddress,
+    #[error("silent payments require the pubkeys of all inputs")]
+    MissingInputPubkey,
+    #[error("invalid silent payment input pubkeys")]
+    InvalidInputs,
+    #[error("the silent payment DLEQ proof is invalid")]
+    InvalidProof,
+    #[error("the generated silent payment output is invalid")]
+    InvalidOutput,
+}
+
+fn tagged_sha256(tag: &[u8], msg: &[u8]) -> [u8; 32] {
+    let mut engine = sha256::Hash::engine();
+    let tag_hash = sha256::Hash::hash(tag);
+
+    engine.write_all(tag_hash.as_ref()).unwrap();
+    engine.write_all(tag_hash.as_ref()).unwrap();
+    engine.write_all(msg).unwrap();
+
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// A decoded silent payment address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub scan_pubkey: PublicKey,
+    pub spend_pubkey: PublicKey,
+}
+
+impl Address {
+    /// Decodes a version 0 silent payment address with the "sp" (mainnet) or "tsp" (testnet)
+    /// prefix.
+    pub fn decode(address: &str) -> Result<Self, Error> {
+        let mut checked =
+            CheckedHrpstring::new::<Bech32m>(address).map_err(|_| Error::InvalidAddress)?;
+        if !matches!(checked.hrp().as_str(), "sp" | "tsp") {
+            return Err(Error::InvalidAddress);
+        }
+        if checked.remove_witness_version() != Some(Fe32::Q) {
+            return Err(Error::InvalidAddress);
+        }
+        let data: Vec<u8> = checked.byte_iter().collect();
+        if data.len() != 66 {
+            return Err(Error::InvalidAddress);
+        }
+        Ok(Address {
+            scan_pubkey: PublicKey::from_slice(&data[..33]).map_err(|_| Error::InvalidAddress)?,
+            spend_pubkey: PublicKey::from_slice(&data[33..]).map_err(|_| Error::InvalidAddress)?,
+        })
+    }
+
+    /// Encodes the address as a version 0 silent payment address. `hrp` is "sp" for mainnet and
+    /// "tsp" for testnet.
+    pub fn encode(&self, hrp: &str) -> String {
+        let hrp = Hrp::parse(hrp).unwrap();
+        let mut data = self.scan_pubkey.serialize().to_vec();
+        data.extend_from_slice(&self.spend_pubkey.serialize());
+        data.into_iter()
+            .bytes_to_fes()
+            .with_checksum::<Bech32m>(&hrp)
+            .with_witness_version(Fe32::Q)
+            .chars()
+            .collect()
+    }
+}
+
+/// An input of the transaction, as needed to compute silent payment outputs.
+pub struct Input<'a> {
+    /// Previous transaction ID in internal byte order.
+    pub prev_out_hash: &'a [u8],
+    pub prev_out_index: u32,
+    /// The input's pubkey as defined in BIP-352. For Taproot inputs, this is the output key
+    /// with even Y coordinate.
+    pub pubkey: PublicKey,
+}
+
+/// Computes `input_hash` and the sum of all input pubkeys `A`.
+fn input_hash_and_pubkey_sum(inputs: &[Input]) -> Result<([u8; 32], PublicKey), Error> {
+    let pubkeys: Vec<&PublicKey> = inputs.iter().map(|input| &input.pubkey).collect();
+    let pubkey_sum = PublicKey::combine_keys(&pubkeys).map_err(|_| Error::InvalidInputs)?;
+
+    let smallest_outpoint = inputs
+        .iter()
+        .map(|input| {
+            let mut outpoint = input.prev_out_hash.to_vec();
+            outpoint.extend_from_slice(&input.prev_out_index.to_le_bytes());
+            outpoint
+        })
+        .min()
+        .ok_or(Error::InvalidInputs)?;
+
+    let mut msg = smallest_outpoint;
+    msg.extend_from_slice(&pubkey_sum.serialize());
+    Ok((tagged_sha256(b"BIP0352/Inputs", &msg), pubkey_sum))
+}
+
+/// Computes the DLEQ challenge as defined in BIP-374, without the optional message.
+fn dleq_challenge(
+    a: &PublicKey,
+    b: &PublicKey,
+    c: &PublicKey,
+    g: &PublicKey,
+    r1: &PublicKey,
+    r2: &PublicKey,
+) -> [u8; 32] {
+    let mut msg = Vec::with_capacity(6 * 33);
+    for point in [a, b, c, g, r1, r2] {
+        msg.extend_from_slice(&point.serialize());
+    }
+    tagged_sha256(b"BIP0374/challenge", &msg)
+}
+
+/// Verifies a BIP-374 DLEQ proof (`e || s`, 64 bytes) that `log_G(a) == log_b(c)`.
+fn dleq_verify(a: &PublicKey, b: &PublicKey, c: &PublicKey, proof: &[u8]) -> Result<(), Error> {
+    let secp = Secp256k1::new();
+    let g = SecretKey::from_slice(&Scalar::ONE.to_be_bytes())
+        .unwrap()
+        .public_key(&secp);
+
+    let (e, s) = proof.split_at(32);
+    let e = Scalar::from_be_bytes(e.try_into().unwrap()).map_err(|_| Error::InvalidProof)?;
+    let s = SecretKey::from_slice(s).map_err(|_| Error::InvalidProof)?;
+
+    // R1 = s*G - e*A
+    let r1 = s
+        .public_key(&secp)
+        .combine(
+            &a.mul_tweak(&secp, &e)
+                .map_err(|_| Error::InvalidProof)?
+                .negate(&secp),
+        )
+        .map_err(|_| Error::InvalidProof)?;
+    // R2 = s*B - e*C
+    let r2 = b
+        .mul_tweak(&secp, &Scalar::from(s))
+        .map_err(|_| Error::InvalidProof)?
+        .combine(
+            &c.mul_tweak(&secp, &e)
+                .map_err(|_| Error::InvalidProof)?
+                .negate(&secp),
+        )
+        .map_err(|_| Error::InvalidProof)?;
+
+    if dleq_challenge(a, b, c, &g, &r1, &r2) != e.to_be_bytes() {
+        return Err(Error::InvalidProof);
+    }
+    Ok(())
+}
+
+/// Verifies a silent payment output generated by the BitBox.
+///
+/// `k` is the number of silent payment outputs preceding this one in the transaction that have the
+/// same scan pubkey. `dleq_proof` is the 33 byte ECDH share `a*B_scan` followed by the 64 byte
+/// DLEQ proof that it was computed using the sum of the input private keys.
+pub fn verify_output(
+    inputs: &[Input],
+    address: &Address,
+    k: u32,
+    generated_output_pkscript: &[u8],
+    dleq_proof: &[u8],
+) -> Result<(), Error> {
+    if dleq_proof.len() != 33 + 64 {
+        return Err(Error::InvalidProof);
+    }
+    let (ecdh_share, proof) = dleq_proof.split_at(33);
+    let ecdh_share = PublicKey::from_slice(ecdh_share).map_err(|_| Error::InvalidProof)?;
+
+    let (input_hash, pubkey_sum) = input_hash_and_pubkey_sum(inputs)?;
+    dleq_verify(&pubkey_sum, &address.scan_pubkey, &ecdh_share, proof)?;
+
+    let secp = Secp256k1::new();
+    let input_hash = Scalar::from_be_bytes(input_hash).map_err(|_| Error::InvalidInputs)?;
+    let shared_secret = ecdh_share
+        .mul_tweak(&secp, &input_hash)
+        .map_err(|_| Error::InvalidInputs)?;
+
+    let mut msg = shared_secret.serialize().to_vec();
+    msg.extend_from_slice(&k.to_be_bytes());
+    let t_k = Scalar::from_be_bytes(tagged_sha256(b"BIP0352/SharedSecret", &msg))
+        .map_err(|_| Error::InvalidOutput)?;
+    let output_pubkey = address
+        .spend_pubkey
+        .add_exp_tweak(&secp, &t_k)
+        .map_err(|_| Error::InvalidOutput)?;
+
+    let mut expected_pkscript = vec![0x51, 0x20];
+    expected_pkscript.extend_from_slice(&output_pubkey.x_only_public_key().0.serialize());
+    if generated_output_pkscript != expected_pkscript.as_slice() {
+        return Err(Error::InvalidOutput);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pubkey(privkey: &SecretKey) -> PublicKey {
+        privkey.public_key(&Secp256k1::new())
+    }
+
+    /// Creates the ECDH share and DLEQ proof the same way the BitBox does.
+    fn prove(input_privkey_sum: &SecretKey, scan_pubkey: &PublicKey) -> Vec<u8> {
+        let secp = Secp256k1::new();
+        let ecdh_share = scan_pubkey
+            .mul_tweak(&secp, &Scalar::from(*input_privkey_sum))
+            .unwrap();
+        let g = pubkey(&SecretKey::from_slice(&Scalar::ONE.to_be_bytes()).unwrap());
+        let nonce = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let r1 = pubkey(&nonce);
+        let r2 = scan_pubkey.mul_tweak(&secp, &Scalar::from(nonce)).unwrap();
+        let e = dleq_challenge(
+            &pubkey(input_privkey_sum),
+            scan_pubkey,
+            &ecdh_share,
+            &g,
+            &r1,
+            &r2,
+        );
+        // s = k + e*a
+        let s = input_privkey_sum
+            .mul_tweak(&Scalar::from_be_bytes(e).unwrap())
+            .unwrap()
+            .add_tweak(&Scalar::from(nonce))
+            .unwrap();
+        let mut result = ecdh_share.serialize().to_vec();
+        result.extend_from_slice(&e);
+        result.extend_from_slice(&s.secret_bytes());
+        result
+    }
+
+    #[test]
+    fn test_address_encode_decode() {
+        let address = Address {
+            scan_pubkey: pubkey(&SecretKey::from_slice(&[1; 32]).unwrap()),
+            spend_pubkey: pubkey(&SecretKey::from_slice(&[2; 32]).unwrap()),
+        };
+        let encoded = address.encode("sp");
+        assert!(encoded.starts_with("sp1q"));
+        assert_eq!(Address::decode(&encoded).unwrap(), address);
+        assert!(address.encode("tsp").starts_with("tsp1q"));
+        assert_eq!(Address::decode(&address.encode("tsp")).unwrap(), address);
+
+        // Wrong prefix.
+        assert!(Address::decode(&address.encode("bc")).is_err());
+        // Invalid checksum.
+        let mut invalid = encoded.clone();
+        invalid.pop();
+        invalid.push(if encoded.ends_with('q') { 'p' } else { 'q' });
+        assert!(Address::decode(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_verify_output() {
+        let secp = Secp256k1::new();
+        let input_privkey1 = SecretKey::from_slice(&[3; 32]).unwrap();
+        let input_privkey2 = SecretKey::from_slice(&[4; 32]).unwrap();
+        let scan_privkey = SecretKey::from_slice(&[5; 32]).unwrap();
+        let spend_privkey = SecretKey::from_slice(&[6; 32]).unwrap();
+        let address = Address {
+            scan_pubkey: pubkey(&scan_privkey),
+            spend_pubkey: pubkey(&spend_privkey),
+        };
+        let inputs = [
+            Input {
+                prev_out_hash: &[0xbb; 32],
+                prev_out_index: 0,
+                pubkey: pubkey(&input_privkey1),
+            },
+            Input {
+                prev_out_hash: &[0xaa; 32],
+                prev_out_index: 1,
+                pubkey: pubkey(&input_privkey2),
+            },
+        ];
+        let input_privkey_sum = input_privkey1
+            .add_tweak(&Scalar::from(input_privkey2))
+            .unwrap();
+
+        // Compute the expected output from the receiver's side: b_scan * input_hash * A.
+        let (input_hash, pubkey_sum) = input_hash_and_pubkey_sum(&inputs).unwrap();
+        let shared_secret = pubkey_sum
+            .mul_tweak(&secp, &Scalar::from(scan_privkey))
+            .unwrap()
+            .mul_tweak(&secp, &Scalar::from_be_bytes(input_hash).unwrap())
+            .unwrap();
+        let pkscript_for_k = |k: u32| {
+            let mut msg = shared_secret.serialize().to_vec();
+            msg.extend_from_slice(&k.to_be_bytes());
+            let t_k = tagged_sha256(b"BIP0352/SharedSecret", &msg);
+            let output_privkey = spend_privkey
+                .add_tweak(&Scalar::from_be_bytes(t_k).unwrap())
+                .unwrap();
+            let mut pkscript = vec![0x51, 0x20];
+            pkscript.extend_from_slice(&output_privkey.x_only_public_key(&secp).0.serialize());
+            pkscript
+        };
+
+        let proof = prove(&input_privkey_sum, &address.scan_pubkey);
+        assert!(verify_output(&inputs, &address, 0, &pkscript_for_k(0), &proof).is_ok());
+        assert!(verify_output(&inputs, &address, 1, &pkscript_for_k(1), &proof).is_ok());
+
+        // Wrong k.
+        assert!(matches!(
+            verify_output(&inputs, &address, 1, &pkscript_for_k(0), &proof),
+            Err(Error::InvalidOutput)
+        ));
+        // Proof created with the wrong input keys.
+        assert!(matches!(
+            verify_output(
+                &inputs,
+                &address,
+                0,
+                &pkscript_for_k(0),
+                &prove(&input_privkey1, &address.scan_pubkey)
+            ),
+            Err(Error::InvalidProof)
+        ));
+        // Tampered proof.
+        let mut tampered_proof = proof.clone();
+        tampered_proof[40] ^= 1;
+        assert!(matches!(
+            verify_output(&inputs, &address, 0, &pkscript_for_k(0), &tampered_proof),
+            Err(Error::InvalidProof)
+        ));
+        // Missing input changes the input hash and the pubkey sum.
+        assert!(verify_output(&inputs[..1], &address, 0, &pkscript_for_k(0), &proof).is_err());
+    }
+}
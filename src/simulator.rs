@@ -59,6 +59,10 @@ impl ReadWrite for TcpClient {
         let n = stream
             .read(&mut buffer)
             .map_err(|_| CommunicationError::Read)?;
+        if n == 0 {
+            // The simulator closed the connection, e.g. because it rebooted.
+            return Err(CommunicationError::Read);
+        }
         buffer.truncate(n);
         Ok(buffer)
     }
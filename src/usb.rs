@@ -19,6 +19,15 @@ const FIRMWARE_PRODUCT_STRING_BITBOX02_NOVA_MULTI: &str = "BitBox02 Nova Multi";
 /// The hid product string of the BitBox02 Nova btc-only edition firmware.
 const FIRMWARE_PRODUCT_STRING_BITBOX02_NOVA_BTCONLY: &str = "BitBox02 Nova BTC-only";
 
+/// The hid product string of the BitBox02 multi edition bootloader.
+const BOOTLOADER_PRODUCT_STRING_BITBOX02_MULTI: &str = "bb02-bootloader";
+/// The hid product string of the BitBox02 btc-only edition bootloader.
+const BOOTLOADER_PRODUCT_STRING_BITBOX02_BTCONLY: &str = "bb02btc-bootloader";
+/// The hid product string of the BitBox02 Nova multi edition bootloader.
+const BOOTLOADER_PRODUCT_STRING_BITBOX02_NOVA_MULTI: &str = "bb02p-bootloader";
+/// The hid product string of the BitBox02 Nova btc-only edition bootloader.
+const BOOTLOADER_PRODUCT_STRING_BITBOX02_NOVA_BTCONLY: &str = "bb02pbtc-bootloader";
+
 #[cfg(feature = "multithreaded")]
 pub(crate) struct HidDevice(Mutex<hidapi::HidDevice>);
 
@@ -78,7 +87,7 @@ pub enum UsbError {
 }
 
 /// Returns true if this device is a BitBox02 device (any edition). This does not identify BitBox02
-/// bootloaders.
+/// bootloaders, see `is_bitbox02_bootloader()` for that.
 pub fn is_bitbox02(device_info: &hidapi::DeviceInfo) -> bool {
     (matches!(
         device_info.product_string(),
@@ -104,3 +113,39 @@ pub fn get_any_bitbox02() -> Result<hidapi::HidDevice, UsbError> {
     }
     Err(UsbError::NotFound)
 }
+
+/// Maps the hid product string of a BitBox02 bootloader to the product.
+pub(crate) fn bootloader_product(product_string: &str) -> Option<crate::Product> {
+    match product_string {
+        BOOTLOADER_PRODUCT_STRING_BITBOX02_MULTI => Some(crate::Product::BitBox02Multi),
+        BOOTLOADER_PRODUCT_STRING_BITBOX02_BTCONLY => Some(crate::Product::BitBox02BtcOnly),
+        BOOTLOADER_PRODUCT_STRING_BITBOX02_NOVA_MULTI => Some(crate::Product::BitBox02NovaMulti),
+        BOOTLOADER_PRODUCT_STRING_BITBOX02_NOVA_BTCONLY => {
+            Some(crate::Product::BitBox02NovaBtcOnly)
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if this device is a BitBox02 bootloader (any edition).
+pub fn is_bitbox02_bootloader(device_info: &hidapi::DeviceInfo) -> bool {
+    device_info
+        .product_string()
+        .and_then(bootloader_product)
+        .is_some()
+        && device_info.vendor_id() == VENDOR_ID
+        && device_info.product_id() == PRODUCT_ID
+        && (device_info.usage_page() == 0xffff || device_info.interface_number() == 0)
+}
+
+/// Returns the first BitBox02 bootloader HID device that is found, or `Err(UsbError::NotFound)` if
+/// none is available. Use `bootloader::Bootloader::from_hid_device()` to communicate with it.
+pub fn get_any_bitbox02_bootloader() -> Result<hidapi::HidDevice, UsbError> {
+    let api = hidapi::HidApi::new().unwrap();
+    for device_info in api.device_list() {
+        if is_bitbox02_bootloader(device_info) {
+            return Ok(device_info.open_device(&api)?);
+        }
+    }
+    Err(UsbError::NotFound)
+}
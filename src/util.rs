@@ -8,6 +8,17 @@ pub fn remove_leading_zeroes(list: &[u8]) -> Vec<u8> {
     }
 }
 
+/// Returns the current unix timestamp and the local timezone offset in seconds, which the device
+/// uses to timestamp backups.
+pub fn timestamp_and_timezone_offset() -> (u32, i32) {
+    let now = std::time::SystemTime::now();
+    let duration_since_epoch = now.duration_since(std::time::UNIX_EPOCH).unwrap();
+    (
+        duration_since_epoch.as_secs() as u32,
+        chrono::Local::now().offset().local_minus_utc(),
+    )
+}
+
 #[cfg(feature = "multithreaded")]
 pub trait Threading: Sync + Send {}
 
@@ -0,0 +1,927 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Import and export of multisig and policy wallets in the formats of other wallet software, so a
+//! wallet registered with `PairedBitBox::btc_register_script_config()` can be set up in a
+//! coordinator and vice versa:
+//!
+//! - BIP-129 BSMS descriptor records, see <https://github.com/bitcoin/bips/blob/master/bip-0129.mediawiki>.
+//!   Supports multisig and policies.
+//! - Electrum multisig wallet files (JSON).
+//! - Multisig setup text files as used by Coldcard, Sparrow, Specter and Keystone.
+//!
+//! The name of the wallet is only part of the multisig text format, and the xpub type (SLIP-132
+//! `Zpub`/`Ypub` vs. `xpub`) only of the multisig text and Electrum formats. When importing from
+//! other formats, `Wallet::name` is empty and `Wallet::xpub_type` is `AutoXpubTpub`.
+
+use thiserror::Error;
+
+use bitcoin::bip32::{DerivationPath, Fingerprint, Xpub};
+
+use std::str::FromStr;
+
+use crate::btc::{derive_address, make_script_config_multisig, xpub_from_pb, KeyOriginInfo};
+use crate::pb;
+use crate::pb::btc_register_script_config_request::XPubType;
+use crate::pb::btc_script_config::multisig::ScriptType;
+use crate::wallet_policy::{descriptor_checksum, WalletPolicy};
+use crate::Keypath;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("unsupported script config")]
+    UnsupportedScriptConfig,
+    #[error("the key origins do not match the multisig xpubs")]
+    KeyOriginMismatch,
+    #[error("missing root fingerprint of key {0}")]
+    MissingFingerprint(usize),
+    #[error("none of the keys belong to the given root fingerprint")]
+    OurKeyNotFound,
+    #[error("several multisig keys belong to the same root fingerprint")]
+    DuplicateFingerprint,
+    #[error("this format only supports multisig wallets")]
+    MultisigOnly,
+    #[error("invalid xpub: {0}")]
+    InvalidXpub(String),
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+    #[error("wallet policy error: {0}")]
+    WalletPolicy(#[from] crate::wallet_policy::Error),
+    #[error("address error: {0}")]
+    Address(#[from] crate::btc::AddressError),
+    #[error("the first address {0} does not match the descriptor")]
+    AddressMismatch(String),
+}
+
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+const YPUB_MULTISIG_VERSION: [u8; 4] = [0x02, 0x95, 0xb4, 0x3f];
+const UPUB_MULTISIG_VERSION: [u8; 4] = [0x02, 0x42, 0x89, 0xef];
+const ZPUB_MULTISIG_VERSION: [u8; 4] = [0x02, 0xaa, 0x7e, 0xd3];
+const VPUB_MULTISIG_VERSION: [u8; 4] = [0x02, 0x57, 0x54, 0x83];
+
+/// Electrum wallet files are upgraded by Electrum from this version when opened.
+const ELECTRUM_SEED_VERSION: u32 = 17;
+
+/// Script of a wallet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalletScript {
+    /// Sorted multisig (BIP-67), i.e. `wsh(sortedmulti(...))` or `sh(wsh(sortedmulti(...)))`.
+    Multisig {
+        threshold: u32,
+        script_type: ScriptType,
+        /// All keys, including ours.
+        keys: Vec<KeyOriginInfo>,
+    },
+    Policy(WalletPolicy),
+}
+
+/// A multisig or policy wallet with everything needed to set it up in other wallet software.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wallet {
+    pub name: String,
+    pub coin: pb::BtcCoin,
+    pub xpub_type: XPubType,
+    pub script: WalletScript,
+}
+
+fn network_kind(coin: pb::BtcCoin) -> bitcoin::NetworkKind {
+    match coin {
+        pb::BtcCoin::Btc | pb::BtcCoin::Ltc => bitcoin::NetworkKind::Main,
+        pb::BtcCoin::Tbtc | pb::BtcCoin::Rbtc | pb::BtcCoin::Tltc => bitcoin::NetworkKind::Test,
+    }
+}
+
+/// Formats a keypath as e.g. `m/48'/0'/0'/2'`.
+fn keypath_to_string(keypath: Option<&Keypath>) -> String {
+    keypath
+        .map_or(vec![], |keypath| keypath.to_vec())
+        .into_iter()
+        .map(|el| bitcoin::bip32::ChildNumber::from(el).to_string())
+        .fold("m".to_string(), |path, el| path + "/" + &el)
+}
+
+fn parse_keypath(keypath: &str) -> Result<Keypath, Error> {
+    let keypath = keypath.trim();
+    // Coldcard and Electrum also use `h` for hardened elements.
+    DerivationPath::from_str(&keypath.replace(['h', 'H'], "'"))
+        .map(|path| Keypath::from(&path))
+        .map_err(|_| Error::Invalid("derivation"))
+}
+
+fn parse_fingerprint(fingerprint: &str) -> Result<Fingerprint, Error> {
+    Fingerprint::from_str(&fingerprint.trim().to_lowercase())
+        .map_err(|_| Error::Invalid("fingerprint"))
+}
+
+/// Serializes an xpub with the SLIP-132 version of multisig `script_type` if `slip132` is true, or
+/// as a plain xpub/tpub otherwise.
+fn encode_xpub(xpub: &Xpub, script_type: ScriptType, slip132: bool) -> String {
+    let mut payload = xpub.encode();
+    let version = match (xpub.network, script_type, slip132) {
+        (_, _, false) => return xpub.to_string(),
+        (bitcoin::NetworkKind::Main, ScriptType::P2wsh, true) => ZPUB_MULTISIG_VERSION,
+        (bitcoin::NetworkKind::Test, ScriptType::P2wsh, true) => VPUB_MULTISIG_VERSION,
+        (bitcoin::NetworkKind::Main, ScriptType::P2wshP2sh, true) => YPUB_MULTISIG_VERSION,
+        (bitcoin::NetworkKind::Test, ScriptType::P2wshP2sh, true) => UPUB_MULTISIG_VERSION,
+    };
+    payload[..4].copy_from_slice(&version);
+    bitcoin::base58::encode_check(&payload)
+}
+
+/// Parses an xpub which may use a SLIP-132 multisig version. Returns the xpub and, in case of a
+/// SLIP-132 version, the script type it encodes.
+fn decode_xpub(xpub: &str) -> Result<(Xpub, Option<ScriptType>), Error> {
+    let xpub = xpub.trim();
+    let invalid = || Error::InvalidXpub(xpub.into());
+    let mut payload = bitcoin::base58::decode_check(xpub).map_err(|_| invalid())?;
+    if payload.len() != 78 {
+        return Err(invalid());
+    }
+    let (version, script_type) = match payload[..4].try_into().unwrap() {
+        XPUB_VERSION => (XPUB_VERSION, None),
+        TPUB_VERSION => (TPUB_VERSION, None),
+        ZPUB_MULTISIG_VERSION => (XPUB_VERSION, Some(ScriptType::P2wsh)),
+        VPUB_MULTISIG_VERSION => (TPUB_VERSION, Some(ScriptType::P2wsh)),
+        YPUB_MULTISIG_VERSION => (XPUB_VERSION, Some(ScriptType::P2wshP2sh)),
+        UPUB_MULTISIG_VERSION => (TPUB_VERSION, Some(ScriptType::P2wshP2sh)),
+        _ => return Err(invalid()),
+    };
+    payload[..4].copy_from_slice(&version);
+    Ok((Xpub::decode(&payload).map_err(|_| invalid())?, script_type))
+}
+
+fn multisig_template(threshold: u32, script_type: ScriptType, num_keys: usize) -> String {
+    let keys: Vec<String> = (0..num_keys).map(|i| format!("@{}/<0;1>/*", i)).collect();
+    let template = format!("wsh(sortedmulti({},{}))", threshold, keys.join(","));
+    match script_type {
+        ScriptType::P2wsh => template,
+        ScriptType::P2wshP2sh => format!("sh({})", template),
+    }
+}
+
+impl Wallet {
+    /// Creates a wallet from a multisig or policy script config as registered with
+    /// `PairedBitBox::btc_register_script_config()`.
+    ///
+    /// A multisig script config only contains the xpubs, so `key_origins` must contain the key
+    /// origin info of each xpub in the same order. For policies, the key origin info is part of
+    /// the script config and `key_origins` is ignored.
+    pub fn from_script_config(
+        name: &str,
+        coin: pb::BtcCoin,
+        xpub_type: XPubType,
+        script_config: &pb::BtcScriptConfig,
+        key_origins: &[KeyOriginInfo],
+    ) -> Result<Self, Error> {
+        let network = network_kind(coin);
+        let script = match &script_config.config {
+            Some(pb::btc_script_config::Config::Multisig(multisig)) => {
+                let xpubs = multisig
+                    .xpubs
+                    .iter()
+                    .map(xpub_from_pb)
+                    .collect::<Result<Vec<Xpub>, _>>()?;
+                if xpubs.len() != key_origins.len()
+                    || xpubs.iter().zip(key_origins).any(|(xpub, key)| {
+                        xpub.public_key != key.xpub.public_key
+                            || xpub.chain_code != key.xpub.chain_code
+                    })
+                {
+                    return Err(Error::KeyOriginMismatch);
+                }
+                WalletScript::Multisig {
+                    threshold: multisig.threshold,
+                    script_type: ScriptType::try_from(multisig.script_type)
+                        .map_err(|_| Error::UnsupportedScriptConfig)?,
+                    keys: key_origins
+                        .iter()
+                        .map(|key| KeyOriginInfo {
+                            xpub: Xpub {
+                                network,
+                                ..key.xpub
+                            },
+                            ..key.clone()
+                        })
+                        .collect(),
+                }
+            }
+            Some(pb::btc_script_config::Config::Policy(policy)) => {
+                let keys = policy
+                    .keys
+                    .iter()
+                    .map(|key| {
+                        Ok(KeyOriginInfo {
+                            root_fingerprint: match key.root_fingerprint.as_slice() {
+                                [] => None,
+                                fingerprint => Some(
+                                    <[u8; 4]>::try_from(fingerprint)
+                                        .map_err(|_| Error::Invalid("fingerprint"))?
+                                        .into(),
+                                ),
+                            },
+                            keypath: (!key.keypath.is_empty())
+                                .then(|| Keypath::from(key.keypath.as_slice())),
+                            xpub: Xpub {
+                                network,
+                                ..xpub_from_pb(
+                                    key.xpub.as_ref().ok_or(Error::Invalid("policy key"))?,
+                                )?
+                            },
+                        })
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                WalletScript::Policy(WalletPolicy {
+                    template: policy.policy.clone(),
+                    keys,
+                })
+            }
+            _ => return Err(Error::UnsupportedScriptConfig),
+        };
+        Ok(Wallet {
+            name: name.into(),
+            coin,
+            xpub_type,
+            script,
+        })
+    }
+
+    /// Returns the script config and the account keypath to register the wallet with
+    /// `PairedBitBox::btc_register_script_config()`. Our key is identified by `our_root_fingerprint`,
+    /// which may only be used by one key of a multisig wallet. The account keypath is only returned
+    /// for multisig wallets.
+    pub fn script_config(
+        &self,
+        our_root_fingerprint: &Fingerprint,
+    ) -> Result<(pb::BtcScriptConfig, Option<Keypath>), Error> {
+        let keys = match &self.script {
+            WalletScript::Multisig { keys, .. } => keys,
+            WalletScript::Policy(policy) => &policy.keys,
+        };
+        let our_index = keys
+            .iter()
+            .position(|key| key.root_fingerprint.as_ref() == Some(our_root_fingerprint))
+            .ok_or(Error::OurKeyNotFound)?;
+        match &self.script {
+            WalletScript::Multisig {
+                threshold,
+                script_type,
+                keys,
+            } => {
+                if keys[our_index + 1..]
+                    .iter()
+                    .any(|key| key.root_fingerprint.as_ref() == Some(our_root_fingerprint))
+                {
+                    return Err(Error::DuplicateFingerprint);
+                }
+                Ok((
+                    self.multisig_script_config(*threshold, *script_type, keys, our_index as u32),
+                    keys[our_index].keypath.clone(),
+                ))
+            }
+            WalletScript::Policy(policy) => Ok((policy.script_config(), None)),
+        }
+    }
+
+    fn multisig_script_config(
+        &self,
+        threshold: u32,
+        script_type: ScriptType,
+        keys: &[KeyOriginInfo],
+        our_xpub_index: u32,
+    ) -> pb::BtcScriptConfig {
+        let xpubs: Vec<Xpub> = keys.iter().map(|key| key.xpub).collect();
+        make_script_config_multisig(threshold, &xpubs, our_xpub_index, script_type)
+    }
+
+    /// Output descriptor of the wallet, including the checksum.
+    pub fn descriptor(&self) -> String {
+        match &self.script {
+            WalletScript::Multisig {
+                threshold,
+                script_type,
+                keys,
+            } => WalletPolicy {
+                template: multisig_template(*threshold, *script_type, keys.len()),
+                keys: keys.clone(),
+            }
+            .to_descriptor(),
+            WalletScript::Policy(policy) => policy.to_descriptor(),
+        }
+    }
+
+    /// Parses an output descriptor. `sortedmulti` descriptors whose keys are all distinct and use
+    /// the `/<0;1>/*` derivation are imported as multisig, all other `wsh(...)` and `tr(...)`
+    /// descriptors as policies.
+    pub fn from_descriptor(coin: pb::BtcCoin, descriptor: &str) -> Result<Self, Error> {
+        let descriptor = descriptor.trim();
+        let descriptor = match descriptor.split_once('#') {
+            Some((descriptor, checksum)) => {
+                if descriptor_checksum(descriptor)? != checksum {
+                    return Err(crate::wallet_policy::Error::InvalidChecksum.into());
+                }
+                descriptor
+            }
+            None => descriptor,
+        };
+        let (policy, script_type) = match descriptor
+            .strip_prefix("sh(")
+            .and_then(|inner| inner.strip_suffix(')'))
+        {
+            Some(inner) => (WalletPolicy::from_descriptor(inner)?, ScriptType::P2wshP2sh),
+            None => (
+                WalletPolicy::from_descriptor(descriptor)?,
+                ScriptType::P2wsh,
+            ),
+        };
+        let threshold = policy
+            .template
+            .strip_prefix("wsh(sortedmulti(")
+            .and_then(|rest| rest.split(',').next())
+            .and_then(|threshold| threshold.parse::<u32>().ok());
+        let network = network_kind(coin);
+        let script = match threshold {
+            Some(threshold)
+                if policy.template
+                    == multisig_template(threshold, ScriptType::P2wsh, policy.keys.len()) =>
+            {
+                WalletScript::Multisig {
+                    threshold,
+                    script_type,
+                    keys: policy
+                        .keys
+                        .into_iter()
+                        .map(|key| KeyOriginInfo {
+                            xpub: Xpub {
+                                network,
+                                ..key.xpub
+                            },
+                            ..key
+                        })
+                        .collect(),
+                }
+            }
+            _ if script_type == ScriptType::P2wsh => WalletScript::Policy(policy),
+            _ => return Err(Error::UnsupportedScriptConfig),
+        };
+        Ok(Wallet {
+            name: String::new(),
+            coin,
+            xpub_type: XPubType::AutoXpubTpub,
+            script,
+        })
+    }
+
+    /// Derives the first receive address, as contained in BSMS records. Deriving policy addresses
+    /// requires the `miniscript` feature.
+    pub fn first_address(&self) -> Result<String, Error> {
+        let (script_config, key, receive_index) = match &self.script {
+            WalletScript::Multisig {
+                threshold,
+                script_type,
+                keys,
+            } => (
+                self.multisig_script_config(*threshold, *script_type, keys, 0),
+                keys.first().ok_or(Error::Invalid("multisig"))?,
+                0,
+            ),
+            WalletScript::Policy(policy) => {
+                // Derive the address from the receive path of the key `@0`.
+                let receive_index = policy
+                    .template
+                    .split_once("@0/<")
+                    .and_then(|(_, rest)| rest.split_once(';'))
+                    .and_then(|(receive, _)| receive.parse::<u32>().ok())
+                    .ok_or(Error::Invalid("policy template"))?;
+                (
+                    policy.script_config(),
+                    policy.keys.first().ok_or(Error::Invalid("policy"))?,
+                    receive_index,
+                )
+            }
+        };
+        let mut keypath = key.keypath.as_ref().map_or(vec![], Keypath::to_vec);
+        keypath.extend([receive_index, 0]);
+        Ok(derive_address(
+            self.coin,
+            &keypath.as_slice().into(),
+            &script_config,
+            None,
+        )?)
+    }
+
+    /// Exports the wallet as a BIP-129 BSMS descriptor record (version 1.0).
+    pub fn to_bsms(&self) -> Result<String, Error> {
+        let descriptor = self.descriptor();
+        let descriptor = descriptor
+            .split('#')
+            .next()
+            .unwrap()
+            .replace("/<0;1>/*", "/**");
+        Ok(format!(
+            "BSMS 1.0\n{}#{}\n/0/*,/1/*\n{}\n",
+            descriptor,
+            descriptor_checksum(&descriptor)?,
+            self.first_address()?,
+        ))
+    }
+
+    /// Imports a BIP-129 BSMS descriptor record (version 1.0). The first address of the record is
+    /// verified, except for policies if the `miniscript` feature is disabled.
+    pub fn from_bsms(coin: pb::BtcCoin, bsms: &str) -> Result<Self, Error> {
+        let lines: Vec<&str> = bsms
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let [header, descriptor, _path_restrictions, address] = lines.as_slice() else {
+            return Err(Error::Invalid("BSMS record"));
+        };
+        if *header != "BSMS 1.0" {
+            return Err(Error::Invalid("BSMS version"));
+        }
+        let wallet = Wallet::from_descriptor(coin, descriptor)?;
+        match wallet.first_address() {
+            Ok(first_address) if first_address != *address => {
+                return Err(Error::AddressMismatch(address.to_string()))
+            }
+            Ok(_) | Err(Error::Address(crate::btc::AddressError::Policy(_))) => {}
+            Err(err) => return Err(err),
+        }
+        Ok(wallet)
+    }
+
+    fn multisig(&self) -> Result<(u32, ScriptType, &[KeyOriginInfo]), Error> {
+        match &self.script {
+            WalletScript::Multisig {
+                threshold,
+                script_type,
+                keys,
+            } => Ok((*threshold, *script_type, keys)),
+            WalletScript::Policy(_) => Err(Error::MultisigOnly),
+        }
+    }
+
+    /// Exports the wallet as an Electrum multisig wallet file. Electrum identifies the script type
+    /// by the SLIP-132 version of the xpubs, so they are always serialized as `Zpub`/`Ypub` (or
+    /// `Vpub`/`Upub` on testnets).
+    pub fn to_electrum(&self) -> Result<String, Error> {
+        let (threshold, script_type, keys) = self.multisig()?;
+        let mut wallet = serde_json::Map::new();
+        wallet.insert(
+            "wallet_type".into(),
+            format!("{}of{}", threshold, keys.len()).into(),
+        );
+        for (i, key) in keys.iter().enumerate() {
+            wallet.insert(
+                format!("x{}/", i + 1),
+                serde_json::json!({
+                    "type": "bip32",
+                    "xpub": encode_xpub(&key.xpub, script_type, true),
+                    "xprv": null,
+                    "derivation": keypath_to_string(key.keypath.as_ref()),
+                    "root_fingerprint": key
+                        .root_fingerprint
+                        .ok_or(Error::MissingFingerprint(i))?
+                        .to_string(),
+                    "label": "",
+                }),
+            );
+        }
+        wallet.insert("use_encryption".into(), false.into());
+        wallet.insert("seed_version".into(), ELECTRUM_SEED_VERSION.into());
+        Ok(serde_json::to_string_pretty(&wallet).unwrap())
+    }
+
+    /// Imports an Electrum multisig wallet file. Only unencrypted watch-only wallets with p2wsh or
+    /// p2wsh-p2sh xpubs are supported.
+    pub fn from_electrum(coin: pb::BtcCoin, json: &str) -> Result<Self, Error> {
+        let invalid = || Error::Invalid("Electrum wallet");
+        let wallet: serde_json::Value = serde_json::from_str(json).map_err(|_| invalid())?;
+        let (threshold, num_keys) = wallet["wallet_type"]
+            .as_str()
+            .and_then(|wallet_type| wallet_type.split_once("of"))
+            .and_then(|(m, n)| Some((m.parse::<u32>().ok()?, n.parse::<usize>().ok()?)))
+            .ok_or(Error::MultisigOnly)?;
+        let mut script_type = None;
+        let mut keys = Vec::with_capacity(num_keys);
+        for i in 1..=num_keys {
+            let keystore = &wallet[format!("x{}/", i)];
+            let (xpub, key_script_type) =
+                decode_xpub(keystore["xpub"].as_str().ok_or_else(invalid)?)?;
+            let key_script_type = key_script_type.ok_or(Error::UnsupportedScriptConfig)?;
+            if *script_type.get_or_insert(key_script_type) != key_script_type {
+                return Err(Error::UnsupportedScriptConfig);
+            }
+            keys.push(KeyOriginInfo {
+                root_fingerprint: keystore["root_fingerprint"]
+                    .as_str()
+                    .map(parse_fingerprint)
+                    .transpose()?,
+                keypath: keystore["derivation"]
+                    .as_str()
+                    .map(parse_keypath)
+                    .transpose()?,
+                xpub: Xpub {
+                    network: network_kind(coin),
+                    ..xpub
+                },
+            });
+        }
+        Ok(Wallet {
+            name: String::new(),
+            coin,
+            xpub_type: XPubType::AutoElectrum,
+            script: WalletScript::Multisig {
+                threshold,
+                script_type: script_type.ok_or_else(invalid)?,
+                keys,
+            },
+        })
+    }
+
+    /// Exports the wallet as a multisig setup text file, as used by Coldcard, Sparrow, Specter and
+    /// Keystone. The xpubs are serialized as `Zpub`/`Ypub` if `xpub_type` is `AutoElectrum`, and
+    /// as plain xpubs otherwise.
+    pub fn to_multisig_text(&self) -> Result<String, Error> {
+        let (threshold, script_type, keys) = self.multisig()?;
+        let mut text = format!(
+            "Name: {}\nPolicy: {} of {}\nFormat: {}\n",
+            self.name,
+            threshold,
+            keys.len(),
+            match script_type {
+                ScriptType::P2wsh => "P2WSH",
+                ScriptType::P2wshP2sh => "P2SH-P2WSH",
+            },
+        );
+        let mut last_derivation = None;
+        for (i, key) in keys.iter().enumerate() {
+            let derivation = keypath_to_string(key.keypath.as_ref());
+            if last_derivation.as_ref() != Some(&derivation) {
+                text.push_str(&format!("\nDerivation: {}\n", derivation));
+            }
+            text.push_str(&format!(
+                "{}: {}\n",
+                key.root_fingerprint
+                    .ok_or(Error::MissingFingerprint(i))?
+                    .to_string()
+                    .to_uppercase(),
+                encode_xpub(
+                    &key.xpub,
+                    script_type,
+                    self.xpub_type == XPubType::AutoElectrum
+                ),
+            ));
+            last_derivation = Some(derivation);
+        }
+        Ok(text)
+    }
+
+    /// Imports a multisig setup text file, as used by Coldcard, Sparrow, Specter and Keystone.
+    /// Lines starting with `#` are comments. A `Derivation:` line applies to all following keys.
+    pub fn from_multisig_text(coin: pb::BtcCoin, text: &str) -> Result<Self, Error> {
+        let mut name = String::new();
+        let mut policy = None;
+        let mut script_type = None;
+        let mut derivation = None;
+        let mut keys = Vec::new();
+        let mut slip132 = false;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (label, value) = line
+                .split_once(':')
+                .ok_or(Error::Invalid("multisig setup file"))?;
+            let value = value.trim();
+            match label.trim().to_lowercase().as_str() {
+                "name" => name = value.into(),
+                "policy" => {
+                    policy = value
+                        .split_once("of")
+                        .and_then(|(m, n)| {
+                            Some((
+                                m.trim().parse::<u32>().ok()?,
+                                n.trim().parse::<usize>().ok()?,
+                            ))
+                        })
+                        .map(Some)
+                        .ok_or(Error::Invalid("policy"))?
+                }
+                "derivation" => derivation = Some(parse_keypath(value)?),
+                "format" => {
+                    script_type = Some(match value.to_uppercase().as_str() {
+                        "P2WSH" => ScriptType::P2wsh,
+                        "P2SH-P2WSH" | "P2WSH-P2SH" => ScriptType::P2wshP2sh,
+                        _ => return Err(Error::UnsupportedScriptConfig),
+                    })
+                }
+                fingerprint => {
+                    let (xpub, xpub_script_type) = decode_xpub(value)?;
+                    slip132 |= xpub_script_type.is_some();
+                    keys.push((
+                        KeyOriginInfo {
+                            root_fingerprint: Some(parse_fingerprint(fingerprint)?),
+                            keypath: derivation.clone(),
+                            xpub: Xpub {
+                                network: network_kind(coin),
+                                ..xpub
+                            },
+                        },
+                        xpub_script_type,
+                    ));
+                }
+            }
+        }
+        let (threshold, num_keys) = policy.ok_or(Error::Invalid("policy"))?;
+        // Without a `Format:` line, the format is implied by the SLIP-132 xpubs.
+        let script_type = script_type
+            .or_else(|| keys.first().and_then(|(_, script_type)| *script_type))
+            .ok_or(Error::Invalid("format"))?;
+        if num_keys != keys.len()
+            || keys
+                .iter()
+                .any(|(_, key_script_type)| key_script_type.is_some_and(|t| t != script_type))
+        {
+            return Err(Error::Invalid("multisig setup file"));
+        }
+        Ok(Wallet {
+            name,
+            coin,
+            xpub_type: if slip132 {
+                XPubType::AutoElectrum
+            } else {
+                XPubType::AutoXpubTpub
+            },
+            script: WalletScript::Multisig {
+                threshold,
+                script_type,
+                keys: keys.into_iter().map(|(key, _)| key).collect(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btc::make_script_config_policy;
+    use bitcoin::bip32::Xpriv;
+
+    fn key(seed: u8, path: &str) -> KeyOriginInfo {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = Xpriv::new_master(bitcoin::NetworkKind::Main, &[seed; 32]).unwrap();
+        KeyOriginInfo {
+            root_fingerprint: Some(xprv.fingerprint(&secp)),
+            keypath: Some(path.try_into().unwrap()),
+            xpub: Xpub::from_priv(
+                &secp,
+                &xprv
+                    .derive_priv(&secp, &DerivationPath::from_str(path).unwrap())
+                    .unwrap(),
+            ),
+        }
+    }
+
+    fn multisig_wallet(script_type: ScriptType) -> Wallet {
+        let keys = vec![
+            key(1, "m/48'/0'/0'/2'"),
+            key(2, "m/48'/0'/0'/2'"),
+            key(3, "m/48'/0'/1'/2'"),
+        ];
+        let xpubs: Vec<Xpub> = keys.iter().map(|key| key.xpub).collect();
+        Wallet::from_script_config(
+            "my wallet",
+            pb::BtcCoin::Btc,
+            XPubType::AutoElectrum,
+            &make_script_config_multisig(2, &xpubs, 1, script_type),
+            &keys,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_script_config() {
+        let wallet = multisig_wallet(ScriptType::P2wsh);
+        let keys = match &wallet.script {
+            WalletScript::Multisig { keys, .. } => keys.clone(),
+            _ => panic!("expected multisig"),
+        };
+        let xpubs: Vec<Xpub> = keys.iter().map(|key| key.xpub).collect();
+        assert_eq!(
+            wallet
+                .script_config(&keys[2].root_fingerprint.unwrap())
+                .unwrap(),
+            (
+                make_script_config_multisig(2, &xpubs, 2, ScriptType::P2wsh),
+                Some("m/48'/0'/1'/2'".try_into().unwrap()),
+            )
+        );
+        assert_eq!(
+            wallet.script_config(&Fingerprint::from([0; 4])),
+            Err(Error::OurKeyNotFound)
+        );
+        let mut duplicate = wallet.clone();
+        if let WalletScript::Multisig { keys, .. } = &mut duplicate.script {
+            keys[1].root_fingerprint = keys[0].root_fingerprint;
+        }
+        assert_eq!(
+            duplicate.script_config(&keys[0].root_fingerprint.unwrap()),
+            Err(Error::DuplicateFingerprint)
+        );
+
+        // Key origins must match the xpubs.
+        assert_eq!(
+            Wallet::from_script_config(
+                "",
+                pb::BtcCoin::Btc,
+                XPubType::AutoElectrum,
+                &make_script_config_multisig(2, &xpubs, 0, ScriptType::P2wsh),
+                &keys[..2],
+            ),
+            Err(Error::KeyOriginMismatch)
+        );
+
+        // Policies carry their key origins.
+        let policy = make_script_config_policy("wsh(pk(@0/**))", &keys[..1]);
+        let wallet = Wallet::from_script_config(
+            "policy",
+            pb::BtcCoin::Btc,
+            XPubType::AutoXpubTpub,
+            &policy,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            wallet.script_config(&keys[0].root_fingerprint.unwrap()),
+            Ok((policy, None))
+        );
+    }
+
+    #[test]
+    fn test_bsms() {
+        for script_type in [ScriptType::P2wsh, ScriptType::P2wshP2sh] {
+            let wallet = multisig_wallet(script_type);
+            let bsms = wallet.to_bsms().unwrap();
+            let lines: Vec<&str> = bsms.lines().collect();
+            assert_eq!(lines[0], "BSMS 1.0");
+            assert!(lines[1].contains("sortedmulti(2,[") && lines[1].contains("/**"));
+            assert_eq!(lines[2], "/0/*,/1/*");
+            assert_eq!(lines[3], wallet.first_address().unwrap());
+
+            let imported = Wallet::from_bsms(pb::BtcCoin::Btc, &bsms).unwrap();
+            assert_eq!(imported.script, wallet.script);
+            assert_eq!(imported.descriptor(), wallet.descriptor());
+        }
+        let wallet = multisig_wallet(ScriptType::P2wsh);
+        assert!(wallet.first_address().unwrap().starts_with("bc1q"));
+        assert!(multisig_wallet(ScriptType::P2wshP2sh)
+            .first_address()
+            .unwrap()
+            .starts_with('3'));
+
+        // Wrong first address.
+        let bsms = wallet.to_bsms().unwrap();
+        let tampered = bsms.replace(
+            &wallet.first_address().unwrap(),
+            &multisig_wallet(ScriptType::P2wshP2sh)
+                .first_address()
+                .unwrap(),
+        );
+        assert!(matches!(
+            Wallet::from_bsms(pb::BtcCoin::Btc, &tampered),
+            Err(Error::AddressMismatch(_))
+        ));
+        assert_eq!(
+            Wallet::from_bsms(pb::BtcCoin::Btc, &bsms.replace("BSMS 1.0", "BSMS 2.0")),
+            Err(Error::Invalid("BSMS version"))
+        );
+
+        // Policies are imported as such.
+        let keys = vec![key(1, "m/48'/0'/0'/2'"), key(2, "m/48'/0'/0'/2'")];
+        let policy = WalletPolicy {
+            template: "wsh(or_d(pk(@0/<0;1>/*),and_v(v:pkh(@1/<0;1>/*),older(144))))".into(),
+            keys,
+        };
+        let descriptor = policy.to_descriptor();
+        let wallet = Wallet::from_descriptor(pb::BtcCoin::Btc, &descriptor).unwrap();
+        assert_eq!(wallet.script, WalletScript::Policy(policy));
+        assert_eq!(wallet.descriptor(), descriptor);
+    }
+
+    #[cfg(feature = "miniscript")]
+    #[test]
+    fn test_bsms_policy() {
+        let keys = vec![key(1, "m/48'/0'/0'/2'"), key(2, "m/48'/0'/0'/2'")];
+        let wallet = Wallet {
+            name: String::new(),
+            coin: pb::BtcCoin::Btc,
+            xpub_type: XPubType::AutoXpubTpub,
+            script: WalletScript::Policy(WalletPolicy {
+                template: "wsh(or_d(pk(@0/<0;1>/*),and_v(v:pkh(@1/<0;1>/*),older(144))))".into(),
+                keys,
+            }),
+        };
+        let bsms = wallet.to_bsms().unwrap();
+        assert!(bsms.lines().nth(3).unwrap().starts_with("bc1q"));
+        assert_eq!(Wallet::from_bsms(pb::BtcCoin::Btc, &bsms), Ok(wallet));
+    }
+
+    #[test]
+    fn test_electrum() {
+        for script_type in [ScriptType::P2wsh, ScriptType::P2wshP2sh] {
+            let wallet = multisig_wallet(script_type);
+            let json = wallet.to_electrum().unwrap();
+            let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed["wallet_type"], "2of3");
+            assert_eq!(parsed["x3/"]["derivation"], "m/48'/0'/1'/2'");
+            assert!(parsed["x1/"]["xpub"]
+                .as_str()
+                .unwrap()
+                .starts_with(match script_type {
+                    ScriptType::P2wsh => "Zpub",
+                    ScriptType::P2wshP2sh => "Ypub",
+                }));
+            let imported = Wallet::from_electrum(pb::BtcCoin::Btc, &json).unwrap();
+            assert_eq!(
+                imported,
+                Wallet {
+                    name: String::new(),
+                    ..wallet
+                }
+            );
+        }
+        let policy_wallet = Wallet::from_descriptor(
+            pb::BtcCoin::Btc,
+            &format!("wsh(pk({}/**))", key(1, "m/48'/0'/0'/2'").xpub),
+        )
+        .unwrap();
+        assert_eq!(policy_wallet.to_electrum(), Err(Error::MultisigOnly));
+        assert_eq!(
+            Wallet::from_electrum(pb::BtcCoin::Btc, r#"{"wallet_type": "standard"}"#),
+            Err(Error::MultisigOnly)
+        );
+    }
+
+    #[test]
+    fn test_multisig_text() {
+        for script_type in [ScriptType::P2wsh, ScriptType::P2wshP2sh] {
+            for xpub_type in [XPubType::AutoElectrum, XPubType::AutoXpubTpub] {
+                let wallet = Wallet {
+                    xpub_type,
+                    ..multisig_wallet(script_type)
+                };
+                let text = wallet.to_multisig_text().unwrap();
+                assert_eq!(
+                    Wallet::from_multisig_text(pb::BtcCoin::Btc, &text),
+                    Ok(wallet)
+                );
+            }
+        }
+
+        let wallet = Wallet {
+            xpub_type: XPubType::AutoXpubTpub,
+            ..multisig_wallet(ScriptType::P2wsh)
+        };
+        let keys = match &wallet.script {
+            WalletScript::Multisig { keys, .. } => keys.clone(),
+            _ => panic!("expected multisig"),
+        };
+        let key_line = |key: &KeyOriginInfo| {
+            format!(
+                "{}: {}",
+                key.root_fingerprint.unwrap().to_string().to_uppercase(),
+                key.xpub
+            )
+        };
+        assert_eq!(
+            wallet.to_multisig_text().unwrap(),
+            format!(
+                "Name: my wallet\nPolicy: 2 of 3\nFormat: P2WSH\n\nDerivation: m/48'/0'/0'/2'\n{}\n{}\n\nDerivation: m/48'/0'/1'/2'\n{}\n",
+                key_line(&keys[0]),
+                key_line(&keys[1]),
+                key_line(&keys[2]),
+            )
+        );
+
+        // Coldcard style file with comments and `h` for hardened derivations.
+        let text = format!(
+            "# Coldcard Multisig setup file\n#\nName: my wallet\nPolicy: 2 of 3\nDerivation: m/48h/0h/0h/2h\nFormat: P2WSH\n\n{}\n{}\nDerivation: m/48h/0h/1h/2h\n{}\n",
+            key_line(&keys[0]),
+            key_line(&keys[1]),
+            key_line(&keys[2]),
+        );
+        assert_eq!(
+            Wallet::from_multisig_text(pb::BtcCoin::Btc, &text),
+            Ok(wallet.clone())
+        );
+        assert_eq!(
+            Wallet::from_multisig_text(pb::BtcCoin::Btc, &text.replace("2 of 3", "2 of 4")),
+            Err(Error::Invalid("multisig setup file"))
+        );
+        assert_eq!(
+            Wallet::from_multisig_text(pb::BtcCoin::Btc, &text.replace("P2WSH", "P2SH")),
+            Err(Error::UnsupportedScriptConfig)
+        );
+    }
+}
@@ -0,0 +1,509 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversion between output descriptors and BIP-388 wallet policies, see
+//! <https://github.com/bitcoin/bips/blob/master/bip-0388.mediawiki>.
+//!
+//! A wallet policy consists of a descriptor template in which the keys are replaced by
+//! placeholders `@0`, `@1`, etc., and the list of the keys with their origin info. This is the
+//! format used by the BitBox to register and use policies (see `btc::make_script_config_policy()`).
+
+use thiserror::Error;
+
+use bitcoin::bip32::{DerivationPath, Fingerprint, Xpub};
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use crate::btc::{make_script_config_policy, KeyOriginInfo};
+use crate::pb;
+use crate::Keypath;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("invalid descriptor checksum")]
+    InvalidChecksum,
+    #[error("invalid character in descriptor: {0}")]
+    InvalidCharacter(char),
+    #[error("only wsh(...) and tr(...) descriptors are supported")]
+    UnsupportedDescriptor,
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("invalid key derivation: {0}")]
+    InvalidDerivation(String),
+    #[error("the same key is used with overlapping derivations")]
+    DuplicateDerivation,
+    #[error("the descriptor does not contain any keys")]
+    NoKeys,
+    #[error("invalid miniscript: {0}")]
+    InvalidMiniscript(String),
+}
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Computes the descriptor checksum as specified in BIP-380.
+pub(crate) fn descriptor_checksum(descriptor: &str) -> Result<String, Error> {
+    const GENERATOR: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+    fn polymod(checksum: u64, value: u64) -> u64 {
+        let top = checksum >> 35;
+        let mut checksum = ((checksum & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+        checksum
+    }
+
+    let mut checksum = 1;
+    let mut class_count = 0;
+    let mut classes = 0;
+    for c in descriptor.chars() {
+        let position = INPUT_CHARSET.find(c).ok_or(Error::InvalidCharacter(c))? as u64;
+        checksum = polymod(checksum, position & 31);
+        classes = classes * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            checksum = polymod(checksum, classes);
+            classes = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        checksum = polymod(checksum, classes);
+    }
+    for _ in 0..8 {
+        checksum = polymod(checksum, 0);
+    }
+    checksum ^= 1;
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Parses a key expression with origin info, e.g. `[d34db33f/48'/1'/0'/2']tpub.../<0;1>/*`, into
+/// the key info and the multipath derivation indices.
+fn parse_key_expression(expression: &str) -> Result<(KeyOriginInfo, (u32, u32)), Error> {
+    let (origin, key) = match expression.strip_prefix('[') {
+        Some(rest) => {
+            let (origin, key) = rest
+                .split_once(']')
+                .ok_or_else(|| Error::InvalidKey(expression.into()))?;
+            (Some(origin), key)
+        }
+        None => (None, expression),
+    };
+    let (root_fingerprint, keypath) = match origin {
+        Some(origin) => {
+            let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+            let fingerprint = Fingerprint::from_str(fingerprint)
+                .map_err(|_| Error::InvalidKey(expression.into()))?;
+            let path =
+                DerivationPath::from_str(path).map_err(|_| Error::InvalidKey(expression.into()))?;
+            (Some(fingerprint), Some(Keypath::from(&path)))
+        }
+        None => (None, None),
+    };
+    let (xpub, derivation) = key.split_once('/').unwrap_or((key, ""));
+    let xpub = Xpub::from_str(xpub).map_err(|_| Error::InvalidKey(expression.into()))?;
+
+    let invalid_derivation = || Error::InvalidDerivation(expression.into());
+    let indices = if derivation == "**" {
+        (0, 1)
+    } else {
+        let (receive, change) = derivation
+            .strip_prefix('<')
+            .and_then(|derivation| derivation.strip_suffix(">/*"))
+            .and_then(|derivation| derivation.split_once(';'))
+            .ok_or_else(invalid_derivation)?;
+        // Indices must be unhardened and without leading zeros.
+        let parse_index = |index: &str| {
+            index
+                .parse::<u32>()
+                .ok()
+                .filter(|&parsed| parsed < 0x80000000 && parsed.to_string() == index)
+                .ok_or_else(invalid_derivation)
+        };
+        (parse_index(receive)?, parse_index(change)?)
+    };
+    if indices.0 == indices.1 {
+        return Err(invalid_derivation());
+    }
+    Ok((
+        KeyOriginInfo {
+            root_fingerprint,
+            keypath,
+            xpub,
+        },
+        indices,
+    ))
+}
+
+/// Returns true if the argument at `index` of the miniscript fragment is a key.
+fn is_key_arg(fragment: &str, index: usize) -> bool {
+    match fragment {
+        "pk" | "pkh" | "pk_k" | "pk_h" | "tr" => index == 0,
+        // The first argument is the threshold.
+        "multi" | "sortedmulti" | "multi_a" | "sortedmulti_a" => index > 0,
+        _ => false,
+    }
+}
+
+fn key_to_string(key: &KeyOriginInfo) -> String {
+    match (&key.root_fingerprint, &key.keypath) {
+        (Some(fingerprint), keypath) => {
+            let path = keypath.as_ref().map_or(String::new(), |keypath| {
+                DerivationPath::from(
+                    keypath
+                        .to_vec()
+                        .into_iter()
+                        .map(bitcoin::bip32::ChildNumber::from)
+                        .collect::<Vec<_>>(),
+                )
+                .to_string()
+            });
+            if path.is_empty() {
+                format!("[{}]{}", fingerprint, key.xpub)
+            } else {
+                format!("[{}/{}]{}", fingerprint, path, key.xpub)
+            }
+        }
+        (None, _) => key.xpub.to_string(),
+    }
+}
+
+/// A BIP-388 wallet policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalletPolicy {
+    /// Descriptor template, e.g. `wsh(multi(2,@0/<0;1>/*,@1/<0;1>/*))`.
+    pub template: String,
+    /// The keys referenced by the placeholders of the template, in order.
+    pub keys: Vec<KeyOriginInfo>,
+}
+
+impl WalletPolicy {
+    /// Parses an output descriptor such as
+    /// `wsh(multi(2,[d34db33f/48'/1'/0'/2']tpub.../<0;1>/*,tpub.../<0;1>/*))#checksum`.
+    ///
+    /// Only `wsh(...)` and `tr(...)` descriptors are supported. Every key must be an xpub followed
+    /// by a multipath derivation `/<M;N>/*` (or its shorthand `/**`), and derivations of the same
+    /// key may not overlap, as required by BIP-388. The checksum is optional, but verified if
+    /// present. With the `miniscript` feature, the miniscript is validated as well, otherwise it is
+    /// only validated by the BitBox when the policy is registered.
+    pub fn from_descriptor(descriptor: &str) -> Result<Self, Error> {
+        let descriptor = match descriptor.split_once('#') {
+            Some((descriptor, checksum)) => {
+                if descriptor_checksum(descriptor)? != checksum {
+                    return Err(Error::InvalidChecksum);
+                }
+                descriptor
+            }
+            None => descriptor,
+        };
+        if !(descriptor.starts_with("wsh(") || descriptor.starts_with("tr(")) {
+            return Err(Error::UnsupportedDescriptor);
+        }
+
+        let mut template = String::new();
+        let mut keys: Vec<KeyOriginInfo> = Vec::new();
+        let mut used_derivations: BTreeSet<(usize, u32)> = BTreeSet::new();
+        // Fragments enclosing the current position, with the index of the current argument. A
+        // taptree `{...}` is a frame without a name.
+        let mut frames: Vec<(&str, usize)> = Vec::new();
+        let mut token_start = 0;
+        for (pos, c) in descriptor.char_indices().chain([(descriptor.len(), ')')]) {
+            if !matches!(c, '(' | ')' | ',' | '{' | '}') {
+                continue;
+            }
+            let token = &descriptor[token_start..pos];
+            token_start = pos + c.len_utf8();
+            let is_key_position = c != '('
+                && matches!(frames.last(), Some((fragment, arg)) if is_key_arg(fragment, *arg));
+            if is_key_position {
+                let (key, (receive, change)) = parse_key_expression(token)?;
+                let index = match keys.iter().position(|k| k == &key) {
+                    Some(index) => index,
+                    None => {
+                        keys.push(key);
+                        keys.len() - 1
+                    }
+                };
+                if !used_derivations.insert((index, receive))
+                    || !used_derivations.insert((index, change))
+                {
+                    return Err(Error::DuplicateDerivation);
+                }
+                template.push_str(&format!("@{}/<{};{}>/*", index, receive, change));
+            } else if token.starts_with('[')
+                || Xpub::from_str(token.split('/').next().unwrap()).is_ok()
+            {
+                return Err(Error::InvalidKey(token.into()));
+            } else {
+                template.push_str(token);
+            }
+            match c {
+                // Strip wrappers such as `s:` or `v:` from the fragment name.
+                '(' => frames.push((token.rsplit(':').next().unwrap(), 0)),
+                '{' => frames.push(("", 0)),
+                ',' => {
+                    if let Some((_, arg)) = frames.last_mut() {
+                        *arg += 1;
+                    }
+                }
+                _ => {
+                    frames.pop();
+                }
+            }
+            if pos < descriptor.len() {
+                template.push(c);
+            }
+        }
+        if keys.is_empty() {
+            return Err(Error::NoKeys);
+        }
+        let policy = WalletPolicy { template, keys };
+        #[cfg(feature = "miniscript")]
+        policy
+            .to_descriptor()
+            .parse::<miniscript::Descriptor<miniscript::DescriptorPublicKey>>()
+            .map_err(|err| Error::InvalidMiniscript(err.to_string()))?;
+        Ok(policy)
+    }
+
+    /// Returns the script config to register the policy on the BitBox and to use it for receive
+    /// addresses and signing.
+    pub fn script_config(&self) -> pb::BtcScriptConfig {
+        make_script_config_policy(&self.template, &self.keys)
+    }
+
+    /// Returns the output descriptor including the checksum, e.g. to export the wallet to other
+    /// software.
+    pub fn to_descriptor(&self) -> String {
+        let mut descriptor = String::new();
+        let mut chars = self.template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '@' {
+                descriptor.push(c);
+                continue;
+            }
+            let mut index = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                index.push(digit);
+            }
+            match index.parse::<usize>().ok().and_then(|i| self.keys.get(i)) {
+                Some(key) => descriptor.push_str(&key_to_string(key)),
+                None => {
+                    descriptor.push('@');
+                    descriptor.push_str(&index);
+                }
+            }
+        }
+        // All characters of a valid template and of the keys are in the checksum charset.
+        match descriptor_checksum(&descriptor) {
+            Ok(checksum) => format!("{}#{}", descriptor, checksum),
+            Err(_) => descriptor,
+        }
+    }
+}
+
+impl FromStr for WalletPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WalletPolicy::from_descriptor(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR_XPUB: &str = "tpubDFgycCkexSxkdZfeyaasDHityE97kiYM1BeCNoivDHvydGugKtoNobt4vEX6YSHNPy2cqmWQHKjKxciJuocepsGPGxcDZVmiMBnxgA1JKQk";
+    const SOME_XPUB: &str = "tpubDCNtvuCS9oj3psPNfXZXuGjcQ5rSBi3MzigjBqqwQohWWetoRdLzT5v2uJq6KBTwxj1FYvuPTr7RoWkN4cmubDy5wW8SU3q9xYnDRpQepiT";
+
+    #[test]
+    fn test_descriptor_checksum() {
+        // Checksums computed by rust-miniscript.
+        for descriptor in [
+            format!(
+                "wsh(multi(2,[4c00739d/48'/1'/0'/2']{}/<0;1>/*,{}/<0;1>/*))",
+                OUR_XPUB, SOME_XPUB
+            ),
+            format!("tr({}/<0;1>/*,pk({}/<0;1>/*))", OUR_XPUB, SOME_XPUB),
+        ] {
+            let parsed: miniscript::Descriptor<miniscript::DescriptorPublicKey> =
+                descriptor.parse().unwrap();
+            let expected = parsed.to_string();
+            assert_eq!(
+                format!(
+                    "{}#{}",
+                    descriptor,
+                    descriptor_checksum(&descriptor).unwrap()
+                ),
+                expected
+            );
+        }
+        assert_eq!(
+            descriptor_checksum("wsh(pk(\u{e9}))"),
+            Err(Error::InvalidCharacter('\u{e9}'))
+        );
+    }
+
+    #[test]
+    fn test_from_descriptor() {
+        let descriptor = format!(
+            "wsh(or_b(pk([4c00739d/48'/1'/0'/3']{}/<0;1>/*),s:pk({}/**)))",
+            OUR_XPUB, SOME_XPUB
+        );
+        let policy = WalletPolicy::from_descriptor(&descriptor).unwrap();
+        assert_eq!(
+            policy.template,
+            "wsh(or_b(pk(@0/<0;1>/*),s:pk(@1/<0;1>/*)))"
+        );
+        assert_eq!(
+            policy.keys,
+            vec![
+                KeyOriginInfo {
+                    root_fingerprint: Some(Fingerprint::from_str("4c00739d").unwrap()),
+                    keypath: Some("m/48'/1'/0'/3'".try_into().unwrap()),
+                    xpub: OUR_XPUB.parse().unwrap(),
+                },
+                KeyOriginInfo {
+                    root_fingerprint: None,
+                    keypath: None,
+                    xpub: SOME_XPUB.parse().unwrap(),
+                },
+            ]
+        );
+        assert_eq!(
+            policy.script_config(),
+            make_script_config_policy(&policy.template, &policy.keys)
+        );
+
+        // Round trip, `/**` is normalized and the checksum is added.
+        let exported = policy.to_descriptor();
+        let normalized = descriptor.replace("/**", "/<0;1>/*");
+        assert_eq!(
+            exported,
+            format!(
+                "{}#{}",
+                normalized,
+                descriptor_checksum(&normalized).unwrap()
+            )
+        );
+        assert_eq!(WalletPolicy::from_descriptor(&exported).unwrap(), policy);
+    }
+
+    #[test]
+    fn test_from_descriptor_taproot() {
+        // Same key used twice with different derivations, and in a taptree.
+        let descriptor = format!(
+            "tr([4c00739d/86'/1'/0']{0}/<0;1>/*,{{pk({1}/<0;1>/*),pk({0}/<2;3>/*)}})",
+            OUR_XPUB, SOME_XPUB
+        );
+        let policy: WalletPolicy = descriptor.parse().unwrap();
+        assert_eq!(
+            policy.template,
+            "tr(@0/<0;1>/*,{pk(@1/<0;1>/*),pk(@2/<2;3>/*)})"
+        );
+        // The last key has no origin info, so it is a different key from the first one.
+        assert_eq!(policy.keys.len(), 3);
+
+        let descriptor = format!(
+            "tr([4c00739d/86'/1'/0']{0}/<0;1>/*,{{pk({1}/<0;1>/*),pk([4c00739d/86'/1'/0']{0}/<2;3>/*)}})",
+            OUR_XPUB, SOME_XPUB
+        );
+        let policy: WalletPolicy = descriptor.parse().unwrap();
+        assert_eq!(
+            policy.template,
+            "tr(@0/<0;1>/*,{pk(@1/<0;1>/*),pk(@0/<2;3>/*)})"
+        );
+        assert_eq!(policy.keys.len(), 2);
+        assert_eq!(
+            policy.to_descriptor(),
+            format!(
+                "{}#{}",
+                descriptor,
+                descriptor_checksum(&descriptor).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_from_descriptor_invalid() {
+        let checksum = descriptor_checksum(&format!("wsh(pk({}/<0;1>/*))", OUR_XPUB)).unwrap();
+        assert_eq!(
+            WalletPolicy::from_descriptor(&format!("wsh(pk({}/<0;1>/*))#{}", SOME_XPUB, checksum)),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            WalletPolicy::from_descriptor(&format!("sh(pk({}/<0;1>/*))", OUR_XPUB)),
+            Err(Error::UnsupportedDescriptor)
+        );
+        assert_eq!(
+            WalletPolicy::from_descriptor("wsh(older(100))"),
+            Err(Error::NoKeys)
+        );
+        // Keys without multipath derivation, or with invalid derivations.
+        for derivation in [
+            "",
+            "/0/*",
+            "/<0;0>/*",
+            "/<0;1>",
+            "/<0;01>/*",
+            "/<0';1>/*",
+            "/<0;1>/*'",
+        ] {
+            assert!(matches!(
+                WalletPolicy::from_descriptor(&format!("wsh(pk({}{}))", OUR_XPUB, derivation)),
+                Err(Error::InvalidDerivation(_))
+            ));
+        }
+        assert!(matches!(
+            WalletPolicy::from_descriptor(&format!("wsh(pk([4c00739x/48']{}/<0;1>/*))", OUR_XPUB)),
+            Err(Error::InvalidKey(_))
+        ));
+        assert_eq!(
+            WalletPolicy::from_descriptor(&format!(
+                "wsh(or_b(pk({0}/<0;1>/*),s:pk({0}/<1;2>/*)))",
+                OUR_XPUB
+            )),
+            Err(Error::DuplicateDerivation)
+        );
+        // Invalid miniscript: the second argument of or_b must be a wrapped expression.
+        let descriptor = format!(
+            "wsh(or_b(pk({}/<0;1>/*),pk({}/<0;1>/*)))",
+            OUR_XPUB, SOME_XPUB
+        );
+        #[cfg(feature = "miniscript")]
+        assert!(matches!(
+            WalletPolicy::from_descriptor(&descriptor),
+            Err(Error::InvalidMiniscript(_))
+        ));
+        #[cfg(not(feature = "miniscript"))]
+        assert!(WalletPolicy::from_descriptor(&descriptor).is_ok());
+
+        // Keys which are not xpubs, and keys in positions where no key is expected.
+        for descriptor in [
+            "wsh(pk(02e0e0a6b2cb2dcb4cbf35e5dfe30d4b8bd3f9ed1d36ac5c2b1c5a3e2e6f1f5a29))".into(),
+            format!(
+                "wsh(multi(1,{}/<0;1>/*,02e0e0a6b2cb2dcb4cbf35e5dfe30d4b8bd3f9ed1d36ac5c2b1c5a3e2e6f1f5a29))",
+                OUR_XPUB
+            ),
+            "wsh(pk())".into(),
+            format!("wsh(older({}/<0;1>/*))", OUR_XPUB),
+        ] {
+            assert!(matches!(
+                WalletPolicy::from_descriptor(&descriptor),
+                Err(Error::InvalidKey(_))
+            ));
+        }
+    }
+}
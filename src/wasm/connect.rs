@@ -42,7 +42,7 @@ impl communication::ReadWrite for JsReadWrite {
                 .map_err(|_| communication::Error::Read)?,
         ))
         .await
-        .unwrap();
+        .map_err(|_| communication::Error::Read)?;
         Ok(js_sys::Uint8Array::from(result).to_vec())
     }
 }
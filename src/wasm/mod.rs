@@ -146,6 +146,29 @@ pub struct PairedBitBox {
 
 #[wasm_bindgen]
 impl BitBox {
+    /// Returns the device info (version, product, unlocked and initialized state). This is
+    /// available before unlocking and pairing.
+    #[wasm_bindgen(js_name = info)]
+    pub fn info(&self) -> types::TsInfo {
+        let info = self.device.info();
+        serde_wasm_bindgen::to_value(&types::Info {
+            version: info.version.to_string(),
+            product: types::product_str(info.product),
+            unlocked: info.unlocked,
+            initialized: info.initialized,
+        })
+        .unwrap()
+        .into()
+    }
+
+    /// Performs the device attestation, checking that the device is a genuine BitBox. Returns
+    /// false if the verification failed, in which case the device might not be a genuine BitBox
+    /// and the user should be warned. This should be called before `unlockAndPair()`.
+    #[wasm_bindgen(js_name = performAttestation)]
+    pub async fn perform_attestation(&self) -> Result<bool, JavascriptError> {
+        Ok(self.device.perform_attestation().await?.is_verified())
+    }
+
     /// Invokes the device unlock and pairing. After this, stop using this instance and continue
     /// with the returned instance of type `PairingBitBox`.
     #[wasm_bindgen(js_name = unlockAndPair)]
@@ -209,15 +232,7 @@ impl PairedBitBox {
     /// Returns which product we are connected to.
     #[wasm_bindgen(js_name = product)]
     pub fn product(&self) -> types::TsProduct {
-        match self.device.product() {
-            crate::Product::Unknown => JsValue::from_str("unknown").into(),
-            crate::Product::BitBox02Multi => JsValue::from_str("bitbox02-multi").into(),
-            crate::Product::BitBox02BtcOnly => JsValue::from_str("bitbox02-btconly").into(),
-            crate::Product::BitBox02NovaMulti => JsValue::from_str("bitbox02-nova-multi").into(),
-            crate::Product::BitBox02NovaBtcOnly => {
-                JsValue::from_str("bitbox02-nova-btconly").into()
-            }
-        }
+        self.device.product().into()
     }
 
     /// Returns the firmware version, e.g. "9.18.0".
@@ -371,13 +386,35 @@ impl PairedBitBox {
             .await?)
     }
 
+    /// Like `btcAddress()`, but also derives the address on the host and fails if it does not
+    /// match the address returned by the BitBox.
+    #[wasm_bindgen(js_name = btcAddressVerified)]
+    pub async fn btc_address_verified(
+        &self,
+        coin: types::TsBtcCoin,
+        keypath: types::TsKeypath,
+        script_config: types::TsBtcScriptConfig,
+        display: bool,
+    ) -> Result<String, JavascriptError> {
+        Ok(self
+            .device
+            .btc_address_verified(
+                coin.try_into()?,
+                &keypath.try_into()?,
+                &script_config.try_into()?,
+                display,
+            )
+            .await?)
+    }
+
     /// Sign a PSBT.
     ///
     /// If `force_script_config` is `undefined`, we attempt to infer the involved script
     /// configs. For the simple script config (single sig), we infer the script config from the
     /// involved redeem scripts and provided derviation paths.
     ///
-    /// Multisig and policy configs are currently not inferred and must be provided using
+    /// For segwit multisig, the script config is reconstructed from the witness script and the
+    /// PSBT global xpubs. Policy configs are currently not inferred and must be provided using
     /// `force_script_config`.
     #[wasm_bindgen(js_name = btcSignPSBT)]
     pub async fn btc_sign_psbt(
@@ -402,6 +439,31 @@ impl PairedBitBox {
         Ok(psbt.to_string())
     }
 
+    /// Sign a version 2 PSBT (BIP-370), base64 encoded. See `btcSignPSBT()`.
+    #[wasm_bindgen(js_name = btcSignPSBTV2)]
+    pub async fn btc_sign_psbt_v2(
+        &self,
+        coin: types::TsBtcCoin,
+        psbt: &str,
+        force_script_config: Option<types::TsBtcScriptConfigWithKeypath>,
+        format_unit: types::TsBtcFormatUnit,
+    ) -> Result<String, JavascriptError> {
+        let mut psbt =
+            crate::psbt_v2::PsbtV2::from_str(psbt.trim()).map_err(crate::error::Error::from)?;
+        self.device
+            .btc_sign_psbt_v2(
+                coin.try_into()?,
+                &mut psbt,
+                match force_script_config {
+                    Some(sc) => Some(sc.try_into()?),
+                    None => None,
+                },
+                format_unit.try_into()?,
+            )
+            .await?;
+        Ok(psbt.to_string())
+    }
+
     #[wasm_bindgen(js_name = btcSignMessage)]
     pub async fn btc_sign_message(
         &self,
@@ -612,6 +674,30 @@ impl PairedBitBox {
     pub async fn bip85_app_bip39(&self) -> Result<(), JavascriptError> {
         Ok(self.device.bip85_app_bip39().await?)
     }
+
+    /// Invokes the BIP85-LN workflow on the device, returning 32 bytes of entropy derived at the
+    /// given account number, to be used as a Lightning wallet seed.
+    #[wasm_bindgen(js_name = bip85AppLn)]
+    pub async fn bip85_app_ln(
+        &self,
+        account_number: u32,
+    ) -> Result<js_sys::Uint8Array, JavascriptError> {
+        let entropy = self.device.bip85_app_ln(account_number).await?;
+        Ok(js_sys::Uint8Array::from(&entropy[..]))
+    }
+
+    /// Retrieves the key used by Electrum to encrypt the wallet file, derived at the given
+    /// keypath.
+    #[wasm_bindgen(js_name = electrumEncryptionKey)]
+    pub async fn electrum_encryption_key(
+        &self,
+        keypath: types::TsKeypath,
+    ) -> Result<String, JavascriptError> {
+        Ok(self
+            .device
+            .electrum_encryption_key(&keypath.try_into()?)
+            .await?)
+    }
 }
 
 #[cfg(test)]
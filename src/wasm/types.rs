@@ -14,6 +14,13 @@ extern "C" {
 const TS_TYPES: &'static str = r#"
 type OnCloseCb = undefined | (() => void);
 type Product = 'unknown' | 'bitbox02-multi' | 'bitbox02-btconly' | 'bitbox02-nova-multi' | 'bitbox02-nova-btconly';
+type Info = {
+  version: string;
+  product: Product;
+  unlocked: boolean;
+  // undefined before firmware v9.20.0
+  initialized?: boolean;
+};
 type BtcCoin = 'btc' | 'tbtc' | 'ltc' | 'tltc' | 'rbtc';
 type BtcFormatUnit = 'default' | 'sat';
 type XPubType = 'tpub' | 'xpub' | 'ypub' | 'zpub' | 'vpub' | 'upub' | 'Vpub' | 'Zpub' | 'Upub' | 'Ypub';
@@ -170,6 +177,8 @@ extern "C" {
     pub type TsOnCloseCb;
     #[wasm_bindgen(typescript_type = "Product")]
     pub type TsProduct;
+    #[wasm_bindgen(typescript_type = "Info")]
+    pub type TsInfo;
     #[wasm_bindgen(typescript_type = "BtcCoin")]
     pub type TsBtcCoin;
     #[wasm_bindgen(typescript_type = "BtcFormatUnit")]
@@ -222,6 +231,22 @@ extern "C" {
     pub type TsError;
 }
 
+pub fn product_str(product: crate::Product) -> &'static str {
+    match product {
+        crate::Product::Unknown => "unknown",
+        crate::Product::BitBox02Multi => "bitbox02-multi",
+        crate::Product::BitBox02BtcOnly => "bitbox02-btconly",
+        crate::Product::BitBox02NovaMulti => "bitbox02-nova-multi",
+        crate::Product::BitBox02NovaBtcOnly => "bitbox02-nova-btconly",
+    }
+}
+
+impl From<crate::Product> for TsProduct {
+    fn from(product: crate::Product) -> Self {
+        JsValue::from_str(product_str(product)).into()
+    }
+}
+
 impl TryFrom<TsBtcCoin> for crate::pb::BtcCoin {
     type Error = JavascriptError;
     fn try_from(value: TsBtcCoin) -> Result<Self, Self::Error> {
@@ -356,3 +381,12 @@ pub struct EthSignature {
     pub s: Vec<u8>,
     pub v: Vec<u8>,
 }
+
+#[derive(serde::Serialize)]
+pub struct Info {
+    pub version: String,
+    pub product: &'static str,
+    pub unlocked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initialized: Option<bool>,
+}
@@ -85,6 +85,88 @@ async fn test_btc_address() {
     .await
 }
 
+#[tokio::test]
+async fn test_btc_address_verified() {
+    test_initialized_simulators(async |paired_bitbox| {
+        for (coin, keypath, simple_type) in [
+            (
+                pb::BtcCoin::Tbtc,
+                "m/84'/1'/0'/1/10",
+                pb::btc_script_config::SimpleType::P2wpkh,
+            ),
+            (
+                pb::BtcCoin::Tbtc,
+                "m/49'/1'/0'/0/1",
+                pb::btc_script_config::SimpleType::P2wpkhP2sh,
+            ),
+            (
+                pb::BtcCoin::Btc,
+                "m/86'/0'/0'/0/0",
+                pb::btc_script_config::SimpleType::P2tr,
+            ),
+            (
+                pb::BtcCoin::Ltc,
+                "m/84'/2'/0'/0/0",
+                pb::btc_script_config::SimpleType::P2wpkh,
+            ),
+        ] {
+            let keypath = keypath.try_into().unwrap();
+            let script_config = bitbox_api::btc::make_script_config_simple(simple_type);
+            let address = paired_bitbox
+                .btc_address_verified(coin, &keypath, &script_config, false)
+                .await
+                .unwrap();
+            assert_eq!(
+                address,
+                paired_bitbox
+                    .btc_address(coin, &keypath, &script_config, false)
+                    .await
+                    .unwrap()
+            );
+        }
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_btc_discover_accounts() {
+    test_initialized_simulators(async |paired_bitbox| {
+        let coin = pb::BtcCoin::Tbtc;
+        let script_config =
+            bitbox_api::btc::make_script_config_simple(pb::btc_script_config::SimpleType::P2wpkh);
+        let mut source = bitbox_api::discovery::InMemoryUtxoSource::new();
+        for keypath in ["m/84'/1'/0'/0/2", "m/84'/1'/1'/1/0"] {
+            let address = paired_bitbox
+                .btc_address(coin, &keypath.try_into().unwrap(), &script_config, false)
+                .await
+                .unwrap();
+            source.insert(&address);
+        }
+
+        let accounts = paired_bitbox
+            .btc_discover_accounts(
+                coin,
+                &[
+                    pb::btc_script_config::SimpleType::P2wpkh,
+                    pb::btc_script_config::SimpleType::P2tr,
+                ],
+                &source,
+                bitbox_api::discovery::DEFAULT_GAP_LIMIT,
+            )
+            .await
+            .unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].keypath, "m/84'/1'/0'".try_into().unwrap());
+        assert_eq!(accounts[0].script_config, script_config);
+        assert_eq!(accounts[0].next_receive_index, 3);
+        assert_eq!(accounts[0].next_change_index, 0);
+        assert_eq!(accounts[1].keypath, "m/84'/1'/1'".try_into().unwrap());
+        assert_eq!(accounts[1].next_receive_index, 0);
+        assert_eq!(accounts[1].next_change_index, 1);
+    })
+    .await
+}
+
 #[tokio::test]
 async fn test_btc_sign_message() {
     test_initialized_simulators(async |paired_bitbox| {
@@ -136,6 +218,181 @@ async fn test_btc_sign_message() {
                 &bitcoin::secp256k1::ecdsa::Signature::from_compact(&sign_result.sig).unwrap(),
             )
             .unwrap();
+
+        let script_config = bitbox_api::btc::make_script_config_simple(
+            pb::btc_script_config::SimpleType::P2wpkhP2sh,
+        );
+        let address = paired_bitbox
+            .btc_address(
+                pb::BtcCoin::Btc,
+                &"m/49'/0'/0'/0/10".try_into().unwrap(),
+                &script_config,
+                false,
+            )
+            .await
+            .unwrap();
+        bitbox_api::btc::verify_message_signature(
+            pb::BtcCoin::Btc,
+            &script_config,
+            b"message",
+            &sign_result,
+            &address,
+        )
+        .unwrap();
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_btc_sign_message_verified() {
+    test_initialized_simulators(async |paired_bitbox| {
+        for (simple_type, keypath) in [
+            (
+                pb::btc_script_config::SimpleType::P2wpkhP2sh,
+                "m/49'/0'/0'/0/10",
+            ),
+            (
+                pb::btc_script_config::SimpleType::P2wpkh,
+                "m/84'/0'/0'/0/10",
+            ),
+        ] {
+            let sign_result = paired_bitbox
+                .btc_sign_message_verified(
+                    pb::BtcCoin::Btc,
+                    pb::BtcScriptConfigWithKeypath {
+                        script_config: Some(bitbox_api::btc::make_script_config_simple(
+                            simple_type,
+                        )),
+                        keypath: bitbox_api::Keypath::try_from(keypath).unwrap().to_vec(),
+                    },
+                    b"message",
+                )
+                .await
+                .unwrap();
+            assert_eq!(sign_result.electrum_sig65.len(), 65);
+        }
+    })
+    .await
+}
+
+// Test that `btc_sign()` answers the request of the BitBox for the payment request of an output.
+// There is no trusted payment request provider key available here, so the payment request is
+// signed with an arbitrary key and the BitBox rejects it when verifying it. Signing the same
+// transaction without the payment request succeeds, so the rejection comes from the payment
+// request step.
+#[tokio::test]
+async fn test_btc_sign_payment_request() {
+    test_initialized_simulators(async |paired_bitbox| {
+        if !semver::VersionReq::parse(">=9.19.0")
+            .unwrap()
+            .matches(paired_bitbox.version())
+        {
+            return;
+        }
+        use bitbox_api::btc::{
+            Payload, PaymentRequest, PaymentRequestMemo, Transaction, TxExternalOutput, TxInput,
+            TxInternalOutput, TxOutput,
+        };
+
+        let make_transaction = |payment_request_index: Option<u32>| Transaction {
+            script_configs: vec![pb::BtcScriptConfigWithKeypath {
+                script_config: Some(bitbox_api::btc::make_script_config_simple(
+                    pb::btc_script_config::SimpleType::P2tr,
+                )),
+                keypath: bitbox_api::Keypath::try_from("m/86'/1'/0'")
+                    .unwrap()
+                    .to_vec(),
+            }],
+            output_script_configs: vec![],
+            version: 2,
+            inputs: vec![TxInput {
+                prev_out_hash: vec![0x31; 32],
+                prev_out_index: 0,
+                prev_out_value: 100_000_000,
+                sequence: 0xFFFFFFFF,
+                keypath: "m/86'/1'/0'/0/0".try_into().unwrap(),
+                script_config_index: 0,
+                // Not needed, as all inputs are Taproot.
+                prev_tx: None,
+                silent_payment_pubkey: None,
+            }],
+            outputs: vec![
+                TxOutput::External(TxExternalOutput {
+                    payload: Payload {
+                        data: vec![0x42; 20],
+                        output_type: pb::BtcOutputType::P2wpkh,
+                    },
+                    value: 50_000_000,
+                    payment_request_index,
+                }),
+                TxOutput::Internal(TxInternalOutput {
+                    keypath: "m/86'/1'/0'/1/0".try_into().unwrap(),
+                    value: 49_990_000,
+                    script_config_index: 0,
+                    output_script_config_index: None,
+                }),
+            ],
+            locktime: 0,
+            payment_requests: payment_request_index
+                .map(|_| PaymentRequest {
+                    recipient_name: "Test Merchant".into(),
+                    memos: vec![PaymentRequestMemo::Text("Order #123".into())],
+                    nonce: vec![],
+                    total_amount: 50_000_000,
+                    signature: vec![0x01; 64],
+                })
+                .into_iter()
+                .collect(),
+        };
+
+        let sign_result = paired_bitbox
+            .btc_sign(
+                pb::BtcCoin::Tbtc,
+                &make_transaction(None),
+                pb::btc_sign_init_request::FormatUnit::Default,
+            )
+            .await
+            .unwrap();
+        assert_eq!(sign_result.signatures.len(), 1);
+
+        assert!(matches!(
+            paired_bitbox
+                .btc_sign(
+                    pb::BtcCoin::Tbtc,
+                    &make_transaction(Some(0)),
+                    pb::btc_sign_init_request::FormatUnit::Default,
+                )
+                .await,
+            Err(bitbox_api::error::Error::BitBox(
+                bitbox_api::error::BitBoxError::InvalidInput
+            ))
+        ));
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_btc_sign_message_bip322() {
+    test_initialized_simulators(async |paired_bitbox| {
+        // No firmware release signs the BIP-322 `to_sign` transaction yet.
+        assert!(matches!(
+            paired_bitbox
+                .btc_sign_message_bip322(
+                    pb::BtcCoin::Btc,
+                    pb::BtcScriptConfigWithKeypath {
+                        script_config: Some(bitbox_api::btc::make_script_config_simple(
+                            pb::btc_script_config::SimpleType::P2wpkh,
+                        )),
+                        keypath: bitbox_api::Keypath::try_from("m/84'/0'/0'/0/0")
+                            .unwrap()
+                            .to_vec(),
+                    },
+                    b"Hello World",
+                    bitbox_api::bip322::SignatureFormat::Simple,
+                )
+                .await,
+            Err(bitbox_api::error::Error::Version(_))
+        ));
     })
     .await
 }
@@ -589,6 +589,191 @@ async fn test_btc_psbt_multisig_p2wsh() {
     }).await
 }
 
+// Test signing a 2-of-3 multisig PSBT without providing the script config, which is inferred from
+// the witness script and the PSBT global xpubs.
+#[tokio::test]
+async fn test_btc_psbt_multisig_inferred() {
+    test_initialized_simulators(async |bitbox| {
+        let secp = secp256k1::Secp256k1::new();
+
+        let coin = pb::BtcCoin::Tbtc;
+
+        let our_root_fingerprint = util::simulator_xprv().fingerprint(&secp);
+
+        for (descriptor_template, keypath_account, script_type) in [
+            (
+                "wsh(sortedmulti(2,KEYS))",
+                "m/48'/1'/0'/2'",
+                pb::btc_script_config::multisig::ScriptType::P2wsh,
+            ),
+            (
+                "sh(wsh(sortedmulti(2,KEYS)))",
+                "m/48'/1'/0'/1'",
+                pb::btc_script_config::multisig::ScriptType::P2wshP2sh,
+            ),
+        ] {
+            let keypath_account: DerivationPath = keypath_account.parse().unwrap();
+
+            let our_xpub: Xpub = util::simulator_xpub_at(&secp, &keypath_account);
+            let cosigner_xprvs: Vec<bitcoin::bip32::Xpriv> = [2u8, 3]
+                .iter()
+                .map(|&seed| {
+                    bitcoin::bip32::Xpriv::new_master(bitcoin::NetworkKind::Test, &[seed; 32])
+                        .unwrap()
+                })
+                .collect();
+            let mut keys = vec![(our_root_fingerprint, our_xpub)];
+            for xprv in cosigner_xprvs.iter() {
+                keys.push((
+                    xprv.fingerprint(&secp),
+                    Xpub::from_priv(&secp, &xprv.derive_priv(&secp, &keypath_account).unwrap()),
+                ));
+            }
+
+            let keys_str: Vec<String> = keys
+                .iter()
+                .map(|(fingerprint, xpub)| {
+                    format!(
+                        "[{}/{}]{}/<0;1>/*",
+                        fingerprint,
+                        keypath_account.to_string().trim_start_matches("m/"),
+                        xpub
+                    )
+                })
+                .collect();
+            let multi_descriptor: miniscript::Descriptor<miniscript::DescriptorPublicKey> =
+                descriptor_template
+                    .replace("KEYS", &keys_str.join(","))
+                    .parse()
+                    .unwrap();
+            assert!(multi_descriptor.sanity_check().is_ok());
+
+            let [descriptor_receive, descriptor_change] = multi_descriptor
+                .into_single_descriptors()
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let input_descriptor = descriptor_receive.at_derivation_index(0).unwrap();
+            let change_descriptor = descriptor_change.at_derivation_index(0).unwrap();
+
+            // The script config is inferred with the xpubs sorted.
+            let mut xpubs: Vec<Xpub> = keys.iter().map(|(_, xpub)| *xpub).collect();
+            xpubs.sort_by_key(|xpub| xpub.encode());
+            let multisig_config = bitbox_api::btc::make_script_config_multisig(
+                2,
+                &xpubs,
+                xpubs.iter().position(|xpub| xpub == &our_xpub).unwrap() as _,
+                script_type,
+            );
+
+            let is_registered = bitbox
+                .btc_is_script_config_registered(
+                    coin,
+                    &multisig_config,
+                    Some(&(&keypath_account).into()),
+                )
+                .await
+                .unwrap();
+            if !is_registered {
+                bitbox
+                    .btc_register_script_config(
+                        coin,
+                        &multisig_config,
+                        Some(&(&keypath_account).into()),
+                        pb::btc_register_script_config_request::XPubType::AutoXpubTpub,
+                        Some("test multisig 2of3"),
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            let prev_tx = Transaction {
+                version: transaction::Version::TWO,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output:
+                        "3131313131313131313131313131313131313131313131313131313131313131:0"
+                            .parse()
+                            .unwrap(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence(0xFFFFFFFF),
+                    witness: Witness::default(),
+                }],
+                output: vec![TxOut {
+                    value: Amount::from_sat(100_000_000),
+                    script_pubkey: input_descriptor.script_pubkey(),
+                }],
+            };
+
+            let tx = Transaction {
+                version: transaction::Version::TWO,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output: OutPoint {
+                        txid: prev_tx.compute_txid(),
+                        vout: 0,
+                    },
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence(0xFFFFFFFF),
+                    witness: Witness::default(),
+                }],
+                output: vec![
+                    TxOut {
+                        value: Amount::from_sat(70_000_000),
+                        script_pubkey: change_descriptor.script_pubkey(),
+                    },
+                    TxOut {
+                        value: Amount::from_sat(20_000_000),
+                        script_pubkey: ScriptBuf::new_p2tr(
+                            &secp,
+                            // random private key:
+                            // 9dbb534622a6100a39b73dece43c6d4db14b9a612eb46a6c64c2bb849e283ce8
+                            "e4adbb12c3426ec71ebb10688d8ae69d531ca822a2b790acee216a7f1b95b576"
+                                .parse()
+                                .unwrap(),
+                            None,
+                        ),
+                    },
+                ],
+            };
+
+            let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+            psbt.inputs[0].non_witness_utxo = Some(prev_tx.clone());
+            psbt.update_input_with_descriptor(0, &input_descriptor)
+                .unwrap();
+            psbt.update_output_with_descriptor(0, &change_descriptor)
+                .unwrap();
+            for (fingerprint, xpub) in keys.iter() {
+                psbt.xpub
+                    .insert(*xpub, (*fingerprint, keypath_account.clone()));
+            }
+
+            bitbox
+                .btc_sign_psbt(
+                    coin,
+                    &mut psbt,
+                    None,
+                    pb::btc_sign_init_request::FormatUnit::Default,
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+
+            // Add the second signature of a cosigner.
+            psbt.sign(&cosigner_xprvs[0], &secp).unwrap();
+            assert_eq!(psbt.inputs[0].partial_sigs.len(), 2);
+
+            // Finalize, add witnesses.
+            psbt.finalize_mut(&secp).unwrap();
+
+            // Verify the signed tx, including that all sigs/witnesses are correct.
+            verify_transaction(psbt);
+        }
+    })
+    .await
+}
+
 #[tokio::test]
 async fn test_btc_psbt_policy_wsh() {
     test_initialized_simulators(async |bitbox| {
@@ -1066,3 +1251,310 @@ async fn test_btc_psbt_policy_tr_scriptspend() {
         verify_transaction(psbt);
     }).await
 }
+
+// Test signing a version 2 PSBT (BIP-370).
+#[tokio::test]
+async fn test_btc_psbt_v2() {
+    test_initialized_simulators(async |bitbox| {
+        let secp = secp256k1::Secp256k1::new();
+
+        let fingerprint = util::simulator_xprv().fingerprint(&secp);
+
+        let change_path: DerivationPath = "m/86'/1'/0'/1/0".parse().unwrap();
+        let change_xpub = util::simulator_xpub_at(&secp, &change_path);
+
+        let input_path: DerivationPath = "m/86'/1'/0'/0/0".parse().unwrap();
+        let input_xpub = util::simulator_xpub_at(&secp, &input_path);
+
+        let prev_txid: bitcoin::Txid =
+            "3131313131313131313131313131313131313131313131313131313131313131"
+                .parse()
+                .unwrap();
+        let prev_output = TxOut {
+            value: Amount::from_sat(100_000_000),
+            script_pubkey: ScriptBuf::new_p2tr(&secp, input_xpub.to_x_only_pub(), None),
+        };
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: prev_txid,
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0xFFFFFFFD),
+                witness: Witness::default(),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(70_000_000),
+                    script_pubkey: ScriptBuf::new_p2tr(&secp, change_xpub.to_x_only_pub(), None),
+                },
+                TxOut {
+                    value: Amount::from_sat(29_990_000),
+                    script_pubkey: ScriptBuf::new_p2tr(
+                        &secp,
+                        // random private key:
+                        // 9dbb534622a6100a39b73dece43c6d4db14b9a612eb46a6c64c2bb849e283ce8
+                        "e4adbb12c3426ec71ebb10688d8ae69d531ca822a2b790acee216a7f1b95b576"
+                            .parse()
+                            .unwrap(),
+                        None,
+                    ),
+                },
+            ],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(prev_output);
+        psbt.inputs[0].tap_internal_key = Some(input_xpub.to_x_only_pub());
+        psbt.inputs[0].tap_key_origins.insert(
+            input_xpub.to_x_only_pub(),
+            (vec![], (fingerprint, input_path.clone())),
+        );
+        psbt.outputs[0].tap_internal_key = Some(change_xpub.to_x_only_pub());
+        psbt.outputs[0].tap_key_origins.insert(
+            change_xpub.to_x_only_pub(),
+            (vec![], (fingerprint, change_path.clone())),
+        );
+
+        let mut psbt_v2: bitbox_api::psbt_v2::PsbtV2 =
+            bitbox_api::psbt_v2::PsbtV2::from_psbt_v0(psbt)
+                .to_string()
+                .parse()
+                .unwrap();
+        psbt_v2.inputs[0].required_height_locktime =
+            Some(bitcoin::absolute::Height::from_consensus(1000).unwrap());
+
+        // Sign.
+        bitbox
+            .btc_sign_psbt_v2(
+                pb::BtcCoin::Tbtc,
+                &mut psbt_v2,
+                None,
+                pb::btc_sign_init_request::FormatUnit::Default,
+            )
+            .await
+            .unwrap();
+        assert!(psbt_v2.inputs[0].input.tap_key_sig.is_some());
+
+        let mut psbt = psbt_v2.to_psbt_v0().unwrap();
+        assert_eq!(
+            psbt.unsigned_tx.lock_time,
+            bitcoin::absolute::LockTime::from_consensus(1000)
+        );
+        bitbox_api::btc::finalize_psbt(&mut psbt).unwrap();
+
+        // Verify the signed tx, including that all sigs/witnesses are correct.
+        verify_transaction(psbt);
+    })
+    .await
+}
+
+// Test signing a transaction which sends to a different account of the same keystore.
+#[tokio::test]
+async fn test_btc_psbt_send_to_other_account() {
+    test_initialized_simulators(async |bitbox| {
+        if !semver::VersionReq::parse(">=9.22.0")
+            .unwrap()
+            .matches(bitbox.version())
+        {
+            return;
+        }
+        let secp = secp256k1::Secp256k1::new();
+
+        let fingerprint = util::simulator_xprv().fingerprint(&secp);
+
+        let input_path: DerivationPath = "m/86'/1'/0'/0/0".parse().unwrap();
+        let input_xpub = util::simulator_xpub_at(&secp, &input_path);
+
+        // Receive address of account #1 (p2wpkh), while spending from account #0 (p2tr).
+        let other_account_path: DerivationPath = "m/84'/1'/1'/0/0".parse().unwrap();
+        let other_account_xpub = util::simulator_xpub_at(&secp, &other_account_path);
+
+        let prev_txid: bitcoin::Txid =
+            "3131313131313131313131313131313131313131313131313131313131313131"
+                .parse()
+                .unwrap();
+        let prev_output = TxOut {
+            value: Amount::from_sat(100_000_000),
+            script_pubkey: ScriptBuf::new_p2tr(&secp, input_xpub.to_x_only_pub(), None),
+        };
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: prev_txid,
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0xFFFFFFFF),
+                witness: Witness::default(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_990_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(
+                    &bitcoin::CompressedPublicKey(other_account_xpub.public_key).wpubkey_hash(),
+                ),
+            }],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(prev_output);
+        psbt.inputs[0].tap_internal_key = Some(input_xpub.to_x_only_pub());
+        psbt.inputs[0].tap_key_origins.insert(
+            input_xpub.to_x_only_pub(),
+            (vec![], (fingerprint, input_path.clone())),
+        );
+        psbt.outputs[0].bip32_derivation.insert(
+            other_account_xpub.public_key,
+            (fingerprint, other_account_path.clone()),
+        );
+
+        // Sign.
+        bitbox
+            .btc_sign_psbt(
+                pb::BtcCoin::Tbtc,
+                &mut psbt,
+                None,
+                pb::btc_sign_init_request::FormatUnit::Default,
+            )
+            .await
+            .unwrap();
+
+        // Finalize, add witnesses.
+        bitbox_api::btc::finalize_psbt(&mut psbt).unwrap();
+
+        // Verify the signed tx, including that all sigs/witnesses are correct.
+        verify_transaction(psbt);
+    })
+    .await
+}
+
+// Test finalizing and extracting with `finalize_psbt()` and `extract_tx()` instead of miniscript;
+// mixed input types (p2wpkh, p2wpkh-p2sh, p2tr).
+#[tokio::test]
+async fn test_btc_psbt_finalize_extract() {
+    test_initialized_simulators(async |bitbox| {
+        let secp = secp256k1::Secp256k1::new();
+
+        let fingerprint = util::simulator_xprv().fingerprint(&secp);
+
+        let input0_path: DerivationPath = "m/86'/1'/0'/0/0".parse().unwrap();
+        let input0_xpub = util::simulator_xpub_at(&secp, &input0_path);
+
+        let input1_path: DerivationPath = "m/84'/1'/0'/0/0".parse().unwrap();
+        let input1_xpub = util::simulator_xpub_at(&secp, &input1_path);
+
+        let input2_path: DerivationPath = "m/49'/1'/0'/0/0".parse().unwrap();
+        let input2_xpub = util::simulator_xpub_at(&secp, &input2_path);
+
+        let input2_redeemscript = ScriptBuf::new_p2wpkh(&input2_xpub.to_pub().wpubkey_hash());
+
+        let prev_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output:
+                    "3131313131313131313131313131313131313131313131313131313131313131:0"
+                        .parse()
+                        .unwrap(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0xFFFFFFFF),
+                witness: Witness::default(),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(100_000_000),
+                    script_pubkey: ScriptBuf::new_p2tr(&secp, input0_xpub.to_x_only_pub(), None),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000_000),
+                    script_pubkey: ScriptBuf::new_p2wpkh(&input1_xpub.to_pub().wpubkey_hash()),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000_000),
+                    script_pubkey: ScriptBuf::new_p2sh(&input2_redeemscript.clone().into()),
+                },
+            ],
+        };
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: (0..3)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint {
+                        txid: prev_tx.compute_txid(),
+                        vout,
+                    },
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence(0xFFFFFFFF),
+                    witness: Witness::default(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(299_990_000),
+                script_pubkey: ScriptBuf::new_p2tr(
+                    &secp,
+                    // random private key:
+                    // 9dbb534622a6100a39b73dece43c6d4db14b9a612eb46a6c64c2bb849e283ce8
+                    "e4adbb12c3426ec71ebb10688d8ae69d531ca822a2b790acee216a7f1b95b576"
+                        .parse()
+                        .unwrap(),
+                    None,
+                ),
+            }],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+
+        psbt.inputs[0].non_witness_utxo = Some(prev_tx.clone());
+        psbt.inputs[0].tap_internal_key = Some(input0_xpub.to_x_only_pub());
+        psbt.inputs[0].tap_key_origins.insert(
+            input0_xpub.to_x_only_pub(),
+            (vec![], (fingerprint, input0_path.clone())),
+        );
+
+        psbt.inputs[1].non_witness_utxo = Some(prev_tx.clone());
+        psbt.inputs[1]
+            .bip32_derivation
+            .insert(input1_xpub.to_pub().0, (fingerprint, input1_path.clone()));
+
+        psbt.inputs[2].non_witness_utxo = Some(prev_tx.clone());
+        psbt.inputs[2].redeem_script = Some(input2_redeemscript.clone());
+        psbt.inputs[2]
+            .bip32_derivation
+            .insert(input2_xpub.to_pub().0, (fingerprint, input2_path.clone()));
+
+        // Sign.
+        bitbox
+            .btc_sign_psbt(
+                pb::BtcCoin::Tbtc,
+                &mut psbt,
+                None,
+                pb::btc_sign_init_request::FormatUnit::Default,
+            )
+            .await
+            .unwrap();
+
+        // Finalizing and extracting results in the same transaction as with miniscript.
+        let mut psbt_miniscript = psbt.clone();
+        psbt_miniscript.finalize_mut(&secp).unwrap();
+
+        bitbox_api::btc::finalize_psbt(&mut psbt).unwrap();
+        let final_tx = bitbox_api::btc::extract_tx(psbt.clone()).unwrap();
+        assert_eq!(final_tx, psbt_miniscript.extract_tx_unchecked_fee_rate());
+        verify_transaction(psbt.clone());
+
+        // Extracting fails before finalizing.
+        let mut unfinalized = psbt.clone();
+        unfinalized.inputs[1].final_script_witness = None;
+        assert!(bitbox_api::btc::extract_tx(unfinalized).is_err());
+    })
+    .await
+}
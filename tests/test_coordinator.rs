@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: Apache-2.0
+
+#![cfg(feature = "simulator")]
+// Simulators only run on linux/amd64.
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+#[cfg(not(feature = "tokio"))]
+compile_error!("Enable the tokio feature to run simulator tests");
+
+mod util;
+
+use util::test_multiple_initialized_simulators;
+
+use bitbox_api::btc::KeyOriginInfo;
+use bitbox_api::coordinator;
+use bitbox_api::pb;
+use bitbox_api::wallet_export::{Wallet, WalletScript};
+
+use bitcoin::psbt::Psbt;
+use bitcoin::{
+    transaction, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use miniscript::psbt::PsbtExt;
+
+#[tokio::test]
+async fn test_setup_multisig() {
+    test_multiple_initialized_simulators(2, async |bitboxes| {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let coin = pb::BtcCoin::Tbtc;
+        let account_keypath: bitbox_api::Keypath = "m/48'/1'/0'/2'".try_into().unwrap();
+
+        // External cosigner, e.g. a key held in a different wallet.
+        let cosigner_xprv =
+            bitcoin::bip32::Xpriv::new_master(bitcoin::NetworkKind::Test, &[42u8; 32]).unwrap();
+        let cosigner = KeyOriginInfo {
+            root_fingerprint: Some(cosigner_xprv.fingerprint(&secp)),
+            keypath: Some(account_keypath.clone()),
+            xpub: bitcoin::bip32::Xpub::from_priv(
+                &secp,
+                &cosigner_xprv
+                    .derive_priv(
+                        &secp,
+                        &"m/48'/1'/0'/2'"
+                            .parse::<bitcoin::bip32::DerivationPath>()
+                            .unwrap(),
+                    )
+                    .unwrap(),
+            ),
+        };
+
+        let wallet = coordinator::setup_multisig(
+            bitboxes,
+            &[cosigner],
+            &coordinator::MultisigSetup {
+                coin,
+                threshold: 2,
+                script_type: pb::btc_script_config::multisig::ScriptType::P2wsh,
+                account_keypath: account_keypath.clone(),
+                xpub_type: pb::btc_register_script_config_request::XPubType::AutoXpubTpub,
+                name: "coordinated 2of3".into(),
+            },
+        )
+        .await
+        .unwrap();
+
+        // Registering again is a no-op.
+        assert_eq!(
+            coordinator::register_wallet(bitboxes, &wallet)
+                .await
+                .unwrap(),
+            vec![false, false]
+        );
+
+        // All devices agree on the addresses of the wallet.
+        let first_address = wallet.first_address().unwrap();
+        let mut receive_keypath = account_keypath.to_vec();
+        receive_keypath.extend([0, 0]);
+        for bitbox in bitboxes {
+            let fingerprint = bitbox.root_fingerprint().await.unwrap().parse().unwrap();
+            let (script_config, keypath_account) = wallet.script_config(&fingerprint).unwrap();
+            assert_eq!(keypath_account.as_ref(), Some(&account_keypath));
+            assert!(bitbox
+                .btc_is_script_config_registered(coin, &script_config, keypath_account.as_ref())
+                .await
+                .unwrap());
+            assert_eq!(
+                bitbox
+                    .btc_address(
+                        coin,
+                        &receive_keypath.as_slice().into(),
+                        &script_config,
+                        false,
+                    )
+                    .await
+                    .unwrap(),
+                first_address,
+            );
+        }
+
+        // The descriptor round-trips through the export formats.
+        let imported = Wallet::from_bsms(coin, &wallet.to_bsms().unwrap()).unwrap();
+        assert_eq!(imported.descriptor(), wallet.descriptor());
+        assert_eq!(
+            Wallet::from_multisig_text(coin, &wallet.to_multisig_text().unwrap()).unwrap(),
+            wallet,
+        );
+
+        // Both devices sign a PSBT spending from the wallet without a forced script config. The
+        // script config inferred from the PSBT must be the registered one.
+        let descriptor: miniscript::Descriptor<miniscript::DescriptorPublicKey> =
+            wallet.descriptor().parse().unwrap();
+        let [descriptor_receive, descriptor_change] = descriptor
+            .into_single_descriptors()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let input_descriptor = descriptor_receive.at_derivation_index(0).unwrap();
+        let change_descriptor = descriptor_change.at_derivation_index(0).unwrap();
+        assert_eq!(
+            input_descriptor
+                .address(bitcoin::Network::Testnet)
+                .unwrap()
+                .to_string(),
+            first_address
+        );
+
+        let prev_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output:
+                    "3131313131313131313131313131313131313131313131313131313131313131:0"
+                        .parse()
+                        .unwrap(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0xFFFFFFFF),
+                witness: Witness::default(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000_000),
+                script_pubkey: input_descriptor.script_pubkey(),
+            }],
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: prev_tx.compute_txid(),
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0xFFFFFFFF),
+                witness: Witness::default(),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(70_000_000),
+                    script_pubkey: change_descriptor.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(20_000_000),
+                    script_pubkey: ScriptBuf::new_p2tr(
+                        &secp,
+                        // random private key:
+                        // 9dbb534622a6100a39b73dece43c6d4db14b9a612eb46a6c64c2bb849e283ce8
+                        "e4adbb12c3426ec71ebb10688d8ae69d531ca822a2b790acee216a7f1b95b576"
+                            .parse()
+                            .unwrap(),
+                        None,
+                    ),
+                },
+            ],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].non_witness_utxo = Some(prev_tx);
+        psbt.update_input_with_descriptor(0, &input_descriptor)
+            .unwrap();
+        psbt.update_output_with_descriptor(0, &change_descriptor)
+            .unwrap();
+        let WalletScript::Multisig { keys, .. } = &wallet.script else {
+            panic!("not a multisig wallet");
+        };
+        for key in keys {
+            psbt.xpub.insert(
+                key.xpub,
+                (
+                    key.root_fingerprint.unwrap(),
+                    "m/48'/1'/0'/2'".parse().unwrap(),
+                ),
+            );
+        }
+        for bitbox in bitboxes {
+            bitbox
+                .btc_sign_psbt(
+                    coin,
+                    &mut psbt,
+                    None,
+                    pb::btc_sign_init_request::FormatUnit::Default,
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(psbt.inputs[0].partial_sigs.len(), 2);
+        psbt.finalize_mut(&secp).unwrap();
+    })
+    .await
+}
@@ -62,3 +62,104 @@ async fn test_change_password() {
     })
     .await
 }
+
+#[tokio::test]
+async fn test_backup() {
+    test_initialized_simulators(async |bitbox| {
+        assert!(bitbox.check_sdcard().await.unwrap());
+        bitbox.insert_sdcard().await.unwrap();
+
+        bitbox.create_backup().await.unwrap();
+        let backup_id = bitbox.check_backup(true).await.unwrap();
+        let backups = bitbox.list_backups().await.unwrap();
+        assert!(backups.iter().any(|backup| backup.id == backup_id));
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_setup() {
+    test_simulators_after_pairing(async |bitbox| {
+        assert!(!bitbox.device_info().await.unwrap().initialized);
+
+        assert!(bitbox
+            .setup("Test BitBox", bitbox_api::SeedLength::Words24)
+            .await
+            .unwrap());
+
+        let device_info = bitbox.device_info().await.unwrap();
+        assert!(device_info.initialized);
+        assert_eq!(device_info.name, "Test BitBox");
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_set_mnemonic_passphrase_enabled() {
+    test_initialized_simulators(async |bitbox| {
+        bitbox.set_mnemonic_passphrase_enabled(true).await.unwrap();
+        assert!(
+            bitbox
+                .device_info()
+                .await
+                .unwrap()
+                .mnemonic_passphrase_enabled
+        );
+        bitbox.set_mnemonic_passphrase_enabled(false).await.unwrap();
+        assert!(
+            !bitbox
+                .device_info()
+                .await
+                .unwrap()
+                .mnemonic_passphrase_enabled
+        );
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_reboot() {
+    test_initialized_simulators(async |bitbox| {
+        bitbox
+            .reboot(bitbox_api::pb::reboot_request::Purpose::Upgrade)
+            .await
+            .unwrap();
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_reset() {
+    test_initialized_simulators(async |bitbox| {
+        bitbox.reset().await.unwrap();
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_bip85_app_ln() {
+    test_initialized_simulators(async |bitbox| {
+        if !semver::VersionReq::parse(">=9.18.0")
+            .unwrap()
+            .matches(bitbox.version())
+        {
+            return;
+        }
+        let entropy = bitbox.bip85_app_ln(0).await.unwrap();
+        assert_eq!(bitbox.bip85_app_ln(0).await.unwrap(), entropy);
+        assert_ne!(bitbox.bip85_app_ln(1).await.unwrap(), entropy);
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_electrum_encryption_key() {
+    test_initialized_simulators(async |bitbox| {
+        let key = bitbox
+            .electrum_encryption_key(&"m/4541509'/1112098098'".try_into().unwrap())
+            .await
+            .unwrap();
+        assert!(key.starts_with("xpub"));
+    })
+    .await
+}
@@ -149,6 +149,40 @@ async fn test_eth_address() {
     .await
 }
 
+#[tokio::test]
+async fn test_eth_sign_message() {
+    test_initialized_simulators(async |paired_bitbox| {
+        let signature = paired_bitbox
+            .eth_sign_message(1, &"m/44'/60'/0'/0/0".try_into().unwrap(), b"message")
+            .await
+            .unwrap();
+        bitbox_api::eth::verify_message_signature(
+            b"message",
+            &signature,
+            "0x416E88840Eb6353E49252Da2a2c140eA1f969D1a",
+        )
+        .unwrap();
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_eth_sign_message_verified() {
+    test_initialized_simulators(async |paired_bitbox| {
+        let signature = paired_bitbox
+            .eth_sign_message_verified(1, &"m/44'/60'/0'/0/0".try_into().unwrap(), b"message")
+            .await
+            .unwrap();
+        bitbox_api::eth::verify_message_signature(
+            b"message",
+            &signature,
+            "0x416E88840Eb6353E49252Da2a2c140eA1f969D1a",
+        )
+        .unwrap();
+    })
+    .await
+}
+
 #[tokio::test]
 async fn test_eth_sign_transaction_nonstreaming() {
     test_initialized_simulators(async |paired_bitbox| {
@@ -40,12 +40,17 @@ struct Server(Child);
 
 impl Server {
     fn launch(filename: &str) -> Self {
+        Self::launch_with_args(filename, &[])
+    }
+
+    fn launch_with_args(filename: &str, args: &[&str]) -> Self {
         //let mut command = Command::new(filename);
 
         let mut command = Command::new("stdbuf");
         command
             .arg("-oL") // Line buffering for stdout
             .arg(filename)
+            .args(args)
             .stdout(std::process::Stdio::piped());
 
         command.stdout(std::process::Stdio::piped()); // Capture stdout
@@ -159,14 +164,17 @@ async fn download_simulators() -> Result<Vec<String>, ()> {
     Ok(filenames)
 }
 
+fn simulator_filenames_env() -> Option<Vec<String>> {
+    option_env!("SIMULATOR").map(|simulator_filename| vec![simulator_filename.into()])
+}
+
 /// Tests on an initialized device, which is not yet seeded.
 pub async fn test_simulators_after_pairing(
     run: impl AsyncFn(&bitbox_api::PairedBitBox<bitbox_api::runtime::TokioRuntime>),
 ) {
-    let simulator_filenames = if let Some(simulator_filename) = option_env!("SIMULATOR") {
-        vec![simulator_filename.into()]
-    } else {
-        download_simulators().await.unwrap()
+    let simulator_filenames = match simulator_filenames_env() {
+        Some(simulator_filenames) => simulator_filenames,
+        None => download_simulators().await.unwrap(),
     };
     for simulator_filename in simulator_filenames {
         println!();
@@ -197,3 +205,65 @@ pub async fn test_initialized_simulators(
     })
     .await
 }
+
+/// Tests on `count` simulator instances running at the same time, each listening on its own port.
+/// The first one is initialized with the mnemonic of `test_initialized_simulators()`, the others
+/// with a random seed each, so all devices have different keys.
+///
+/// Not all simulator versions support the `--port` argument. Versions that do not listen on the
+/// requested port are skipped. The ports start above the default port, so a simulator that ignores
+/// the argument is not mistaken for one that supports it. At least one simulator version must
+/// support it, so that the test does not pass without running.
+pub async fn test_multiple_initialized_simulators(
+    count: u16,
+    run: impl AsyncFn(&[&bitbox_api::PairedBitBox<bitbox_api::runtime::TokioRuntime>]),
+) {
+    let simulator_filenames = match simulator_filenames_env() {
+        Some(simulator_filenames) => simulator_filenames,
+        None => download_simulators().await.unwrap(),
+    };
+    let mut tested = false;
+    'simulators: for simulator_filename in simulator_filenames {
+        println!();
+        println!("\tSimulator tests using {count} instances of {simulator_filename}");
+        let mut servers = Vec::new();
+        let mut paired_bitboxes = Vec::new();
+        for i in 0..count {
+            let port = (15424 + i).to_string();
+            servers.push(Server::launch_with_args(
+                &simulator_filename,
+                &["--port", &port],
+            ));
+            let noise_config = Box::new(bitbox_api::NoiseConfigNoCache {});
+            let bitbox =
+                match bitbox_api::BitBox::<bitbox_api::runtime::TokioRuntime>::from_simulator(
+                    Some(&format!("127.0.0.1:{port}")),
+                    noise_config,
+                )
+                .await
+                {
+                    Ok(bitbox) => bitbox,
+                    Err(_) if i == 0 => {
+                        println!("\tSkipping {simulator_filename}: it does not support --port");
+                        continue 'simulators;
+                    }
+                    Err(err) => panic!("could not connect to simulator on port {port}: {err:?}"),
+                };
+            let pairing_bitbox = bitbox.unlock_and_pair().await.unwrap();
+            let paired_bitbox = pairing_bitbox.wait_confirm().await.unwrap();
+            if i == 0 {
+                assert!(paired_bitbox.restore_from_mnemonic().await.is_ok());
+            } else {
+                assert!(paired_bitbox
+                    .setup("cosigner", bitbox_api::SeedLength::Words24)
+                    .await
+                    .unwrap());
+            }
+            paired_bitboxes.push(paired_bitbox);
+        }
+        let paired_bitboxes: Vec<_> = paired_bitboxes.iter().collect();
+        run(&paired_bitboxes).await;
+        tested = true;
+    }
+    assert!(tested, "no simulator supports running multiple instances");
+}